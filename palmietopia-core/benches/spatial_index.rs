@@ -0,0 +1,148 @@
+//! Cost of the tile and unit lookups behind visibility and move validation on a crowded Huge
+//! map, comparing the indexes in `GameMap` and `GameSession` with the linear scans they replaced.
+//! Both sides of each comparison do the same work apart from the lookups; the full
+//! `get_visible_tiles`, which also traces line of sight, is timed on its own for reference.
+//!
+//! Run with `cargo bench -p palmietopia-core --bench spatial_index`.
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use palmietopia_core::{GameSession, MapSize, Unit, UnitType, CITY_VISION_RANGE, HEX_DIRECTIONS};
+use std::collections::HashSet;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const PLAYERS: usize = 5;
+const UNITS: usize = 200;
+const ITERATIONS: u32 = 200;
+
+fn crowded_huge_game() -> GameSession {
+    let mut game = common::game(&["p0", "p1", "p2", "p3", "p4"][..PLAYERS], MapSize::Huge, 42);
+
+    let land: Vec<(i32, i32)> = game.map.tiles.iter()
+        .filter(|t| GameSession::movement_cost(t.terrain).is_some())
+        .map(|t| (t.q, t.r))
+        .collect();
+    for (i, &(q, r)) in land.iter().step_by(2).enumerate() {
+        if game.units.len() >= UNITS {
+            break;
+        }
+        if game.units.iter().any(|u| u.q == q && u.r == r) {
+            continue;
+        }
+        let owner = game.players[i % PLAYERS].id.clone();
+        game.units.push(Unit::new(format!("bench-{}", i), owner, UnitType::Explorer, q, r));
+    }
+    game.reindex();
+    game
+}
+
+fn tiles_in_range(q: i32, r: i32, range: i32) -> Vec<(i32, i32)> {
+    let mut tiles = Vec::new();
+    for dq in -range..=range {
+        for dr in (-range).max(-dq - range)..=range.min(-dq + range) {
+            tiles.push((q + dq, r + dr));
+        }
+    }
+    tiles
+}
+
+/// Tiles within vision range of a player's cities and units (without line of sight), with
+/// `on_map` deciding which of them exist
+fn tiles_in_vision(game: &GameSession, player_id: &str, on_map: impl Fn(i32, i32) -> bool) -> HashSet<(i32, i32)> {
+    let mut tiles = HashSet::new();
+    let sources = game.cities.iter()
+        .filter(|c| c.owner_id == player_id)
+        .map(|c| (c.q, c.r, CITY_VISION_RANGE))
+        .chain(game.units.iter()
+            .filter(|u| u.owner_id == player_id)
+            .map(|u| (u.q, u.r, u.unit_type.vision_range())));
+    for (q, r, range) in sources {
+        for (tq, tr) in tiles_in_range(q, r, range) {
+            if on_map(tq, tr) {
+                tiles.insert((tq, tr));
+            }
+        }
+    }
+    tiles
+}
+
+/// Move validation as it was before the index: terrain and occupancy by scanning
+fn linear_can_move(game: &GameSession, unit: &Unit, to_q: i32, to_r: i32) -> bool {
+    let Some(terrain) = game.map.tiles.iter().find(|t| t.q == to_q && t.r == to_r).map(|t| t.terrain) else {
+        return false;
+    };
+    let Some(cost) = GameSession::movement_cost(terrain) else {
+        return false;
+    };
+    unit.movement_remaining >= cost && !game.units.iter().any(|u| u.q == to_q && u.r == to_r)
+}
+
+/// The same checks through the tile and unit indexes
+fn indexed_can_move(game: &GameSession, unit: &Unit, to_q: i32, to_r: i32) -> bool {
+    let Some(terrain) = game.map.terrain_at(to_q, to_r) else {
+        return false;
+    };
+    let Some(cost) = GameSession::movement_cost(terrain) else {
+        return false;
+    };
+    unit.movement_remaining >= cost && !game.is_occupied(to_q, to_r)
+}
+
+fn time(iterations: u32, mut f: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    for _ in 0..iterations {
+        f();
+    }
+    start.elapsed() / iterations
+}
+
+fn report(name: &str, linear: Duration, indexed: Duration) {
+    let speedup = linear.as_secs_f64() / indexed.as_secs_f64().max(f64::EPSILON);
+    println!("{:<32} {:>12?} {:>12?} {:>8.1}x", name, linear, indexed, speedup);
+}
+
+fn main() {
+    let game = crowded_huge_game();
+    println!(
+        "Huge map: {} tiles, {} units, {} players, {} iterations",
+        game.map.tiles.len(), game.units.len(), PLAYERS, ITERATIONS
+    );
+    println!("{:<32} {:>12} {:>12} {:>9}", "", "linear scan", "indexed", "speedup");
+
+    let linear = time(ITERATIONS, || {
+        for p in &game.players {
+            black_box(tiles_in_vision(&game, &p.id, |q, r| game.map.tiles.iter().any(|t| t.q == q && t.r == r)));
+        }
+    });
+    let indexed = time(ITERATIONS, || {
+        for p in &game.players {
+            black_box(tiles_in_vision(&game, &p.id, |q, r| game.map.contains(q, r)));
+        }
+    });
+    report("tiles in vision (all players)", linear, indexed);
+
+    let linear = time(ITERATIONS, || {
+        for unit in &game.units {
+            for (dq, dr) in HEX_DIRECTIONS {
+                black_box(linear_can_move(&game, unit, unit.q + dq, unit.r + dr));
+            }
+        }
+    });
+    let indexed = time(ITERATIONS, || {
+        for unit in &game.units {
+            for (dq, dr) in HEX_DIRECTIONS {
+                black_box(indexed_can_move(&game, unit, unit.q + dq, unit.r + dr));
+            }
+        }
+    });
+    report("move checks (all neighbours)", linear, indexed);
+
+    let visibility = time(ITERATIONS, || {
+        for p in &game.players {
+            black_box(game.get_visible_tiles(&p.id));
+        }
+    });
+    println!("{:<32} {:>25?}", "visibility with line of sight", visibility);
+}
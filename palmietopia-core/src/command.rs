@@ -0,0 +1,225 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{GameError, GameSession, GameStatus, Unit, UnitType};
+
+/// A player action. Every change to a running game goes through `GameSession::apply`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type")]
+pub enum GameCommand {
+    MoveUnit { player_id: String, unit_id: String, to_q: i32, to_r: i32 },
+    MoveUnitPath { player_id: String, unit_id: String, path: Vec<(i32, i32)> },
+    AttackUnit { player_id: String, attacker_id: String, defender_id: String },
+    FortifyUnit { player_id: String, unit_id: String },
+    BuyUnit { player_id: String, city_id: String, unit_type: UnitType },
+    /// `time_used_ms` is charged to the player's clock before the increment is added
+    EndTurn { player_id: String, time_used_ms: u64 },
+    /// Leave the game for good
+    Resign { player_id: String },
+    OfferDraw { player_id: String },
+    AcceptDraw { player_id: String },
+    /// Stop the game without a result (host only)
+    Abort { player_id: String },
+}
+
+impl GameCommand {
+    pub fn player_id(&self) -> &str {
+        match self {
+            GameCommand::MoveUnit { player_id, .. }
+            | GameCommand::MoveUnitPath { player_id, .. }
+            | GameCommand::AttackUnit { player_id, .. }
+            | GameCommand::FortifyUnit { player_id, .. }
+            | GameCommand::BuyUnit { player_id, .. }
+            | GameCommand::EndTurn { player_id, .. }
+            | GameCommand::Resign { player_id }
+            | GameCommand::OfferDraw { player_id }
+            | GameCommand::AcceptDraw { player_id }
+            | GameCommand::Abort { player_id } => player_id,
+        }
+    }
+
+    /// Whether only the player whose turn it is may do this. Resigning, draws and aborting are
+    /// allowed at any time.
+    pub fn needs_turn(&self) -> bool {
+        !matches!(
+            self,
+            GameCommand::Resign { .. }
+                | GameCommand::OfferDraw { .. }
+                | GameCommand::AcceptDraw { .. }
+                | GameCommand::Abort { .. }
+        )
+    }
+}
+
+/// Something that happened as the result of a command, in the order it happened
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type")]
+pub enum GameEvent {
+    UnitMoved { unit_id: String, to_q: i32, to_r: i32, movement_remaining: u32 },
+    UnitMovedAlongPath { unit_id: String, path: Vec<(i32, i32)>, movement_remaining: u32 },
+    CombatResolved {
+        attacker_id: String,
+        defender_id: String,
+        attacker_hp: u32,
+        defender_hp: u32,
+        damage_to_attacker: u32,
+        damage_to_defender: u32,
+        attacker_died: bool,
+        defender_died: bool,
+        attacker_new_q: Option<i32>,
+        attacker_new_r: Option<i32>,
+    },
+    UnitFortified { unit_id: String, new_hp: u32 },
+    UnitPurchased { unit: Unit, city_id: String, player_gold: u64 },
+    PlayerEliminated { player_id: String, conquerer_id: String },
+    /// The player's units are gone and their cities are left for the taking
+    PlayerResigned { player_id: String },
+    DrawOffered { player_id: String },
+    DrawAccepted { player_id: String },
+    GameDrawn,
+    /// Stopped by the host, or with no `player_id` when every player still in it had left
+    GameAborted { player_id: Option<String> },
+    /// Cities that changed owner, including those handed over by an eliminated player
+    CitiesCaptured { city_ids: Vec<String> },
+    TurnEnded { current_turn: usize },
+    GameWon { winner_id: String },
+}
+
+impl GameSession {
+    /// Validate and execute a command for the player whose turn it is. On success the session
+    /// has been updated and the returned events describe every change; on error nothing changed.
+    pub fn apply(&mut self, command: GameCommand) -> Result<Vec<GameEvent>, GameError> {
+        if self.status != GameStatus::InProgress {
+            return Err(GameError::GameFinished);
+        }
+        let player_id = command.player_id().to_string();
+        if !self.players.iter().any(|p| p.id == player_id) {
+            return Err(GameError::PlayerNotFound);
+        }
+        if self.eliminated_players.contains(&player_id) {
+            return Err(GameError::PlayerEliminated);
+        }
+        if command.needs_turn() && self.players[self.current_turn].id != player_id {
+            return Err(GameError::NotYourTurn);
+        }
+
+        let owners_before: Vec<String> = self.cities.iter().map(|c| c.owner_id.clone()).collect();
+        let mut events = Vec::new();
+        let mut eliminated = Vec::new();
+        match command {
+            GameCommand::MoveUnit { unit_id, to_q, to_r, .. } => {
+                self.check_unit_owner(&unit_id, &player_id)?;
+                let outcome = self.move_unit(&unit_id, to_q, to_r)?;
+                events.push(GameEvent::UnitMoved {
+                    unit_id,
+                    to_q,
+                    to_r,
+                    movement_remaining: outcome.movement_remaining,
+                });
+                eliminated.extend(outcome.eliminated_player);
+            }
+            GameCommand::MoveUnitPath { unit_id, path, .. } => {
+                self.check_unit_owner(&unit_id, &player_id)?;
+                let outcome = self.move_unit_along_path(&unit_id, &path)?;
+                events.push(GameEvent::UnitMovedAlongPath {
+                    unit_id,
+                    path: outcome.path,
+                    movement_remaining: outcome.movement_remaining,
+                });
+                eliminated.extend(outcome.eliminated_players);
+            }
+            GameCommand::AttackUnit { attacker_id, defender_id, .. } => {
+                self.check_unit_owner(&attacker_id, &player_id)?;
+                let outcome = self.resolve_combat(&attacker_id, &defender_id)?;
+                events.push(GameEvent::CombatResolved {
+                    attacker_id,
+                    defender_id,
+                    attacker_hp: outcome.attacker_hp,
+                    defender_hp: outcome.defender_hp,
+                    damage_to_attacker: outcome.damage_to_attacker,
+                    damage_to_defender: outcome.damage_to_defender,
+                    attacker_died: outcome.attacker_died,
+                    defender_died: outcome.defender_died,
+                    attacker_new_q: outcome.attacker_new_q,
+                    attacker_new_r: outcome.attacker_new_r,
+                });
+                eliminated.extend(outcome.eliminated_player);
+            }
+            GameCommand::FortifyUnit { unit_id, .. } => {
+                self.check_unit_owner(&unit_id, &player_id)?;
+                let new_hp = self.fortify_unit(&unit_id)?;
+                events.push(GameEvent::UnitFortified { unit_id, new_hp });
+            }
+            GameCommand::BuyUnit { city_id, unit_type, .. } => {
+                let unit = self.buy_unit(&player_id, &city_id, unit_type)?;
+                let player_gold = self.player_gold[self.current_turn];
+                events.push(GameEvent::UnitPurchased { unit, city_id, player_gold });
+            }
+            GameCommand::EndTurn { time_used_ms, .. } => {
+                self.end_current_turn(time_used_ms);
+                events.push(GameEvent::TurnEnded { current_turn: self.current_turn });
+            }
+            GameCommand::Resign { .. } => {
+                let drawn = self.resign(&player_id);
+                events.push(GameEvent::PlayerResigned { player_id: player_id.clone() });
+                if drawn {
+                    events.push(GameEvent::GameDrawn);
+                }
+                // Play moves on if it was their turn and someone is left to take it. They are out,
+                // so they get neither income nor the clock increment for it.
+                if self.status == GameStatus::InProgress && self.players[self.current_turn].id == player_id {
+                    self.pass_turn();
+                    events.push(GameEvent::TurnEnded { current_turn: self.current_turn });
+                }
+            }
+            GameCommand::OfferDraw { .. } => {
+                self.offer_draw(&player_id)?;
+                events.push(GameEvent::DrawOffered { player_id: player_id.clone() });
+            }
+            GameCommand::AcceptDraw { .. } => {
+                let drawn = self.accept_draw(&player_id)?;
+                events.push(GameEvent::DrawAccepted { player_id: player_id.clone() });
+                if drawn {
+                    events.push(GameEvent::GameDrawn);
+                }
+            }
+            GameCommand::Abort { .. } => {
+                self.abort(&player_id)?;
+                events.push(GameEvent::GameAborted { player_id: Some(player_id.clone()) });
+            }
+        }
+
+        // Everyone's memory of what they can see may have changed, not just the acting player's
+        let player_ids: Vec<String> = self.players.iter().map(|p| p.id.clone()).collect();
+        for id in player_ids {
+            self.update_exploration(&id);
+        }
+
+        for eliminated_id in eliminated {
+            events.push(GameEvent::PlayerEliminated {
+                player_id: eliminated_id,
+                conquerer_id: player_id.clone(),
+            });
+        }
+        let city_ids: Vec<String> = self.cities.iter()
+            .zip(&owners_before)
+            .filter(|(city, before)| city.owner_id != **before)
+            .map(|(city, _)| city.id.clone())
+            .collect();
+        if !city_ids.is_empty() {
+            events.push(GameEvent::CitiesCaptured { city_ids });
+        }
+        if let GameStatus::Victory { winner_id } = &self.status {
+            events.push(GameEvent::GameWon { winner_id: winner_id.clone() });
+        }
+        Ok(events)
+    }
+
+    fn check_unit_owner(&self, unit_id: &str, player_id: &str) -> Result<(), GameError> {
+        let unit = self.unit(unit_id)
+            .ok_or_else(|| GameError::UnitNotFound { unit_id: unit_id.to_string() })?;
+        if unit.owner_id != player_id {
+            return Err(GameError::NotYourUnit);
+        }
+        Ok(())
+    }
+}
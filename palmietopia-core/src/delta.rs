@@ -0,0 +1,172 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use crate::{City, GameStatus, PlayerView, SeenCity, SeenUnit, Unit};
+
+/// What changed between two views of the same game for the same player. Lists of units and
+/// cities carry only entries that were added or changed (replace by id) and the ids of entries
+/// that went away; tile sets carry only tiles added or removed; everything else is set only when
+/// it changed. The map, players and clock settings never change during a game and are left out.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct ViewDelta {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub units_changed: Vec<Unit>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub units_removed: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cities_changed: Vec<City>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cities_removed: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub last_seen_cities_changed: Vec<SeenCity>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub last_seen_cities_removed: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub last_seen_units_changed: Vec<SeenUnit>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub last_seen_units_removed: Vec<String>,
+    /// Tiles explored for the first time (explored tiles are never forgotten)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub explored_added: Vec<(i32, i32)>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub visible_added: Vec<(i32, i32)>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub visible_removed: Vec<(i32, i32)>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gold: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub current_turn: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub turn_number: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<GameStatus>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub eliminated_players: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub draw_offered_by: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub player_times_ms: Option<Vec<u64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub turn_started_at_ms: Option<u64>,
+}
+
+impl ViewDelta {
+    pub fn is_empty(&self) -> bool {
+        *self == ViewDelta::default()
+    }
+}
+
+/// Entries of `new` that are missing from or different in `old`, and ids of `old` entries
+/// missing from `new`
+fn diff_by_id<T: Clone + PartialEq>(old: &[T], new: &[T], id: impl Fn(&T) -> &str) -> (Vec<T>, Vec<String>) {
+    let changed = new
+        .iter()
+        .filter(|n| !old.iter().any(|o| id(o) == id(n) && o == *n))
+        .cloned()
+        .collect();
+    let removed = old
+        .iter()
+        .filter(|o| !new.iter().any(|n| id(n) == id(o)))
+        .map(|o| id(o).to_string())
+        .collect();
+    (changed, removed)
+}
+
+/// Replace entries by id, append new ones and drop removed ones
+fn patch_by_id<T: Clone>(list: &mut Vec<T>, changed: &[T], removed: &[String], id: impl Fn(&T) -> &str) {
+    list.retain(|item| !removed.iter().any(|r| r == id(item)));
+    for entry in changed {
+        match list.iter_mut().find(|item| id(item) == id(entry)) {
+            Some(item) => *item = entry.clone(),
+            None => list.push(entry.clone()),
+        }
+    }
+}
+
+fn added(old: &HashSet<(i32, i32)>, new: &HashSet<(i32, i32)>) -> Vec<(i32, i32)> {
+    new.difference(old).copied().collect()
+}
+
+fn changed<T: Clone + PartialEq>(old: &T, new: &T) -> Option<T> {
+    (old != new).then(|| new.clone())
+}
+
+impl PlayerView {
+    /// The delta that turns this view into `newer`
+    pub fn diff(&self, newer: &PlayerView) -> ViewDelta {
+        let (units_changed, units_removed) = diff_by_id(&self.units, &newer.units, |u| &u.id);
+        let (cities_changed, cities_removed) = diff_by_id(&self.cities, &newer.cities, |c| &c.id);
+        let (last_seen_cities_changed, last_seen_cities_removed) =
+            diff_by_id(&self.last_seen_cities, &newer.last_seen_cities, |s| &s.city.id);
+        let (last_seen_units_changed, last_seen_units_removed) =
+            diff_by_id(&self.last_seen_units, &newer.last_seen_units, |s| &s.unit.id);
+        ViewDelta {
+            units_changed,
+            units_removed,
+            cities_changed,
+            cities_removed,
+            last_seen_cities_changed,
+            last_seen_cities_removed,
+            last_seen_units_changed,
+            last_seen_units_removed,
+            explored_added: added(&self.explored_tiles, &newer.explored_tiles),
+            visible_added: added(&self.visible_tiles, &newer.visible_tiles),
+            visible_removed: added(&newer.visible_tiles, &self.visible_tiles),
+            gold: changed(&self.gold, &newer.gold),
+            current_turn: changed(&self.current_turn, &newer.current_turn),
+            turn_number: changed(&self.turn_number, &newer.turn_number),
+            status: changed(&self.status, &newer.status),
+            eliminated_players: changed(&self.eliminated_players, &newer.eliminated_players),
+            draw_offered_by: changed(&self.draw_offered_by, &newer.draw_offered_by),
+            player_times_ms: changed(&self.player_times_ms, &newer.player_times_ms),
+            turn_started_at_ms: changed(&self.turn_started_at_ms, &newer.turn_started_at_ms),
+        }
+    }
+
+    /// Bring this view up to date with a delta from `diff`
+    pub fn apply_delta(&mut self, delta: &ViewDelta) {
+        patch_by_id(&mut self.units, &delta.units_changed, &delta.units_removed, |u| &u.id);
+        patch_by_id(&mut self.cities, &delta.cities_changed, &delta.cities_removed, |c| &c.id);
+        patch_by_id(
+            &mut self.last_seen_cities,
+            &delta.last_seen_cities_changed,
+            &delta.last_seen_cities_removed,
+            |s| &s.city.id,
+        );
+        patch_by_id(
+            &mut self.last_seen_units,
+            &delta.last_seen_units_changed,
+            &delta.last_seen_units_removed,
+            |s| &s.unit.id,
+        );
+        self.explored_tiles.extend(&delta.explored_added);
+        for tile in &delta.visible_removed {
+            self.visible_tiles.remove(tile);
+        }
+        self.visible_tiles.extend(&delta.visible_added);
+        if let Some(gold) = delta.gold {
+            self.gold = gold;
+        }
+        if let Some(current_turn) = delta.current_turn {
+            self.current_turn = current_turn;
+        }
+        if let Some(turn_number) = delta.turn_number {
+            self.turn_number = turn_number;
+        }
+        if let Some(status) = &delta.status {
+            self.status = status.clone();
+        }
+        if let Some(eliminated) = &delta.eliminated_players {
+            self.eliminated_players = eliminated.clone();
+        }
+        if let Some(offered_by) = &delta.draw_offered_by {
+            self.draw_offered_by = offered_by.clone();
+        }
+        if let Some(times) = &delta.player_times_ms {
+            self.player_times_ms = times.clone();
+        }
+        if let Some(started) = delta.turn_started_at_ms {
+            self.turn_started_at_ms = started;
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Why a game action was rejected. `code()` is a stable machine-readable identifier sent to
+/// clients alongside the English `Display` message, so the UI can react to specific failures.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "code", rename_all = "snake_case")]
+pub enum GameError {
+    GameNotFound,
+    GameFinished,
+    PlayerNotFound,
+    PlayerEliminated,
+    UnitNotFound { unit_id: String },
+    CityNotFound { city_id: String },
+    NotYourTurn,
+    NotYourUnit,
+    NotYourCity,
+    InvalidDestination,
+    ImpassableTerrain,
+    NotAdjacent,
+    TileOccupied,
+    EmptyPath,
+    NotEnoughMovement { required: u32, remaining: u32 },
+    OutOfRange { range: i32, distance: i32 },
+    NoLineOfSight,
+    NoMovementToAttack,
+    CannotFortifyAfterMoving,
+    CityAlreadyProduced,
+    CityOccupied,
+    NotEnoughGold { cost: u64, balance: u64 },
+    InvalidUnitType { unit_type: String },
+    NoDrawOffer,
+    DrawAlreadyOffered,
+    NotHost,
+}
+
+impl GameError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            GameError::GameNotFound => "game_not_found",
+            GameError::GameFinished => "game_finished",
+            GameError::PlayerNotFound => "player_not_found",
+            GameError::PlayerEliminated => "player_eliminated",
+            GameError::UnitNotFound { .. } => "unit_not_found",
+            GameError::CityNotFound { .. } => "city_not_found",
+            GameError::NotYourTurn => "not_your_turn",
+            GameError::NotYourUnit => "not_your_unit",
+            GameError::NotYourCity => "not_your_city",
+            GameError::InvalidDestination => "invalid_destination",
+            GameError::ImpassableTerrain => "impassable_terrain",
+            GameError::NotAdjacent => "not_adjacent",
+            GameError::TileOccupied => "tile_occupied",
+            GameError::EmptyPath => "empty_path",
+            GameError::NotEnoughMovement { .. } => "not_enough_movement",
+            GameError::OutOfRange { .. } => "out_of_range",
+            GameError::NoLineOfSight => "no_line_of_sight",
+            GameError::NoMovementToAttack => "no_movement_to_attack",
+            GameError::CannotFortifyAfterMoving => "cannot_fortify_after_moving",
+            GameError::CityAlreadyProduced => "city_already_produced",
+            GameError::CityOccupied => "city_occupied",
+            GameError::NotEnoughGold { .. } => "not_enough_gold",
+            GameError::InvalidUnitType { .. } => "invalid_unit_type",
+            GameError::NoDrawOffer => "no_draw_offer",
+            GameError::DrawAlreadyOffered => "draw_already_offered",
+            GameError::NotHost => "not_host",
+        }
+    }
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameError::GameNotFound => write!(f, "Game not found"),
+            GameError::GameFinished => write!(f, "Game is already over"),
+            GameError::PlayerNotFound => write!(f, "Player not found"),
+            GameError::PlayerEliminated => write!(f, "Player is out of the game"),
+            GameError::UnitNotFound { unit_id } => write!(f, "Unit not found: {}", unit_id),
+            GameError::CityNotFound { city_id } => write!(f, "City not found: {}", city_id),
+            GameError::NotYourTurn => write!(f, "Not your turn"),
+            GameError::NotYourUnit => write!(f, "Not your unit"),
+            GameError::NotYourCity => write!(f, "Not your city"),
+            GameError::InvalidDestination => write!(f, "Invalid destination"),
+            GameError::ImpassableTerrain => write!(f, "Cannot move to water"),
+            GameError::NotAdjacent => write!(f, "Can only move to adjacent tiles"),
+            GameError::TileOccupied => write!(f, "Tile is occupied"),
+            GameError::EmptyPath => write!(f, "Path is empty"),
+            GameError::NotEnoughMovement { required, remaining } => {
+                write!(f, "Not enough movement remaining (need {}, have {})", required, remaining)
+            }
+            GameError::OutOfRange { range, distance } => {
+                write!(f, "Target out of range (range: {}, distance: {})", range, distance)
+            }
+            GameError::NoLineOfSight => write!(f, "No line of sight to target"),
+            GameError::NoMovementToAttack => write!(f, "No movement remaining to attack"),
+            GameError::CannotFortifyAfterMoving => write!(f, "Cannot fortify after moving"),
+            GameError::CityAlreadyProduced => write!(f, "City has already produced this turn"),
+            GameError::CityOccupied => write!(f, "City is occupied by a unit"),
+            GameError::NotEnoughGold { cost, balance } => {
+                write!(f, "Not enough gold (cost {}, have {})", cost, balance)
+            }
+            GameError::InvalidUnitType { unit_type } => write!(f, "Invalid unit type: {}", unit_type),
+            GameError::NoDrawOffer => write!(f, "No draw has been offered"),
+            GameError::DrawAlreadyOffered => write!(f, "A draw has already been offered"),
+            GameError::NotHost => write!(f, "Only the host can do that"),
+        }
+    }
+}
+
+impl std::error::Error for GameError {}
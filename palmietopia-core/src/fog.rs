@@ -0,0 +1,24 @@
+use crate::{City, GameSession, Unit};
+
+impl GameSession {
+    /// Units a player can see: their own plus enemy units standing on tiles they currently see
+    pub fn visible_units(&self, player_id: &str) -> Vec<Unit> {
+        let visible = self.get_visible_tiles(player_id);
+        self.units
+            .iter()
+            .filter(|u| u.owner_id == player_id || visible.contains(&(u.q, u.r)))
+            .cloned()
+            .collect()
+    }
+
+    /// Cities a player can see as they are now: their own plus any on tiles they currently see.
+    /// Enemy cities out of sight are only known as they were last seen (`last_seen_cities`).
+    pub fn known_cities(&self, player_id: &str) -> Vec<City> {
+        let visible = self.get_visible_tiles(player_id);
+        self.cities
+            .iter()
+            .filter(|c| c.owner_id == player_id || visible.contains(&(c.q, c.r)))
+            .cloned()
+            .collect()
+    }
+}
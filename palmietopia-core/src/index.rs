@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+
+use crate::{GameMap, GameSession, Terrain, Tile, Unit};
+
+/// Dense coordinate-to-tile lookup for a hexagonal map. Axial coordinates are shifted by the
+/// radius into a (2r+1)² grid, so a lookup is one multiplication and one array read.
+#[derive(Clone, Debug)]
+pub(crate) struct TileIndex {
+    radius: i32,
+    width: i32,
+    slots: Vec<Option<u32>>,
+}
+
+impl TileIndex {
+    pub(crate) fn build(tiles: &[Tile], radius: u32) -> Self {
+        let radius = radius as i32;
+        let width = radius * 2 + 1;
+        let mut index = Self {
+            radius,
+            width,
+            slots: vec![None; (width * width) as usize],
+        };
+        for (i, tile) in tiles.iter().enumerate() {
+            if let Some(slot) = index.slot(tile.q, tile.r) {
+                index.slots[slot] = Some(i as u32);
+            }
+        }
+        index
+    }
+
+    fn slot(&self, q: i32, r: i32) -> Option<usize> {
+        let (x, y) = (q + self.radius, r + self.radius);
+        if x < 0 || y < 0 || x >= self.width || y >= self.width {
+            return None;
+        }
+        Some((x * self.width + y) as usize)
+    }
+
+    pub(crate) fn get(&self, q: i32, r: i32) -> Option<usize> {
+        self.slot(q, r).and_then(|slot| self.slots[slot]).map(|i| i as usize)
+    }
+}
+
+/// Position-to-unit and id-to-unit lookups kept by `GameSession`. It is not serialized: a freshly
+/// deserialized session falls back to scanning `units` until the next core action (or `reindex`)
+/// rebuilds it.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct UnitIndex {
+    by_position: HashMap<(i32, i32), usize>,
+    by_id: HashMap<String, usize>,
+    valid: bool,
+}
+
+impl GameMap {
+    pub fn new(tiles: Vec<Tile>, radius: u32) -> Self {
+        let index = TileIndex::build(&tiles, radius);
+        Self {
+            tiles,
+            radius,
+            index: index.into(),
+        }
+    }
+
+    fn tile_index(&self) -> &TileIndex {
+        self.index.get_or_init(|| TileIndex::build(&self.tiles, self.radius))
+    }
+
+    pub fn tile(&self, q: i32, r: i32) -> Option<&Tile> {
+        self.tile_index().get(q, r).map(|i| &self.tiles[i])
+    }
+
+    pub fn contains(&self, q: i32, r: i32) -> bool {
+        self.tile_index().get(q, r).is_some()
+    }
+
+    pub fn terrain_at(&self, q: i32, r: i32) -> Option<Terrain> {
+        self.tile(q, r).map(|t| t.terrain)
+    }
+}
+
+impl GameSession {
+    /// Rebuild the unit lookups. Core actions keep it up to date themselves; call
+    /// this after editing `units` directly.
+    pub fn reindex(&mut self) {
+        self.unit_index.by_position = self.units.iter()
+            .enumerate()
+            .map(|(i, u)| ((u.q, u.r), i))
+            .collect();
+        self.unit_index.by_id = self.units.iter()
+            .enumerate()
+            .map(|(i, u)| (u.id.clone(), i))
+            .collect();
+        self.unit_index.valid = true;
+    }
+
+    pub fn unit(&self, unit_id: &str) -> Option<&Unit> {
+        if self.unit_index.valid {
+            self.unit_index.by_id.get(unit_id).map(|&i| &self.units[i])
+        } else {
+            self.units.iter().find(|u| u.id == unit_id)
+        }
+    }
+
+    pub fn unit_at(&self, q: i32, r: i32) -> Option<&Unit> {
+        if self.unit_index.valid {
+            self.unit_index.by_position.get(&(q, r)).map(|&i| &self.units[i])
+        } else {
+            self.units.iter().find(|u| u.q == q && u.r == r)
+        }
+    }
+
+    pub fn is_occupied(&self, q: i32, r: i32) -> bool {
+        self.unit_at(q, r).is_some()
+    }
+}
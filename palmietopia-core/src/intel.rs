@@ -0,0 +1,91 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+use crate::{City, GameSession, Unit};
+
+/// An enemy city as a player last saw it
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct SeenCity {
+    pub city: City,
+    pub seen_turn: u32,
+}
+
+/// An enemy unit as a player last saw it
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct SeenUnit {
+    pub unit: Unit,
+    pub seen_turn: u32,
+}
+
+/// What one player remembers about enemy cities and units, keyed by id. Entries are refreshed
+/// whenever the player sees them again and dropped once the player can see they are gone.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct Intel {
+    pub cities: BTreeMap<String, SeenCity>,
+    pub units: BTreeMap<String, SeenUnit>,
+}
+
+impl GameSession {
+    /// Update a player's memory from the tiles they can see right now
+    pub(crate) fn record_sightings(&mut self, player_idx: usize, visible: &HashSet<(i32, i32)>) {
+        let player_id = &self.players[player_idx].id;
+        let seen_turn = self.turn_number;
+        let cities: Vec<SeenCity> = self.cities
+            .iter()
+            .filter(|c| &c.owner_id != player_id && visible.contains(&(c.q, c.r)))
+            .map(|c| SeenCity { city: c.clone(), seen_turn })
+            .collect();
+        let units: Vec<SeenUnit> = self.units
+            .iter()
+            .filter(|u| &u.owner_id != player_id && visible.contains(&(u.q, u.r)))
+            .map(|u| SeenUnit { unit: u.clone(), seen_turn })
+            .collect();
+
+        let Some(intel) = self.intel.get_mut(player_idx) else {
+            return;
+        };
+        // Whatever was remembered on a tile we can see is either still there (and re-added
+        // below) or gone
+        intel.cities.retain(|_, seen| !visible.contains(&(seen.city.q, seen.city.r)));
+        intel.units.retain(|_, seen| !visible.contains(&(seen.unit.q, seen.unit.r)));
+        for seen in cities {
+            intel.cities.insert(seen.city.id.clone(), seen);
+        }
+        for seen in units {
+            intel.units.insert(seen.unit.id.clone(), seen);
+        }
+    }
+
+    fn intel_of(&self, player_id: &str) -> Option<&Intel> {
+        let idx = self.players.iter().position(|p| p.id == player_id)?;
+        self.intel.get(idx)
+    }
+
+    /// Enemy cities the player remembers but cannot currently see
+    pub fn last_seen_cities(&self, player_id: &str) -> Vec<SeenCity> {
+        let visible = self.get_visible_tiles(player_id);
+        self.intel_of(player_id)
+            .map(|intel| {
+                intel.cities
+                    .values()
+                    .filter(|seen| !visible.contains(&(seen.city.q, seen.city.r)))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Enemy units the player remembers but cannot currently see, at the position last seen
+    pub fn last_seen_units(&self, player_id: &str) -> Vec<SeenUnit> {
+        let visible = self.get_visible_tiles(player_id);
+        self.intel_of(player_id)
+            .map(|intel| {
+                intel.units
+                    .values()
+                    .filter(|seen| !visible.contains(&(seen.unit.q, seen.unit.r)))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
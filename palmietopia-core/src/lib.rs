@@ -1,6 +1,35 @@
 use wasm_bindgen::prelude::*;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::collections::{HashSet, VecDeque};
+use std::sync::OnceLock;
+
+mod command;
+mod delta;
+mod error;
+mod fog;
+mod index;
+mod intel;
+mod mapgen;
+mod outcome;
+mod pathfinding;
+mod replay;
+mod rng;
+mod sight;
+mod starts;
+mod view;
+
+pub use command::{GameCommand, GameEvent};
+pub use delta::ViewDelta;
+pub use error::GameError;
+pub use intel::{Intel, SeenCity, SeenUnit};
+pub use mapgen::{MapGenConfig, MapGenerator};
+pub use outcome::DrawOffer;
+pub use pathfinding::{Path, PathMoveOutcome, ReachableTile};
+pub use replay::{Replay, ReplayEntry};
+pub use rng::{random_seed, GameRng};
+pub use sight::{hex_line, FOREST_SIGHT_PENALTY, MOUNTAIN_VISION_BONUS};
+pub use starts::{balanced_starting_positions, evaluate_starts, start_spread, StartConfig, StartScore};
+pub use view::PlayerView;
 
 #[wasm_bindgen]
 pub fn get_welcome_message() -> String {
@@ -79,10 +108,23 @@ pub struct Lobby {
     pub map_size: MapSize,
     pub max_players: u8,
     pub status: LobbyStatus,
+    #[serde(default)]
+    pub seed: u32,
+    /// Terrain ratios the game's map is generated with
+    #[serde(default)]
+    pub map_config: MapGenConfig,
+    /// How evenly the capitals have to be placed
+    #[serde(default)]
+    pub start_config: StartConfig,
 }
 
 impl Lobby {
     pub fn new(id: String, host: Player, map_size: MapSize) -> Self {
+        Self::with_seed(id, host, map_size, random_seed())
+    }
+
+    /// Create a lobby whose game will be generated from a fixed seed (for reproducing games)
+    pub fn with_seed(id: String, host: Player, map_size: MapSize, seed: u32) -> Self {
         let host_id = host.id.clone();
         Self {
             id,
@@ -91,6 +133,9 @@ impl Lobby {
             map_size,
             max_players: 5,
             status: LobbyStatus::Waiting,
+            seed,
+            map_config: MapGenConfig::default(),
+            start_config: StartConfig::default(),
         }
     }
 
@@ -105,7 +150,7 @@ impl Lobby {
 
 // ============ Cities ============
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct City {
     pub id: String,
     pub owner_id: String,
@@ -175,7 +220,7 @@ impl UnitType {
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct Unit {
     pub id: String,
     pub owner_id: String,
@@ -222,14 +267,21 @@ pub const BASE_INCOME: u64 = 20;
 pub enum GameStatus {
     InProgress,
     Victory { winner_id: String },
+    /// Every player still in the game agreed to a draw
+    Draw,
+    /// Stopped by the host without a result
     Finished,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct GameSession {
     pub id: String,
+    #[serde(default)]
+    pub seed: u32,
     pub map: GameMap,
     pub players: Vec<Player>,
+    #[serde(default)]
+    pub host_id: String, // The lobby host, who may abort the game
     pub cities: Vec<City>,
     pub units: Vec<Unit>,
     pub current_turn: usize,
@@ -238,20 +290,40 @@ pub struct GameSession {
     pub player_times_ms: Vec<u64>,
     pub player_gold: Vec<u64>,
     pub explored_tiles: Vec<HashSet<(i32, i32)>>,  // Per-player explored tiles
+    #[serde(default)]
+    pub intel: Vec<Intel>, // Per-player last-seen enemy cities and units
+    #[serde(default)]
+    pub turn_number: u32, // Turns ended so far, across all players
+    #[serde(default)]
+    pub draw_offer: Option<DrawOffer>,
     pub turn_started_at_ms: u64,
     pub base_time_ms: u64,
     pub increment_ms: u64,
+    #[serde(default = "first_unit_id")]
+    pub next_unit_id: u32, // Counter for deterministic unit IDs
+    #[serde(skip)]
+    unit_index: index::UnitIndex,
 }
 
 pub const CITY_VISION_RANGE: i32 = 2;
 
+/// Starting units are numbered 0, so bought ones count up from here
+fn first_unit_id() -> u32 {
+    1
+}
+
+/// How many derived seeds `from_lobby` tries before settling for the most even layout it found
+const MAX_MAP_ATTEMPTS: u32 = 16;
+
+/// Axial offsets of the six neighbours of a hex
+pub const HEX_DIRECTIONS: [(i32, i32); 6] = [(1, 0), (1, -1), (0, -1), (-1, 0), (-1, 1), (0, 1)];
+
 impl GameSession {
     pub fn from_lobby(lobby: &Lobby) -> Self {
-        let map = GameMap::generate(lobby.map_size.radius());
         let player_count = lobby.players.len();
         
-        // Generate starting positions for cities
-        let starting_positions = Self::calculate_starting_positions(&map, player_count);
+        // Generate the map and starting positions for cities
+        let (map, starting_positions) = Self::starting_map(lobby);
         
         // Create cities and units for each player
         let mut cities = Vec::new();
@@ -286,8 +358,10 @@ impl GameSession {
         
         let mut session = Self {
             id: lobby.id.clone(),
+            seed: lobby.seed,
             map,
             players: lobby.players.clone(),
+            host_id: lobby.host_id.clone(),
             cities,
             units,
             current_turn: 0,
@@ -296,10 +370,16 @@ impl GameSession {
             player_times_ms: vec![DEFAULT_BASE_TIME_MS; player_count],
             player_gold: vec![STARTING_GOLD; player_count],
             explored_tiles,
+            intel: vec![Intel::default(); player_count],
+            turn_number: 0,
+            draw_offer: None,
             turn_started_at_ms: 0,
             base_time_ms: DEFAULT_BASE_TIME_MS,
             increment_ms: DEFAULT_INCREMENT_MS,
+            next_unit_id: first_unit_id(),
+            unit_index: Default::default(),
         };
+        session.reindex();
         
         // Initialize exploration for all players based on starting positions
         for player in &lobby.players {
@@ -309,43 +389,40 @@ impl GameSession {
         session
     }
 
-    fn calculate_starting_positions(map: &GameMap, player_count: usize) -> Vec<(i32, i32)> {
-        let mut positions = Vec::new();
-        let radius = map.radius as f64;
-        
-        // Get valid tiles (not water or mountain)
-        let valid_tiles: Vec<&Tile> = map.tiles.iter()
-            .filter(|t| t.terrain != Terrain::Water && t.terrain != Terrain::Mountain)
-            .collect();
-        
-        for i in 0..player_count {
-            // Calculate angle for this player's sector
-            let angle = (2.0 * std::f64::consts::PI * i as f64) / player_count as f64;
-            
-            // Target direction vector
-            let target_q = (angle.cos() * radius * 0.7) as i32;
-            let target_r = (angle.sin() * radius * 0.7) as i32;
-            
-            // Find valid tile closest to target that's far from existing positions
-            let best_tile = valid_tiles.iter()
-                .filter(|t| {
-                    // Ensure minimum distance from other starting positions
-                    positions.iter().all(|(pq, pr)| {
-                        Self::hex_distance(t.q, t.r, *pq, *pr) >= (radius as i32 / 2).max(3)
-                    })
-                })
-                .min_by_key(|t| {
-                    Self::hex_distance(t.q, t.r, target_q, target_r)
-                });
-            
-            if let Some(tile) = best_tile {
-                positions.push((tile.q, tile.r));
-            } else if let Some(tile) = valid_tiles.first() {
-                positions.push((tile.q, tile.r));
+    /// Pick the map and capitals for a new game. Each attempt uses the next derived seed and is
+    /// taken straight away if every capital was placed, all of them are joined over land and the
+    /// start spread is within tolerance. Otherwise the most even connected layout wins, and if no
+    /// attempt connected its capitals at all, the last map has land carved between them.
+    fn starting_map(lobby: &Lobby) -> (GameMap, Vec<(i32, i32)>) {
+        let radius = lobby.map_size.radius();
+        let player_count = lobby.players.len();
+        let config = &lobby.start_config;
+        let mut most_even = None;
+        let mut best_spread = f64::MAX;
+        let mut last = None;
+        for attempt in 0..MAX_MAP_ATTEMPTS {
+            let seed = lobby.seed.wrapping_add(attempt.wrapping_mul(0x9E37_79B9));
+            let map = GameMap::generate_with_config(radius, seed, &lobby.map_config);
+            let positions = balanced_starting_positions(&map, player_count, config);
+            if positions.len() < player_count || !map.all_connected(&positions) {
+                last = Some((map, positions));
+                continue;
+            }
+            let spread = start_spread(&evaluate_starts(&map, &positions, config));
+            if spread <= config.tolerance {
+                return (map, positions);
+            }
+            if spread < best_spread {
+                best_spread = spread;
+                most_even = Some((map, positions));
             }
         }
-        
-        positions
+        if let Some(layout) = most_even {
+            return layout;
+        }
+        let (mut map, positions) = last.expect("MAX_MAP_ATTEMPTS is at least 1");
+        map.join_over_land(&positions);
+        (map, positions)
     }
 
     pub fn hex_distance(q1: i32, r1: i32, q2: i32, r2: i32) -> i32 {
@@ -353,7 +430,7 @@ impl GameSession {
     }
 
     /// Get all tiles within a given range from a position
-    fn tiles_in_range(q: i32, r: i32, range: i32) -> Vec<(i32, i32)> {
+    pub(crate) fn tiles_in_range(q: i32, r: i32, range: i32) -> Vec<(i32, i32)> {
         let mut tiles = Vec::new();
         for dq in -range..=range {
             for dr in (-range).max(-dq - range)..=(range).min(-dq + range) {
@@ -363,41 +440,46 @@ impl GameSession {
         tiles
     }
 
-    /// Calculate all currently visible tiles for a player
+    /// Calculate all currently visible tiles for a player. Cities and units see up to their
+    /// vision range, subject to line of sight (see `sight_cost`).
     pub fn get_visible_tiles(&self, player_id: &str) -> HashSet<(i32, i32)> {
         let mut visible = HashSet::new();
         
         // Vision from cities owned by player
         for city in &self.cities {
             if city.owner_id == player_id {
-                for (tq, tr) in Self::tiles_in_range(city.q, city.r, CITY_VISION_RANGE) {
-                    if self.map.tiles.iter().any(|t| t.q == tq && t.r == tr) {
-                        visible.insert((tq, tr));
-                    }
-                }
+                visible.extend(self.tiles_in_sight(city.q, city.r, CITY_VISION_RANGE));
             }
         }
         
         // Vision from units owned by player
         for unit in &self.units {
             if unit.owner_id == player_id {
-                let vision = unit.unit_type.vision_range();
-                for (tq, tr) in Self::tiles_in_range(unit.q, unit.r, vision) {
-                    if self.map.tiles.iter().any(|t| t.q == tq && t.r == tr) {
-                        visible.insert((tq, tr));
-                    }
-                }
+                visible.extend(self.tiles_in_sight(unit.q, unit.r, unit.unit_type.vision_range()));
             }
         }
         
         visible
     }
 
-    /// Update explored tiles for a player (adds current visible tiles to explored)
+    /// Whether `attacker` can target `defender` from where it stands: adjacent units can always
+    /// be attacked, ranged attacks need line of sight within the attacker's vision
+    fn can_target(&self, attacker: &Unit, defender: &Unit) -> bool {
+        let distance = Self::hex_distance(attacker.q, attacker.r, defender.q, defender.r);
+        if distance <= 1 {
+            return true;
+        }
+        let vision = self.vision_range_at(attacker.unit_type.vision_range(), attacker.q, attacker.r);
+        self.has_line_of_sight((attacker.q, attacker.r), (defender.q, defender.r), vision)
+    }
+
+    /// Update explored tiles for a player (adds current visible tiles to explored) and
+    /// remember the enemy cities and units they can see
     pub fn update_exploration(&mut self, player_id: &str) {
         let player_idx = self.players.iter().position(|p| p.id == player_id);
         if let Some(idx) = player_idx {
             let visible = self.get_visible_tiles(player_id);
+            self.record_sightings(idx, &visible);
             self.explored_tiles[idx].extend(visible);
         }
     }
@@ -428,7 +510,7 @@ impl GameSession {
     }
 
     pub fn get_terrain_at(&self, q: i32, r: i32) -> Option<Terrain> {
-        self.map.tiles.iter().find(|t| t.q == q && t.r == r).map(|t| t.terrain)
+        self.map.terrain_at(q, r)
     }
 
     pub fn movement_cost(terrain: Terrain) -> Option<u32> {
@@ -439,42 +521,42 @@ impl GameSession {
         }
     }
 
-    pub fn can_move_unit(&self, unit_id: &str, to_q: i32, to_r: i32) -> Result<u32, String> {
-        let unit = self.units.iter().find(|u| u.id == unit_id)
-            .ok_or("Unit not found")?;
+    pub fn can_move_unit(&self, unit_id: &str, to_q: i32, to_r: i32) -> Result<u32, GameError> {
+        let unit = self.unit(unit_id)
+            .ok_or_else(|| GameError::UnitNotFound { unit_id: unit_id.to_string() })?;
         
         // Check if destination tile exists and get terrain
         let terrain = self.get_terrain_at(to_q, to_r)
-            .ok_or("Invalid destination")?;
+            .ok_or(GameError::InvalidDestination)?;
         
         // Check terrain is passable
         let cost = Self::movement_cost(terrain)
-            .ok_or("Cannot move to water")?;
+            .ok_or(GameError::ImpassableTerrain)?;
         
         // Check distance is exactly 1
         let distance = Self::hex_distance(unit.q, unit.r, to_q, to_r);
         if distance != 1 {
-            return Err("Can only move to adjacent tiles".to_string());
+            return Err(GameError::NotAdjacent);
         }
         
         // Check unit has enough movement
         if unit.movement_remaining < cost {
-            return Err("Not enough movement remaining".to_string());
+            return Err(GameError::NotEnoughMovement { required: cost, remaining: unit.movement_remaining });
         }
         
         // Check no other unit occupies the tile
-        if self.units.iter().any(|u| u.q == to_q && u.r == to_r) {
-            return Err("Tile is occupied".to_string());
+        if self.is_occupied(to_q, to_r) {
+            return Err(GameError::TileOccupied);
         }
         
         Ok(cost)
     }
 
-    pub fn move_unit(&mut self, unit_id: &str, to_q: i32, to_r: i32) -> Result<MoveOutcome, String> {
+    pub fn move_unit(&mut self, unit_id: &str, to_q: i32, to_r: i32) -> Result<MoveOutcome, GameError> {
         let cost = self.can_move_unit(unit_id, to_q, to_r)?;
         
         let unit = self.units.iter_mut().find(|u| u.id == unit_id)
-            .ok_or("Unit not found")?;
+            .ok_or_else(|| GameError::UnitNotFound { unit_id: unit_id.to_string() })?;
         
         let attacker_owner = unit.owner_id.clone();
         unit.q = to_q;
@@ -487,6 +569,7 @@ impl GameSession {
         
         // Check for city capture
         let (captured_city, eliminated_player) = self.try_capture_city(to_q, to_r, &attacker_owner);
+        self.reindex();
         
         Ok(MoveOutcome {
             movement_remaining,
@@ -513,7 +596,8 @@ impl GameSession {
         let is_capitol = self.cities[idx].is_capitol;
         let mut eliminated_player = None;
         
-        if is_capitol {
+        // A resigned player's capital is just another city
+        if is_capitol && !self.eliminated_players.contains(&old_owner) {
             // Eliminate the player!
             eliminated_player = Some(old_owner.clone());
             self.eliminated_players.push(old_owner.clone());
@@ -531,13 +615,7 @@ impl GameSession {
             // Remove all their units
             self.units.retain(|u| u.owner_id != old_owner);
             
-            // Check for victory
-            let remaining_players: Vec<_> = self.players.iter()
-                .filter(|p| !self.eliminated_players.contains(&p.id))
-                .collect();
-            if remaining_players.len() == 1 {
-                self.status = GameStatus::Victory { winner_id: remaining_players[0].id.clone() };
-            }
+            self.check_victory();
         } else {
             // Just capture the city
             self.cities[idx].owner_id = new_owner.to_string();
@@ -547,6 +625,16 @@ impl GameSession {
         (captured_city, eliminated_player)
     }
 
+    /// The last player standing wins
+    pub(crate) fn check_victory(&mut self) {
+        let remaining_players: Vec<_> = self.players.iter()
+            .filter(|p| !self.eliminated_players.contains(&p.id))
+            .collect();
+        if remaining_players.len() == 1 {
+            self.status = GameStatus::Victory { winner_id: remaining_players[0].id.clone() };
+        }
+    }
+
     pub fn reset_movement_for_player(&mut self, player_id: &str) {
         for unit in self.units.iter_mut() {
             if unit.owner_id == player_id {
@@ -555,13 +643,13 @@ impl GameSession {
         }
     }
 
-    pub fn fortify_unit(&mut self, unit_id: &str) -> Result<u32, String> {
+    pub fn fortify_unit(&mut self, unit_id: &str) -> Result<u32, GameError> {
         let unit = self.units.iter_mut().find(|u| u.id == unit_id)
-            .ok_or("Unit not found")?;
+            .ok_or_else(|| GameError::UnitNotFound { unit_id: unit_id.to_string() })?;
         
         // Must have full movement (hasn't acted this turn)
         if unit.movement_remaining < unit.unit_type.base_movement() {
-            return Err("Cannot fortify after moving".to_string());
+            return Err(GameError::CannotFortifyAfterMoving);
         }
         
         // Heal 25% of max HP
@@ -572,14 +660,14 @@ impl GameSession {
         Ok(unit.hp)
     }
 
-    pub fn buy_unit(&mut self, player_id: &str, city_id: &str, unit_type: UnitType) -> Result<Unit, String> {
+    pub fn buy_unit(&mut self, player_id: &str, city_id: &str, unit_type: UnitType) -> Result<Unit, GameError> {
         // Find player index
         let player_idx = self.players.iter().position(|p| p.id == player_id)
-            .ok_or("Player not found")?;
+            .ok_or(GameError::PlayerNotFound)?;
         
         // Find city
         let city_idx = self.cities.iter().position(|c| c.id == city_id)
-            .ok_or("City not found")?;
+            .ok_or_else(|| GameError::CityNotFound { city_id: city_id.to_string() })?;
         
         // Extract city info first to avoid borrow issues
         let city = &self.cities[city_idx];
@@ -590,23 +678,24 @@ impl GameSession {
         
         // Validate ownership
         if city_owner != player_id {
-            return Err("Not your city".to_string());
+            return Err(GameError::NotYourCity);
         }
         
         // Check if city already produced this turn
         if city_produced {
-            return Err("City has already produced this turn".to_string());
+            return Err(GameError::CityAlreadyProduced);
         }
         
         // Check if city is unoccupied
-        if self.units.iter().any(|u| u.q == city_q && u.r == city_r) {
-            return Err("City is occupied by a unit".to_string());
+        if self.is_occupied(city_q, city_r) {
+            return Err(GameError::CityOccupied);
         }
         
         // Check gold
         let cost = unit_type.cost();
-        if self.player_gold[player_idx] < cost {
-            return Err("Not enough gold".to_string());
+        let balance = self.player_gold[player_idx];
+        if balance < cost {
+            return Err(GameError::NotEnoughGold { cost, balance });
         }
         
         // Deduct gold
@@ -615,11 +704,9 @@ impl GameSession {
         // Mark city as produced
         self.cities[city_idx].produced_this_turn = true;
         
-        // Create unit with 0 movement - generate random ID
-        let mut rand_bytes = [0u8; 8];
-        getrandom::getrandom(&mut rand_bytes).unwrap();
-        let rand_num = u64::from_le_bytes(rand_bytes);
-        let unit_id = format!("unit-{}-{:x}", player_id, rand_num);
+        // Create unit with 0 movement - IDs come from a counter so replays are deterministic
+        let unit_id = format!("unit-{}-{}", player_id, self.next_unit_id);
+        self.next_unit_id += 1;
         let mut unit = Unit::new(
             unit_id,
             player_id.to_string(),
@@ -630,6 +717,7 @@ impl GameSession {
         unit.movement_remaining = 0; // Can't move on turn created
         
         self.units.push(unit.clone());
+        self.reindex();
         
         Ok(unit)
     }
@@ -642,6 +730,15 @@ impl GameSession {
         
         // Grant income to the player who just finished their turn
         self.player_gold[current] += BASE_INCOME;
+        self.pass_turn();
+    }
+
+    /// Hand the turn to the next player still in the game, without settling the clock or income
+    /// of the player whose turn it was
+    pub(crate) fn pass_turn(&mut self) {
+        let current = self.current_turn;
+        self.turn_number += 1;
+        self.lapse_draw_offer();
         
         // Skip eliminated players
         loop {
@@ -687,13 +784,32 @@ impl GameSession {
         }
     }
 
+    /// IDs of enemy units the given unit can attack right now (within `UnitType::range` and
+    /// with movement left and, for ranged attacks, in line of sight), using the same rules as
+    /// `resolve_combat`
+    pub fn attackable_units(&self, unit_id: &str) -> Vec<String> {
+        let Some(attacker) = self.unit(unit_id) else {
+            return Vec::new();
+        };
+        if attacker.movement_remaining == 0 {
+            return Vec::new();
+        }
+        let range = attacker.unit_type.range();
+        self.units.iter()
+            .filter(|u| u.owner_id != attacker.owner_id)
+            .filter(|u| Self::hex_distance(attacker.q, attacker.r, u.q, u.r) <= range)
+            .filter(|u| self.can_target(attacker, u))
+            .map(|u| u.id.clone())
+            .collect()
+    }
+
     /// Combat result struct
-    pub fn resolve_combat(&mut self, attacker_id: &str, defender_id: &str) -> Result<CombatOutcome, String> {
+    pub fn resolve_combat(&mut self, attacker_id: &str, defender_id: &str) -> Result<CombatOutcome, GameError> {
         // Find units
         let attacker_idx = self.units.iter().position(|u| u.id == attacker_id)
-            .ok_or("Attacker not found")?;
+            .ok_or_else(|| GameError::UnitNotFound { unit_id: attacker_id.to_string() })?;
         let defender_idx = self.units.iter().position(|u| u.id == defender_id)
-            .ok_or("Defender not found")?;
+            .ok_or_else(|| GameError::UnitNotFound { unit_id: defender_id.to_string() })?;
         
         // Check attacker can reach defender (within range)
         let attacker = &self.units[attacker_idx];
@@ -701,12 +817,15 @@ impl GameSession {
         let distance = Self::hex_distance(attacker.q, attacker.r, defender.q, defender.r);
         let attacker_range = attacker.unit_type.range();
         if distance > attacker_range {
-            return Err(format!("Target out of range (range: {}, distance: {})", attacker_range, distance));
+            return Err(GameError::OutOfRange { range: attacker_range, distance });
+        }
+        if !self.can_target(attacker, defender) {
+            return Err(GameError::NoLineOfSight);
         }
         
         // Check attacker has movement
         if attacker.movement_remaining == 0 {
-            return Err("No movement remaining to attack".to_string());
+            return Err(GameError::NoMovementToAttack);
         }
         
         // Calculate damage
@@ -770,6 +889,7 @@ impl GameSession {
             captured_city = cap_city;
             eliminated_player = elim_player;
         }
+        self.reindex();
         
         Ok(CombatOutcome {
             attacker_hp,
@@ -809,53 +929,128 @@ pub struct MoveOutcome {
 
 // ============ Messages ============
 
+/// Whether a player in a game has a client connected to it
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Presence {
+    Connected,
+    /// Connected, but the player has switched to another tab or window
+    Away,
+    Disconnected,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum ClientMessage {
-    CreateLobby { player_name: String, map_size: MapSize },
+    CreateLobby {
+        player_name: String,
+        map_size: MapSize,
+        #[serde(default)]
+        seed: Option<u32>, // Fixed seed to reproduce a game; random when omitted
+        #[serde(default)]
+        map_config: Option<MapGenConfig>,
+        #[serde(default)]
+        start_config: Option<StartConfig>,
+    },
     JoinLobby { lobby_id: String, player_name: String },
+    /// Get back into the lobby a token was issued for after losing the connection
+    RejoinLobby { token: String },
     LeaveLobby,
     StartGame,
     ListLobbies,
-    EndTurn { game_id: String, player_id: String },
-    RejoinGame { game_id: String, player_id: String },
-    MoveUnit { game_id: String, player_id: String, unit_id: String, to_q: i32, to_r: i32 },
-    AttackUnit { game_id: String, player_id: String, attacker_id: String, defender_id: String },
-    FortifyUnit { game_id: String, player_id: String, unit_id: String },
-    BuyUnit { game_id: String, player_id: String, city_id: String, unit_type: String },
+    // Game actions act as the player the socket is bound to (see RejoinGame); clients don't say
+    // who they are
+    EndTurn { game_id: String },
+    /// Resume as the player a `GameStarted` token was issued to
+    RejoinGame { game_id: String, token: String },
+    /// Ask for the game messages after `since_seq`, e.g. after noticing a gap. The server resends
+    /// them, or replies with a `Snapshot` if they are no longer available.
+    RequestSync { game_id: String, since_seq: u64 },
+    MoveUnit { game_id: String, unit_id: String, to_q: i32, to_r: i32 },
+    MoveUnitPath { game_id: String, unit_id: String, path: Vec<(i32, i32)> },
+    AttackUnit { game_id: String, attacker_id: String, defender_id: String },
+    FortifyUnit { game_id: String, unit_id: String },
+    BuyUnit { game_id: String, city_id: String, unit_type: String },
+    /// The player stepped away from (or came back to) the game window
+    SetAway { game_id: String, away: bool },
+    Resign { game_id: String },
+    /// Offer a draw, which stands until the end of the next turn
+    OfferDraw { game_id: String },
+    AcceptDraw { game_id: String },
+    /// Stop the game without a result (host only)
+    AbortGame { game_id: String },
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum ServerMessage {
-    LobbyCreated { lobby_id: String, player_id: String },
-    JoinedLobby { lobby: Lobby, player_id: String },
+    /// `token` is a secret the client keeps to get back in after a disconnect
+    LobbyCreated { lobby_id: String, player_id: String, token: String },
+    JoinedLobby { lobby: Lobby, player_id: String, token: String },
     LobbyUpdated { lobby: Lobby },
     LobbyList { lobbies: Vec<Lobby> },
-    GameStarted { game: GameSession },
-    GameRejoined { game: GameSession },
+    /// Full views carry the sequence number of the last game message they reflect
+    GameStarted { game: PlayerView, last_seq: u64, token: String },
+    GameRejoined { game: PlayerView, last_seq: u64 },
+    Snapshot { game: PlayerView, last_seq: u64 },
     PlayerLeft { player_id: String },
-    Error { message: String },
-    TurnChanged { current_turn: usize, player_times_ms: Vec<u64>, player_gold: Vec<u64>, units: Vec<Unit>, cities: Vec<City>, explored_tiles: Vec<HashSet<(i32, i32)>> },
+    Error { code: String, message: String },
+    TurnChanged { current_turn: usize, player_times_ms: Vec<u64> },
     TimeTick { player_index: usize, remaining_ms: u64 },
-    UnitMoved { unit_id: String, to_q: i32, to_r: i32, movement_remaining: u32, explored_tiles: Vec<HashSet<(i32, i32)>> },
+    UnitMoved { unit_id: String, to_q: i32, to_r: i32, movement_remaining: u32 },
+    UnitMovedAlongPath { unit_id: String, path: Vec<(i32, i32)>, movement_remaining: u32 },
+    /// The attacker's side is left out (`None`) for players who couldn't see the attacker
     CombatResult {
-        attacker_id: String,
+        attacker_id: Option<String>,
         defender_id: String,
-        attacker_hp: u32,
+        attacker_hp: Option<u32>,
         defender_hp: u32,
-        damage_to_attacker: u32,
+        damage_to_attacker: Option<u32>,
         damage_to_defender: u32,
-        attacker_died: bool,
+        attacker_died: Option<bool>,
         defender_died: bool,
         attacker_new_q: Option<i32>,
         attacker_new_r: Option<i32>,
     },
     PlayerEliminated { player_id: String, conquerer_id: String },
-    CitiesCaptured { cities: Vec<City> },
+    PlayerResigned { player_id: String },
+    DrawOffered { player_id: String },
+    DrawAccepted { player_id: String },
+    GameDrawn,
+    /// `player_id` is the host who stopped it, or None if everyone had left
+    GameAborted { player_id: Option<String> },
+    /// A player connected, dropped or went away. While disconnected, `grace_ends_at_ms` is when
+    /// the server stops waiting for them to come back.
+    PresenceChanged { player_id: String, presence: Presence, grace_ends_at_ms: Option<u64> },
+    CitiesCaptured { city_ids: Vec<String> },
     GameOver { winner_id: String },
     UnitFortified { unit_id: String, new_hp: u32 },
     UnitPurchased { unit: Unit, city_id: String, player_gold: u64 },
+    /// What changed in the player's view after a command
+    StateDelta { delta: ViewDelta },
+}
+
+/// A message sent on a player's game channel, numbered so the client can tell when it missed
+/// one. Serialized flat: `{"seq": 7, "type": "UnitMoved", ...}`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SequencedMessage {
+    pub seq: u64,
+    #[serde(flatten)]
+    pub message: ServerMessage,
+}
+
+impl ServerMessage {
+    pub fn error(code: &str, message: impl Into<String>) -> Self {
+        ServerMessage::Error {
+            code: code.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+impl From<GameError> for ServerMessage {
+    fn from(err: GameError) -> Self {
+        ServerMessage::error(err.code(), err.to_string())
+    }
 }
 
 /// Terrain types for map tiles
@@ -869,22 +1064,6 @@ pub enum Terrain {
     Desert,
 }
 
-impl Terrain {
-    /// Get a random terrain type
-    fn random() -> Self {
-        use getrandom::getrandom;
-        let mut buf = [0u8; 1];
-        getrandom(&mut buf).unwrap();
-        match buf[0] % 5 {
-            0 => Terrain::Grassland,
-            1 => Terrain::Forest,
-            2 => Terrain::Mountain,
-            3 => Terrain::Water,
-            _ => Terrain::Desert,
-        }
-    }
-}
-
 /// A single hex tile with axial coordinates
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Tile {
@@ -893,39 +1072,145 @@ pub struct Tile {
     pub terrain: Terrain,
 }
 
-/// The game map containing all tiles
+/// The game map containing all tiles. Tiles are fixed once the map is generated.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct GameMap {
     pub tiles: Vec<Tile>,
     pub radius: u32,
+    #[serde(skip)]
+    index: OnceLock<index::TileIndex>, // Built on first lookup
 }
 
 impl GameMap {
-    /// Generate a hexagonal map with the given radius
-    pub fn generate(radius: u32) -> Self {
-        let mut tiles = Vec::new();
-        let r = radius as i32;
-
-        // Generate hexagonal map using axial coordinates
-        for q in -r..=r {
-            let r1 = (-r).max(-q - r);
-            let r2 = r.min(-q + r);
-            for r_coord in r1..=r2 {
-                tiles.push(Tile {
-                    q,
-                    r: r_coord,
-                    terrain: Terrain::random(),
-                });
+    /// Generate a hexagonal map with the given radius. The same seed always yields the same tiles.
+    pub fn generate(radius: u32, seed: u32) -> Self {
+        Self::generate_with_config(radius, seed, &MapGenConfig::default())
+    }
+
+    /// Generate a map with custom terrain ratios (e.g. a wetter or drier world)
+    pub fn generate_with_config(radius: u32, seed: u32, config: &MapGenConfig) -> Self {
+        MapGenerator::new(config.clone()).generate(radius, seed)
+    }
+
+    /// All tiles a land unit could walk to from (q, r), ignoring units and movement points
+    pub fn reachable_from(&self, q: i32, r: i32) -> HashSet<(i32, i32)> {
+        let mut reached = HashSet::new();
+        let passable = |q: i32, r: i32| {
+            self.terrain_at(q, r).and_then(GameSession::movement_cost).is_some()
+        };
+        if !passable(q, r) {
+            return reached;
+        }
+        reached.insert((q, r));
+        let mut queue = VecDeque::from([(q, r)]);
+        while let Some((cq, cr)) = queue.pop_front() {
+            for (dq, dr) in HEX_DIRECTIONS {
+                let next = (cq + dq, cr + dr);
+                if !reached.contains(&next) && passable(next.0, next.1) {
+                    reached.insert(next);
+                    queue.push_back(next);
+                }
             }
         }
+        reached
+    }
 
-        GameMap { tiles, radius }
+    /// Turn the water on the straight line from the first position to each of the others into
+    /// grassland, so that all of them are joined over land
+    pub fn join_over_land(&mut self, positions: &[(i32, i32)]) {
+        let Some(&first) = positions.first() else {
+            return;
+        };
+        let carved: HashSet<(i32, i32)> = positions.iter().flat_map(|&p| hex_line(first, p)).collect();
+        for tile in &mut self.tiles {
+            if tile.terrain == Terrain::Water && carved.contains(&(tile.q, tile.r)) {
+                tile.terrain = Terrain::Grassland;
+            }
+        }
+    }
+
+    /// Check that every position has a land path to every other position
+    pub fn all_connected(&self, positions: &[(i32, i32)]) -> bool {
+        let Some(&(q, r)) = positions.first() else {
+            return true;
+        };
+        let reachable = self.reachable_from(q, r);
+        positions.iter().all(|p| reachable.contains(p))
     }
 }
 
 /// Generate a tiny map (radius 2, 19 tiles)
 #[wasm_bindgen]
 pub fn generate_tiny_map() -> String {
-    let map = GameMap::generate(2);
+    let map = GameMap::generate(2, random_seed());
+    serde_json::to_string(&map).unwrap()
+}
+
+/// Tiles a unit can move to this turn, as JSON `[{q, r, cost}]`. Takes the game as JSON.
+#[wasm_bindgen]
+pub fn get_reachable_tiles(game_json: &str, unit_id: &str) -> Result<String, JsError> {
+    let game: GameSession = serde_json::from_str(game_json)?;
+    Ok(serde_json::to_string(&game.reachable_tiles(unit_id))?)
+}
+
+/// IDs of enemy units a unit can attack right now, as a JSON array. Takes the game as JSON.
+#[wasm_bindgen]
+pub fn get_attackable_units(game_json: &str, unit_id: &str) -> Result<String, JsError> {
+    let game: GameSession = serde_json::from_str(game_json)?;
+    Ok(serde_json::to_string(&game.attackable_units(unit_id))?)
+}
+
+/// Apply a command to a game (both as JSON), the same way the server does. Returns
+/// `{"game": ..., "events": [...]}` so the client can update optimistically before the server
+/// confirms; rejected commands throw with the `GameError` as JSON.
+#[wasm_bindgen]
+pub fn apply_command(game_json: &str, command_json: &str) -> Result<String, JsError> {
+    let mut game: GameSession = serde_json::from_str(game_json)?;
+    let command: GameCommand = serde_json::from_str(command_json)?;
+    let events = game.apply(command)
+        .map_err(|e| JsError::new(&serde_json::to_string(&e).unwrap()))?;
+    Ok(serde_json::to_string(&serde_json::json!({ "game": game, "events": events }))?)
+}
+
+/// Tiles one of the player's units can move to this turn, as JSON `[{q, r, cost}]`. Takes the
+/// player's `PlayerView` as JSON.
+#[wasm_bindgen]
+pub fn get_reachable_tiles_for_view(view_json: &str, unit_id: &str) -> Result<String, JsError> {
+    let view: PlayerView = serde_json::from_str(view_json)?;
+    Ok(serde_json::to_string(&view.known_session().reachable_tiles(unit_id))?)
+}
+
+/// IDs of the enemy units in sight that one of the player's units can attack right now, as a
+/// JSON array. Takes the player's `PlayerView` as JSON.
+#[wasm_bindgen]
+pub fn get_attackable_units_for_view(view_json: &str, unit_id: &str) -> Result<String, JsError> {
+    let view: PlayerView = serde_json::from_str(view_json)?;
+    Ok(serde_json::to_string(&view.known_session().attackable_units(unit_id))?)
+}
+
+/// Apply a command to the player's `PlayerView` (both as JSON) for an optimistic update; see
+/// `PlayerView::apply`, which leaves purchases to the server. Returns `{"view": ..., "events": [...]}`; rejected commands throw with
+/// the `GameError` as JSON.
+#[wasm_bindgen]
+pub fn apply_command_to_view(view_json: &str, command_json: &str) -> Result<String, JsError> {
+    let mut view: PlayerView = serde_json::from_str(view_json)?;
+    let command: GameCommand = serde_json::from_str(command_json)?;
+    let events = view.apply(command)
+        .map_err(|e| JsError::new(&serde_json::to_string(&e).unwrap()))?;
+    Ok(serde_json::to_string(&serde_json::json!({ "view": view, "events": events }))?)
+}
+
+/// A* route for one of the player's units to a tile, as JSON `{steps, cost}` or `null` when there
+/// is none. Takes the player's `PlayerView` as JSON, so only units in sight are avoided.
+#[wasm_bindgen]
+pub fn find_path_for_view(view_json: &str, unit_id: &str, to_q: i32, to_r: i32) -> Result<String, JsError> {
+    let view: PlayerView = serde_json::from_str(view_json)?;
+    Ok(serde_json::to_string(&view.known_session().find_path(unit_id, to_q, to_r))?)
+}
+
+/// Generate a map for the given size and seed (matches what the server builds for a lobby)
+#[wasm_bindgen]
+pub fn generate_map(map_size: MapSize, seed: u32) -> String {
+    let map = GameMap::generate(map_size.radius(), seed);
     serde_json::to_string(&map).unwrap()
 }
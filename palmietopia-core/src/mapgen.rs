@@ -0,0 +1,390 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+use crate::{GameMap, GameRng, Terrain, Tile, HEX_DIRECTIONS};
+
+/// Tunable knobs for the terrain generator. Ratios are fractions of the whole map (water)
+/// or of the land that is left over (mountains, forests, deserts).
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct MapGenConfig {
+    pub water_ratio: f64,
+    pub mountain_ratio: f64,
+    pub forest_ratio: f64,
+    pub desert_ratio: f64,
+    /// Number of rivers per 4 tiles of radius (Huge maps get 2 with the default)
+    pub rivers_per_4_radius: u32,
+}
+
+impl Default for MapGenConfig {
+    fn default() -> Self {
+        Self {
+            water_ratio: 0.25,
+            mountain_ratio: 0.12,
+            forest_ratio: 0.30,
+            desert_ratio: 0.15,
+            rivers_per_4_radius: 1,
+        }
+    }
+}
+
+/// Most water a map may be made of; the rest has to hold every capital
+const MAX_WATER_RATIO: f64 = 0.75;
+/// Most rivers per 4 tiles of radius
+const MAX_RIVERS_PER_4_RADIUS: u32 = 8;
+
+impl MapGenConfig {
+    /// Whether a playable map can be generated with these settings (e.g. from a lobby's host)
+    pub fn is_valid(&self) -> bool {
+        let ratio = |r: f64| (0.0..=1.0).contains(&r);
+        (0.0..=MAX_WATER_RATIO).contains(&self.water_ratio)
+            && ratio(self.mountain_ratio)
+            && ratio(self.forest_ratio)
+            && ratio(self.desert_ratio)
+            && self.rivers_per_4_radius <= MAX_RIVERS_PER_4_RADIUS
+    }
+}
+
+/// Builds maps from two noise fields: elevation decides water/land/mountains, moisture decides
+/// forest/grassland/desert. A radial falloff pushes water to the edges so land forms continents.
+pub struct MapGenerator {
+    config: MapGenConfig,
+}
+
+impl MapGenerator {
+    pub fn new(config: MapGenConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn generate(&self, radius: u32, seed: u32) -> GameMap {
+        let mut rng = GameRng::new(seed);
+        let elevation_noise = ValueNoise::new(rng.next_u64());
+        let ridge_noise = ValueNoise::new(rng.next_u64());
+        let moisture_noise = ValueNoise::new(rng.next_u64());
+
+        let coords = hex_coords(radius);
+        let index: HashMap<(i32, i32), usize> =
+            coords.iter().enumerate().map(|(i, &c)| (c, i)).collect();
+        let scale = (radius.max(1)) as f64;
+
+        // Elevation: fractal noise minus a radial falloff so the map edge tends to be sea
+        let mut elevation: Vec<f64> = coords
+            .iter()
+            .map(|&(q, r)| {
+                let (x, y) = to_cartesian(q, r);
+                let (nx, ny) = (x / scale, y / scale);
+                let dist = (nx * nx + ny * ny).sqrt() / 1.5;
+                let base = elevation_noise.fbm(nx * 1.6, ny * 1.6, 4);
+                base - 0.55 * dist * dist
+            })
+            .collect();
+
+        let mut terrain = vec![Terrain::Grassland; coords.len()];
+
+        // Water: the lowest `water_ratio` of tiles
+        let water_count = ratio_count(coords.len(), self.config.water_ratio);
+        for i in lowest(&elevation, water_count, |_| true) {
+            terrain[i] = Terrain::Water;
+        }
+
+        // Mountains: ridged noise concentrates peaks into ranges instead of lone hexes
+        let mountain_score: Vec<f64> = coords
+            .iter()
+            .zip(&elevation)
+            .map(|(&(q, r), &e)| {
+                let (x, y) = to_cartesian(q, r);
+                let ridge = 1.0 - (ridge_noise.fbm(x / scale * 2.2, y / scale * 2.2, 3) * 2.0 - 1.0).abs();
+                e * 0.5 + ridge * 0.5
+            })
+            .collect();
+        let land_count = terrain.iter().filter(|t| **t != Terrain::Water).count();
+        let mountain_count = ratio_count(land_count, self.config.mountain_ratio);
+        let inverted: Vec<f64> = mountain_score.iter().map(|s| -s).collect();
+        for i in lowest(&inverted, mountain_count, |i| terrain[i] != Terrain::Water) {
+            terrain[i] = Terrain::Mountain;
+        }
+
+        // Rivers run downhill from the mountains to the sea
+        let river_count = radius / 4 * self.config.rivers_per_4_radius;
+        for _ in 0..river_count {
+            if let Some(path) = carve_river(&coords, &index, &mut elevation, &terrain, &mut rng) {
+                for i in path {
+                    terrain[i] = Terrain::Water;
+                }
+            }
+        }
+
+        // Moisture: noise plus a bonus close to the coast and to rivers
+        let moisture: Vec<f64> = coords
+            .iter()
+            .map(|&(q, r)| {
+                let (x, y) = to_cartesian(q, r);
+                let wet_neighbours = HEX_DIRECTIONS
+                    .iter()
+                    .filter(|(dq, dr)| {
+                        index.get(&(q + dq, r + dr)).is_some_and(|&n| terrain[n] == Terrain::Water)
+                    })
+                    .count();
+                moisture_noise.fbm(x / scale * 2.0, y / scale * 2.0, 3) + wet_neighbours as f64 * 0.08
+            })
+            .collect();
+
+        let open_land = |i: usize| terrain[i] == Terrain::Grassland;
+        let open_count = (0..coords.len()).filter(|&i| open_land(i)).count();
+        let desert_count = ratio_count(open_count, self.config.desert_ratio);
+        let forest_count = ratio_count(open_count, self.config.forest_ratio);
+        let deserts = lowest(&moisture, desert_count, open_land);
+        let wettest: Vec<f64> = moisture.iter().map(|m| -m).collect();
+        let forests = lowest(&wettest, forest_count, open_land);
+        for i in deserts {
+            terrain[i] = Terrain::Desert;
+        }
+        for i in forests {
+            terrain[i] = Terrain::Forest;
+        }
+
+        // Rivers and noise can cut off islands; make sure every land tile can reach every other
+        connect_landmasses(&coords, &index, &mut terrain);
+
+        let tiles = coords
+            .iter()
+            .zip(terrain)
+            .map(|(&(q, r), terrain)| Tile { q, r, terrain })
+            .collect();
+
+        GameMap::new(tiles, radius)
+    }
+}
+
+/// All axial coordinates of a hexagonal map, in the same order `GameMap` has always used
+pub(crate) fn hex_coords(radius: u32) -> Vec<(i32, i32)> {
+    let r = radius as i32;
+    let mut coords = Vec::new();
+    for q in -r..=r {
+        let r1 = (-r).max(-q - r);
+        let r2 = r.min(-q + r);
+        for r_coord in r1..=r2 {
+            coords.push((q, r_coord));
+        }
+    }
+    coords
+}
+
+/// Pointy-top axial to cartesian, so noise isn't skewed along the r axis
+fn to_cartesian(q: i32, r: i32) -> (f64, f64) {
+    let x = 3f64.sqrt() * (q as f64 + r as f64 / 2.0);
+    let y = 1.5 * r as f64;
+    (x, y)
+}
+
+fn ratio_count(total: usize, ratio: f64) -> usize {
+    ((total as f64 * ratio.clamp(0.0, 1.0)).round() as usize).min(total)
+}
+
+/// Indices of the `count` smallest values among those accepted by `filter` (ties by index)
+fn lowest(values: &[f64], count: usize, filter: impl Fn(usize) -> bool) -> Vec<usize> {
+    let mut candidates: Vec<usize> = (0..values.len()).filter(|&i| filter(i)).collect();
+    candidates.sort_by(|&a, &b| values[a].total_cmp(&values[b]).then(a.cmp(&b)));
+    candidates.truncate(count);
+    candidates
+}
+
+/// Walk downhill from a random mountain until reaching water. Returns the land tiles crossed
+/// (mountains excluded), or None if there is no mountain or the walk gets stuck in a basin.
+fn carve_river(
+    coords: &[(i32, i32)],
+    index: &HashMap<(i32, i32), usize>,
+    elevation: &mut [f64],
+    terrain: &[Terrain],
+    rng: &mut GameRng,
+) -> Option<Vec<usize>> {
+    let sources: Vec<usize> = (0..coords.len()).filter(|&i| terrain[i] == Terrain::Mountain).collect();
+    if sources.is_empty() {
+        return None;
+    }
+    let mut current = sources[rng.below(sources.len() as u32) as usize];
+    let mut path = Vec::new();
+
+    for _ in 0..coords.len() {
+        let (q, r) = coords[current];
+        let next = HEX_DIRECTIONS
+            .iter()
+            .filter_map(|(dq, dr)| index.get(&(q + dq, r + dr)).copied())
+            .filter(|n| !path.contains(n))
+            .min_by(|&a, &b| elevation[a].total_cmp(&elevation[b]))?;
+
+        if terrain[next] == Terrain::Water {
+            return Some(path);
+        }
+        if elevation[next] > elevation[current] {
+            return None;
+        }
+        if terrain[next] != Terrain::Mountain {
+            path.push(next);
+        }
+        // Lower the riverbed slightly so later rivers tend to merge into this one
+        elevation[next] -= 0.01;
+        current = next;
+    }
+    None
+}
+
+/// Islands smaller than this are sunk instead of being bridged to the mainland
+const MIN_ISLAND_SIZE: usize = 3;
+
+/// Repair the map so all passable tiles form a single landmass. Tiny islands are flooded,
+/// larger ones are joined to the mainland by turning the shortest stretch of water into land.
+fn connect_landmasses(coords: &[(i32, i32)], index: &HashMap<(i32, i32), usize>, terrain: &mut [Terrain]) {
+    loop {
+        let components = land_components(coords, index, terrain);
+        if components.len() <= 1 {
+            return;
+        }
+
+        let mainland = components.iter().max_by_key(|c| c.len()).unwrap();
+        let mut component_of = vec![usize::MAX; coords.len()];
+        for (id, component) in components.iter().enumerate() {
+            for &i in component {
+                component_of[i] = id;
+            }
+        }
+        let mainland_id = component_of[mainland[0]];
+
+        let mut sank_island = false;
+        for component in &components {
+            if component.len() < MIN_ISLAND_SIZE {
+                for &i in component {
+                    terrain[i] = Terrain::Water;
+                }
+                sank_island = true;
+            }
+        }
+        if sank_island {
+            continue;
+        }
+
+        // 0-1 BFS from the mainland: stepping onto water costs 1, onto land costs 0
+        let mut cost = vec![usize::MAX; coords.len()];
+        let mut parent = vec![usize::MAX; coords.len()];
+        let mut queue = VecDeque::new();
+        for &i in mainland {
+            cost[i] = 0;
+            queue.push_back(i);
+        }
+        let mut target = None;
+        while let Some(i) = queue.pop_front() {
+            if component_of[i] != usize::MAX && component_of[i] != mainland_id {
+                target = Some(i);
+                break;
+            }
+            let (q, r) = coords[i];
+            for (dq, dr) in HEX_DIRECTIONS {
+                let Some(&n) = index.get(&(q + dq, r + dr)) else { continue };
+                let step = if terrain[n] == Terrain::Water { 1 } else { 0 };
+                if cost[i] + step < cost[n] {
+                    cost[n] = cost[i] + step;
+                    parent[n] = i;
+                    if step == 0 {
+                        queue.push_front(n);
+                    } else {
+                        queue.push_back(n);
+                    }
+                }
+            }
+        }
+
+        let Some(mut i) = target else { return };
+        while parent[i] != usize::MAX {
+            if terrain[i] == Terrain::Water {
+                terrain[i] = Terrain::Grassland;
+            }
+            i = parent[i];
+        }
+    }
+}
+
+/// Connected groups of non-water tiles
+fn land_components(coords: &[(i32, i32)], index: &HashMap<(i32, i32), usize>, terrain: &[Terrain]) -> Vec<Vec<usize>> {
+    let mut seen = vec![false; coords.len()];
+    let mut components = Vec::new();
+    for start in 0..coords.len() {
+        if seen[start] || terrain[start] == Terrain::Water {
+            continue;
+        }
+        seen[start] = true;
+        let mut component = vec![start];
+        let mut queue = VecDeque::from([start]);
+        while let Some(i) = queue.pop_front() {
+            let (q, r) = coords[i];
+            for (dq, dr) in HEX_DIRECTIONS {
+                if let Some(&n) = index.get(&(q + dq, r + dr))
+                    && !seen[n]
+                    && terrain[n] != Terrain::Water
+                {
+                    seen[n] = true;
+                    component.push(n);
+                    queue.push_back(n);
+                }
+            }
+        }
+        components.push(component);
+    }
+    components
+}
+
+/// Seeded 2D value noise on an integer lattice, smoothed with a smoothstep blend
+struct ValueNoise {
+    seed: u64,
+}
+
+impl ValueNoise {
+    fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+
+    fn lattice(&self, x: i64, y: i64) -> f64 {
+        let mut h = self.seed
+            ^ (x as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+            ^ (y as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F);
+        h = (h ^ (h >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        h = (h ^ (h >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        h ^= h >> 31;
+        (h >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Noise in [0, 1)
+    fn sample(&self, x: f64, y: f64) -> f64 {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (smoothstep(x - x0), smoothstep(y - y0));
+        let (ix, iy) = (x0 as i64, y0 as i64);
+
+        let a = self.lattice(ix, iy);
+        let b = self.lattice(ix + 1, iy);
+        let c = self.lattice(ix, iy + 1);
+        let d = self.lattice(ix + 1, iy + 1);
+
+        let top = a + (b - a) * tx;
+        let bottom = c + (d - c) * tx;
+        top + (bottom - top) * ty
+    }
+
+    /// Fractal sum of octaves, normalised back to [0, 1)
+    fn fbm(&self, x: f64, y: f64, octaves: u32) -> f64 {
+        let mut total = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = 1.0;
+        let mut norm = 0.0;
+        for octave in 0..octaves {
+            // Offset each octave so lattice points don't line up at the origin
+            let offset = octave as f64 * 17.31;
+            total += self.sample(x * frequency + offset, y * frequency + offset) * amplitude;
+            norm += amplitude;
+            amplitude *= 0.5;
+            frequency *= 2.0;
+        }
+        total / norm
+    }
+}
+
+fn smoothstep(t: f64) -> f64 {
+    t * t * (3.0 - 2.0 * t)
+}
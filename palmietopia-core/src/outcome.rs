@@ -0,0 +1,105 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{GameError, GameEvent, GameSession, GameStatus};
+
+/// A standing offer to end the game in a draw. It is agreed once every player still in the game
+/// has signed up, and lapses at the end of the turn after the one it was made in, so everyone
+/// gets a full turn to answer.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct DrawOffer {
+    /// Players who offered or accepted, in order
+    pub players: Vec<String>,
+    /// `turn_number` when the offer was made
+    pub turn_number: u32,
+}
+
+impl GameSession {
+    /// Players who have offered or accepted the standing draw offer
+    pub fn draw_offered_by(&self) -> Vec<String> {
+        self.draw_offer.as_ref().map(|o| o.players.clone()).unwrap_or_default()
+    }
+
+    /// Take a player out of the game of their own accord. Their units are removed; their cities
+    /// stay on the map under their name and can be captured like any other. They drop out of
+    /// the standing draw offer, which goes if nobody else is left on it and is agreed if
+    /// everyone still in the game already is. Returns true if the game is now drawn.
+    pub(crate) fn resign(&mut self, player_id: &str) -> bool {
+        self.eliminated_players.push(player_id.to_string());
+        self.units.retain(|u| u.owner_id != player_id);
+        self.reindex();
+        if let Some(offer) = &mut self.draw_offer {
+            offer.players.retain(|id| id != player_id);
+            if offer.players.is_empty() {
+                self.draw_offer = None;
+            }
+        }
+        self.check_victory();
+        self.status == GameStatus::InProgress && self.settle_draw()
+    }
+
+    /// Make a new draw offer. A running game has at least one other player to answer it.
+    pub(crate) fn offer_draw(&mut self, player_id: &str) -> Result<(), GameError> {
+        if self.draw_offer.is_some() {
+            return Err(GameError::DrawAlreadyOffered);
+        }
+        self.draw_offer = Some(DrawOffer {
+            players: vec![player_id.to_string()],
+            turn_number: self.turn_number,
+        });
+        Ok(())
+    }
+
+    /// Sign up to the standing draw offer. Returns true if that made it unanimous and the game
+    /// is now drawn.
+    pub(crate) fn accept_draw(&mut self, player_id: &str) -> Result<bool, GameError> {
+        let offer = self.draw_offer.as_mut().ok_or(GameError::NoDrawOffer)?;
+        if offer.players.iter().any(|id| id == player_id) {
+            return Err(GameError::DrawAlreadyOffered);
+        }
+        offer.players.push(player_id.to_string());
+        Ok(self.settle_draw())
+    }
+
+    fn settle_draw(&mut self) -> bool {
+        let Some(offer) = &self.draw_offer else {
+            return false;
+        };
+        let unanimous = self.players
+            .iter()
+            .filter(|p| !self.eliminated_players.contains(&p.id))
+            .all(|p| offer.players.contains(&p.id));
+        if unanimous {
+            self.status = GameStatus::Draw;
+            self.draw_offer = None;
+        }
+        unanimous
+    }
+
+    /// Drop a draw offer that nobody took up in time. Called as each turn ends.
+    pub(crate) fn lapse_draw_offer(&mut self) {
+        if self.draw_offer.as_ref().is_some_and(|o| self.turn_number > o.turn_number + 1) {
+            self.draw_offer = None;
+        }
+    }
+
+    /// Stop the game with no result. Only the host may.
+    pub(crate) fn abort(&mut self, player_id: &str) -> Result<(), GameError> {
+        if self.host_id != player_id {
+            return Err(GameError::NotHost);
+        }
+        self.status = GameStatus::Finished;
+        self.draw_offer = None;
+        Ok(())
+    }
+
+    /// Stop a game nobody is left to play, with no result. Unlike `abort` it isn't anyone's
+    /// command, so it works whatever has become of the host.
+    pub fn abandon(&mut self) -> Vec<GameEvent> {
+        if self.status != GameStatus::InProgress {
+            return Vec::new();
+        }
+        self.status = GameStatus::Finished;
+        self.draw_offer = None;
+        vec![GameEvent::GameAborted { player_id: None }]
+    }
+}
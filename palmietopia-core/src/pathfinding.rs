@@ -0,0 +1,192 @@
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::{City, GameError, GameSession, GameStatus, HEX_DIRECTIONS};
+
+/// A route for a unit, excluding the tile it starts on
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Path {
+    pub steps: Vec<(i32, i32)>,
+    /// Total movement cost of all steps
+    pub cost: u32,
+}
+
+/// A tile a unit can reach this turn and the movement it costs to get there
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ReachableTile {
+    pub q: i32,
+    pub r: i32,
+    pub cost: u32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PathMoveOutcome {
+    /// The steps actually taken (a prefix of the requested path when movement ran out)
+    pub path: Vec<(i32, i32)>,
+    pub movement_remaining: u32,
+    pub captured_cities: Vec<City>,
+    pub eliminated_players: Vec<String>,
+}
+
+impl GameSession {
+    /// A* search for the cheapest route from a unit to a tile. Water and occupied tiles are
+    /// avoided. The route may be longer than the unit can walk this turn; use `Path::cost`
+    /// against `movement_remaining` (or `move_unit_along_path`) to see how far it gets.
+    pub fn find_path(&self, unit_id: &str, to_q: i32, to_r: i32) -> Option<Path> {
+        let unit = self.unit(unit_id)?;
+        let start = (unit.q, unit.r);
+        let goal = (to_q, to_r);
+        if start == goal {
+            return Some(Path { steps: Vec::new(), cost: 0 });
+        }
+        self.step_cost(to_q, to_r)?;
+
+        // Every passable tile costs at least 1, so hex distance never overestimates
+        let heuristic = |(q, r): (i32, i32)| Self::hex_distance(q, r, to_q, to_r) as u32;
+
+        let mut open = BinaryHeap::new();
+        let mut best_cost: HashMap<(i32, i32), u32> = HashMap::from([(start, 0)]);
+        let mut came_from: HashMap<(i32, i32), (i32, i32)> = HashMap::new();
+        open.push(Reverse((heuristic(start), 0u32, start)));
+
+        while let Some(Reverse((_, cost, current))) = open.pop() {
+            if current == goal {
+                let mut steps = vec![current];
+                let mut tile = current;
+                while let Some(&prev) = came_from.get(&tile) {
+                    if prev == start {
+                        break;
+                    }
+                    steps.push(prev);
+                    tile = prev;
+                }
+                steps.reverse();
+                return Some(Path { steps, cost });
+            }
+            if best_cost.get(&current).is_some_and(|&c| cost > c) {
+                continue;
+            }
+
+            for (dq, dr) in HEX_DIRECTIONS {
+                let next = (current.0 + dq, current.1 + dr);
+                let Some(step) = self.step_cost(next.0, next.1) else { continue };
+                let next_cost = cost + step;
+                if best_cost.get(&next).is_none_or(|&c| next_cost < c) {
+                    best_cost.insert(next, next_cost);
+                    came_from.insert(next, current);
+                    open.push(Reverse((next_cost + heuristic(next), next_cost, next)));
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Every tile the unit can move to with its remaining movement (Dijkstra over `movement_cost`),
+    /// cheapest cost first. The unit's own tile is not included.
+    pub fn reachable_tiles(&self, unit_id: &str) -> Vec<ReachableTile> {
+        let Some(unit) = self.unit(unit_id) else {
+            return Vec::new();
+        };
+        let start = (unit.q, unit.r);
+        let budget = unit.movement_remaining;
+
+        let mut open = BinaryHeap::from([Reverse((0u32, start))]);
+        let mut best_cost: HashMap<(i32, i32), u32> = HashMap::from([(start, 0)]);
+        let mut reached = Vec::new();
+
+        while let Some(Reverse((cost, current))) = open.pop() {
+            if best_cost.get(&current).is_some_and(|&c| cost > c) {
+                continue;
+            }
+            if current != start {
+                reached.push(ReachableTile { q: current.0, r: current.1, cost });
+            }
+            for (dq, dr) in HEX_DIRECTIONS {
+                let next = (current.0 + dq, current.1 + dr);
+                let Some(step) = self.step_cost(next.0, next.1) else { continue };
+                let next_cost = cost + step;
+                if next_cost <= budget && best_cost.get(&next).is_none_or(|&c| next_cost < c) {
+                    best_cost.insert(next, next_cost);
+                    open.push(Reverse((next_cost, next)));
+                }
+            }
+        }
+
+        reached
+    }
+
+    /// Cost of stepping onto a tile, or None if it is off the map, water or occupied
+    fn step_cost(&self, q: i32, r: i32) -> Option<u32> {
+        let cost = self.get_terrain_at(q, r).and_then(Self::movement_cost)?;
+        if self.is_occupied(q, r) {
+            return None;
+        }
+        Some(cost)
+    }
+
+    /// Move a unit along a multi-hex path in one action. The whole path is validated up front
+    /// (adjacent steps, passable and unoccupied tiles); nothing changes if any step is invalid.
+    /// If the unit runs out of movement part-way, it walks the longest affordable prefix.
+    pub fn move_unit_along_path(&mut self, unit_id: &str, path: &[(i32, i32)]) -> Result<PathMoveOutcome, GameError> {
+        let unit = self.unit(unit_id)
+            .ok_or_else(|| GameError::UnitNotFound { unit_id: unit_id.to_string() })?;
+        if path.is_empty() {
+            return Err(GameError::EmptyPath);
+        }
+
+        // Validate every step and find how far the unit's movement reaches
+        let mut movement = unit.movement_remaining;
+        let mut reachable = 0;
+        let mut exhausted = false;
+        let mut first_cost = 0;
+        let mut previous = (unit.q, unit.r);
+        for &(q, r) in path {
+            if Self::hex_distance(previous.0, previous.1, q, r) != 1 {
+                return Err(GameError::NotAdjacent);
+            }
+            let terrain = self.get_terrain_at(q, r).ok_or(GameError::InvalidDestination)?;
+            let cost = Self::movement_cost(terrain).ok_or(GameError::ImpassableTerrain)?;
+            if self.is_occupied(q, r) {
+                return Err(GameError::TileOccupied);
+            }
+            if first_cost == 0 {
+                first_cost = cost;
+            }
+            if !exhausted && movement >= cost {
+                movement -= cost;
+                reachable += 1;
+            } else {
+                exhausted = true;
+            }
+            previous = (q, r);
+        }
+        if reachable == 0 {
+            return Err(GameError::NotEnoughMovement { required: first_cost, remaining: unit.movement_remaining });
+        }
+
+        // Apply step by step on a copy so captures and exploration happen exactly as for
+        // single moves, then commit all at once
+        let mut next = self.clone();
+        let mut outcome = PathMoveOutcome {
+            path: Vec::new(),
+            movement_remaining: 0,
+            captured_cities: Vec::new(),
+            eliminated_players: Vec::new(),
+        };
+        for &(q, r) in &path[..reachable] {
+            let step = next.move_unit(unit_id, q, r)?;
+            outcome.path.push((q, r));
+            outcome.movement_remaining = step.movement_remaining;
+            outcome.captured_cities.extend(step.captured_city);
+            outcome.eliminated_players.extend(step.eliminated_player);
+            if next.status != GameStatus::InProgress {
+                break;
+            }
+        }
+        *self = next;
+
+        Ok(outcome)
+    }
+}
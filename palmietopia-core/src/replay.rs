@@ -0,0 +1,92 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{GameCommand, GameError, GameEvent, GameSession};
+
+/// One accepted command and when the server accepted it
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ReplayEntry {
+    pub at_ms: u64,
+    pub command: GameCommand,
+}
+
+/// A full record of a game: the session as it started (seed, map, capitals and starting units)
+/// plus every accepted command in order. Re-applying the commands rebuilds any later state.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Replay {
+    pub game_id: String,
+    pub seed: u32,
+    pub started_at_ms: u64,
+    pub initial: GameSession,
+    pub entries: Vec<ReplayEntry>,
+}
+
+impl Replay {
+    pub fn new(initial: GameSession, started_at_ms: u64) -> Self {
+        Self {
+            game_id: initial.id.clone(),
+            seed: initial.seed,
+            started_at_ms,
+            initial,
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, at_ms: u64, command: GameCommand) {
+        self.entries.push(ReplayEntry { at_ms, command });
+    }
+
+    /// Number of completed turns: ended by a player, by their clock running out, or by them
+    /// resigning on their own turn. Found by replaying the game and counting `TurnEnded`.
+    pub fn turn_count(&self) -> Result<usize, GameError> {
+        let mut session = self.initial.clone();
+        session.reindex();
+        let mut turns_ended = 0;
+        for entry in &self.entries {
+            if Self::replay_entry(&mut session, entry)? {
+                turns_ended += 1;
+            }
+        }
+        Ok(turns_ended)
+    }
+
+    /// The game after the first `count` entries, for stepping through one action at a time
+    pub fn session_after(&self, count: usize) -> Result<GameSession, GameError> {
+        let mut session = self.initial.clone();
+        session.reindex();
+        for entry in self.entries.iter().take(count) {
+            Self::replay_entry(&mut session, entry)?;
+        }
+        Ok(session)
+    }
+
+    /// The game at the start of the given turn (0 is the initial state). Turns past the end
+    /// give the final state.
+    pub fn session_at_turn(&self, turn: usize) -> Result<GameSession, GameError> {
+        let mut session = self.initial.clone();
+        session.reindex();
+        let mut turns_ended = 0;
+        for entry in &self.entries {
+            if turns_ended == turn {
+                break;
+            }
+            if Self::replay_entry(&mut session, entry)? {
+                turns_ended += 1;
+            }
+        }
+        Ok(session)
+    }
+
+    pub fn final_session(&self) -> Result<GameSession, GameError> {
+        self.session_after(self.entries.len())
+    }
+
+    /// Apply one entry. Returns true if it ended the turn.
+    fn replay_entry(session: &mut GameSession, entry: &ReplayEntry) -> Result<bool, GameError> {
+        let events = session.apply(entry.command.clone())?;
+        let turn_ended = events.iter().any(|e| matches!(e, GameEvent::TurnEnded { .. }));
+        if turn_ended {
+            session.turn_started_at_ms = entry.at_ms;
+        }
+        Ok(turn_ended)
+    }
+}
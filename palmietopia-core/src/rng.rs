@@ -0,0 +1,46 @@
+/// Small deterministic PRNG (SplitMix64) shared by the WASM client and the native server.
+///
+/// Everything that needs randomness in the core draws from a `GameRng` seeded from the
+/// lobby seed, so the same seed always produces the same game on every target.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameRng {
+    state: u64,
+}
+
+impl GameRng {
+    pub fn new(seed: u32) -> Self {
+        Self { state: seed as u64 }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    /// Uniform float in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniform integer in [0, bound). Returns 0 when bound is 0.
+    pub fn below(&mut self, bound: u32) -> u32 {
+        if bound == 0 {
+            return 0;
+        }
+        ((self.next_u32() as u64 * bound as u64) >> 32) as u32
+    }
+}
+
+/// Pick a fresh seed from the OS entropy source (used when a lobby doesn't specify one)
+pub fn random_seed() -> u32 {
+    let mut buf = [0u8; 4];
+    getrandom::getrandom(&mut buf).unwrap();
+    u32::from_le_bytes(buf)
+}
@@ -0,0 +1,81 @@
+use std::collections::HashSet;
+
+use crate::{GameSession, Terrain};
+
+/// Extra vision range for a unit standing on a mountain
+pub const MOUNTAIN_VISION_BONUS: i32 = 1;
+/// Extra range it costs to look past each forest tile (ignored when looking down from a mountain)
+pub const FOREST_SIGHT_PENALTY: i32 = 1;
+
+/// The hexes on a straight line from `from` to `to`, both ends included
+pub fn hex_line(from: (i32, i32), to: (i32, i32)) -> Vec<(i32, i32)> {
+    let n = GameSession::hex_distance(from.0, from.1, to.0, to.1);
+    if n == 0 {
+        return vec![from];
+    }
+    // Nudge the start slightly so lines running exactly along hex edges pick a consistent side
+    let (aq, ar) = (from.0 as f64 + 1e-6, from.1 as f64 + 2e-6);
+    let (bq, br) = (to.0 as f64, to.1 as f64);
+    (0..=n)
+        .map(|i| {
+            let t = i as f64 / n as f64;
+            cube_round(aq + (bq - aq) * t, ar + (br - ar) * t)
+        })
+        .collect()
+}
+
+/// Round fractional axial coordinates to the nearest hex
+fn cube_round(q: f64, r: f64) -> (i32, i32) {
+    let s = -q - r;
+    let (mut rq, mut rr, rs) = (q.round(), r.round(), s.round());
+    let (dq, dr, ds) = ((rq - q).abs(), (rr - r).abs(), (rs - s).abs());
+    if dq > dr && dq > ds {
+        rq = -rr - rs;
+    } else if dr > ds {
+        rr = -rq - rs;
+    }
+    (rq as i32, rr as i32)
+}
+
+impl GameSession {
+    /// Vision range of a unit or city at (q, r), including the mountain bonus
+    pub fn vision_range_at(&self, base_range: i32, q: i32, r: i32) -> i32 {
+        if self.get_terrain_at(q, r) == Some(Terrain::Mountain) {
+            base_range + MOUNTAIN_VISION_BONUS
+        } else {
+            base_range
+        }
+    }
+
+    /// How much range it takes to see `to` from `from`, or None if a mountain is in the way.
+    /// Mountains between the two hexes block sight; forests between them cost
+    /// `FOREST_SIGHT_PENALTY` each unless the viewer stands on a mountain. The end tiles
+    /// themselves never block, so a mountain or forest can always be seen up to its edge.
+    pub fn sight_cost(&self, from: (i32, i32), to: (i32, i32)) -> Option<i32> {
+        let elevated = self.get_terrain_at(from.0, from.1) == Some(Terrain::Mountain);
+        let line = hex_line(from, to);
+        let mut cost = GameSession::hex_distance(from.0, from.1, to.0, to.1);
+        for &(q, r) in line.iter().skip(1).take(line.len().saturating_sub(2)) {
+            match self.get_terrain_at(q, r) {
+                Some(Terrain::Mountain) => return None,
+                Some(Terrain::Forest) if !elevated => cost += FOREST_SIGHT_PENALTY,
+                _ => {}
+            }
+        }
+        Some(cost)
+    }
+
+    pub fn has_line_of_sight(&self, from: (i32, i32), to: (i32, i32), range: i32) -> bool {
+        self.sight_cost(from, to).is_some_and(|cost| cost <= range)
+    }
+
+    /// Tiles that can be seen from (q, r) with the given base vision range
+    pub(crate) fn tiles_in_sight(&self, q: i32, r: i32, base_range: i32) -> HashSet<(i32, i32)> {
+        let range = self.vision_range_at(base_range, q, r);
+        Self::tiles_in_range(q, r, range)
+            .into_iter()
+            .filter(|&(tq, tr)| self.map.contains(tq, tr))
+            .filter(|&tile| self.has_line_of_sight((q, r), tile, range))
+            .collect()
+    }
+}
@@ -0,0 +1,315 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::{GameMap, GameSession, Terrain, HEX_DIRECTIONS};
+
+/// How good a starting position is. Higher `total` is better.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct StartScore {
+    pub q: i32,
+    pub r: i32,
+    /// Passable tiles within `area_radius` that can be walked to without leaving the area
+    pub reachable_land: u32,
+    /// Weighted terrain mix of the tiles around the capital (see `terrain_value`)
+    pub terrain_score: f64,
+    /// Hex distance to the closest rival capital
+    pub rival_distance: i32,
+    pub total: f64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct StartConfig {
+    /// Largest accepted (best - worst) / best spread of start scores between players
+    pub tolerance: f64,
+    /// Radius around each capital that counts as its home area
+    pub area_radius: i32,
+}
+
+impl Default for StartConfig {
+    fn default() -> Self {
+        Self {
+            tolerance: 0.15,
+            area_radius: 3,
+        }
+    }
+}
+
+/// Largest home area a capital can be scored on
+const MAX_AREA_RADIUS: i32 = 5;
+
+impl StartConfig {
+    /// Whether capitals can be placed with these settings (e.g. from a lobby's host)
+    pub fn is_valid(&self) -> bool {
+        self.tolerance.is_finite() && self.tolerance >= 0.0 && (1..=MAX_AREA_RADIUS).contains(&self.area_radius)
+    }
+}
+
+/// Ring distances (as a fraction of map radius) tried when laying capitals out around the map
+const RING_FACTORS: [f64; 4] = [0.7, 0.6, 0.8, 0.5];
+/// Rotations of the whole layout tried per ring
+const ROTATIONS: u32 = 6;
+/// How far the weakest capital may be moved in one rebalancing step
+const NUDGE_RADIUS: i32 = 2;
+
+/// Value of a tile next to a capital: open land is best, mountains and water are barely useful
+fn terrain_value(terrain: Terrain) -> f64 {
+    match terrain {
+        Terrain::Grassland => 1.0,
+        Terrain::Forest => 0.8,
+        Terrain::Desert => 0.5,
+        Terrain::Mountain => 0.3,
+        Terrain::Water => 0.1,
+    }
+}
+
+fn can_found_city(terrain: Terrain) -> bool {
+    terrain != Terrain::Water && terrain != Terrain::Mountain
+}
+
+type TerrainLookup = HashMap<(i32, i32), Terrain>;
+
+/// Scores layouts, caching the terrain part of each tile since hill-climbing revisits them a lot
+struct Scorer<'a> {
+    terrain: TerrainLookup,
+    map_radius: u32,
+    config: &'a StartConfig,
+    home_values: HashMap<(i32, i32), (u32, f64)>,
+}
+
+impl<'a> Scorer<'a> {
+    fn new(map: &GameMap, config: &'a StartConfig) -> Self {
+        Self {
+            terrain: map.tiles.iter().map(|t| ((t.q, t.r), t.terrain)).collect(),
+            map_radius: map.radius,
+            config,
+            home_values: HashMap::new(),
+        }
+    }
+
+    /// (reachable_land, terrain_score) around a tile
+    fn home_value(&mut self, q: i32, r: i32) -> (u32, f64) {
+        if let Some(&value) = self.home_values.get(&(q, r)) {
+            return value;
+        }
+        let reachable_land = reachable_land(&self.terrain, q, r, self.config.area_radius);
+        let terrain_score: f64 = GameSession::tiles_in_range(q, r, self.config.area_radius - 1)
+            .into_iter()
+            .filter(|&tile| tile != (q, r))
+            .filter_map(|tile| self.terrain.get(&tile).copied())
+            .map(terrain_value)
+            .sum();
+        self.home_values.insert((q, r), (reachable_land, terrain_score));
+        (reachable_land, terrain_score)
+    }
+
+    fn score(&mut self, positions: &[(i32, i32)]) -> Vec<StartScore> {
+        let map_radius = self.map_radius as i32;
+        positions
+            .iter()
+            .enumerate()
+            .map(|(i, &(q, r))| {
+                let (reachable_land, terrain_score) = self.home_value(q, r);
+                let rival_distance = positions
+                    .iter()
+                    .enumerate()
+                    .filter(|(j, _)| *j != i)
+                    .map(|(_, &(oq, or))| GameSession::hex_distance(q, r, oq, or))
+                    .min()
+                    .unwrap_or(map_radius * 2);
+                // Being further from rivals than the map radius gains nothing
+                let rival_room = rival_distance.min(map_radius) as f64;
+                let total = reachable_land as f64 + terrain_score * 2.0 + rival_room * 1.5;
+
+                StartScore {
+                    q,
+                    r,
+                    reachable_land,
+                    terrain_score,
+                    rival_distance,
+                    total,
+                }
+            })
+            .collect()
+    }
+
+    fn spread(&mut self, positions: &[(i32, i32)]) -> f64 {
+        start_spread(&self.score(positions))
+    }
+}
+
+/// Score every capital of a layout against the others
+pub fn evaluate_starts(map: &GameMap, positions: &[(i32, i32)], config: &StartConfig) -> Vec<StartScore> {
+    Scorer::new(map, config).score(positions)
+}
+
+/// Relative gap between the best and worst start: 0.0 means perfectly even
+pub fn start_spread(scores: &[StartScore]) -> f64 {
+    let best = scores.iter().map(|s| s.total).fold(f64::MIN, f64::max);
+    let worst = scores.iter().map(|s| s.total).fold(f64::MAX, f64::min);
+    if scores.is_empty() || best <= 0.0 {
+        return 0.0;
+    }
+    (best - worst) / best
+}
+
+/// Place capitals spread around the map so that the start spread stays within the tolerance.
+/// Several rotated/scaled layouts are tried and each is locally rebalanced by nudging the weakest
+/// capital; the first layout within tolerance wins, otherwise the most even one found.
+pub fn balanced_starting_positions(map: &GameMap, player_count: usize, config: &StartConfig) -> Vec<(i32, i32)> {
+    let mut scorer = Scorer::new(map, config);
+    let candidates: Vec<(i32, i32)> = map
+        .tiles
+        .iter()
+        .filter(|t| can_found_city(t.terrain))
+        .map(|t| (t.q, t.r))
+        .collect();
+
+    let mut best: Option<(f64, Vec<(i32, i32)>)> = None;
+    for ring in RING_FACTORS {
+        for rotation in 0..ROTATIONS {
+            let offset = rotation as f64 / ROTATIONS as f64 / player_count.max(1) as f64;
+            let layout = layout_by_angle(map, &candidates, player_count, ring, offset);
+            let layout = rebalance(&mut scorer, map, &candidates, layout);
+            let spread = scorer.spread(&layout);
+
+            if spread <= config.tolerance && layout.len() == player_count {
+                return layout;
+            }
+            if best.as_ref().is_none_or(|(s, _)| spread < *s) {
+                best = Some((spread, layout));
+            }
+        }
+    }
+    best.map(|(_, layout)| layout).unwrap_or_default()
+}
+
+/// Preferred spacing between capitals for a map. `layout_by_angle` lowers it one step at a time,
+/// down to 1, when no free tile is that far from the capitals already placed.
+fn min_spacing(map: &GameMap) -> i32 {
+    (map.radius as i32 / 2).max(3)
+}
+
+/// Put each player in their own angular sector at `ring` times the map radius
+fn layout_by_angle(
+    map: &GameMap,
+    candidates: &[(i32, i32)],
+    player_count: usize,
+    ring: f64,
+    offset: f64,
+) -> Vec<(i32, i32)> {
+    let radius = map.radius as f64;
+    let mut positions: Vec<(i32, i32)> = Vec::new();
+
+    for i in 0..player_count {
+        let angle = 2.0 * std::f64::consts::PI * (i as f64 / player_count as f64 + offset);
+        let target_q = (angle.cos() * radius * ring).round() as i32;
+        let target_r = (angle.sin() * radius * ring).round() as i32;
+
+        // Prefer well-spaced tiles, but never give two players the same tile
+        let mut spacing = min_spacing(map);
+        loop {
+            let best_tile = candidates
+                .iter()
+                .filter(|&&(q, r)| {
+                    positions.iter().all(|&(pq, pr)| GameSession::hex_distance(q, r, pq, pr) >= spacing)
+                })
+                .min_by_key(|&&(q, r)| GameSession::hex_distance(q, r, target_q, target_r));
+            if let Some(&tile) = best_tile {
+                positions.push(tile);
+                break;
+            }
+            if spacing <= 1 {
+                break;
+            }
+            spacing -= 1;
+        }
+    }
+
+    positions
+}
+
+/// Hill-climb: repeatedly move the weakest capital to a nearby tile that lowers the spread
+fn rebalance(
+    scorer: &mut Scorer,
+    map: &GameMap,
+    candidates: &[(i32, i32)],
+    mut layout: Vec<(i32, i32)>,
+) -> Vec<(i32, i32)> {
+    let tolerance = scorer.config.tolerance;
+    let spacing = layout
+        .iter()
+        .enumerate()
+        .flat_map(|(i, a)| layout[i + 1..].iter().map(move |b| GameSession::hex_distance(a.0, a.1, b.0, b.1)))
+        .min()
+        .unwrap_or(0)
+        .min(min_spacing(map));
+    let candidate_set: HashSet<(i32, i32)> = candidates.iter().copied().collect();
+
+    let mut spread = scorer.spread(&layout);
+    for _ in 0..layout.len() * 4 {
+        if spread <= tolerance {
+            break;
+        }
+        let scores = scorer.score(&layout);
+        let weakest = scores
+            .iter()
+            .enumerate()
+            .min_by(|a, b| a.1.total.total_cmp(&b.1.total))
+            .map(|(i, _)| i)
+            .unwrap();
+        let (wq, wr) = layout[weakest];
+
+        let mut improved = None;
+        for tile in GameSession::tiles_in_range(wq, wr, NUDGE_RADIUS) {
+            if !candidate_set.contains(&tile) || tile == (wq, wr) {
+                continue;
+            }
+            let spaced = layout
+                .iter()
+                .enumerate()
+                .all(|(j, &(pq, pr))| j == weakest || GameSession::hex_distance(tile.0, tile.1, pq, pr) >= spacing);
+            if !spaced {
+                continue;
+            }
+            let mut trial = layout.clone();
+            trial[weakest] = tile;
+            let trial_spread = scorer.spread(&trial);
+            if trial_spread < improved.map_or(spread, |(s, _)| s) {
+                improved = Some((trial_spread, tile));
+            }
+        }
+
+        match improved {
+            Some((new_spread, tile)) => {
+                layout[weakest] = tile;
+                spread = new_spread;
+            }
+            None => break,
+        }
+    }
+    layout
+}
+
+/// Passable tiles reachable from (q, r) without leaving the hex area of the given radius
+fn reachable_land(terrain: &TerrainLookup, q: i32, r: i32, area_radius: i32) -> u32 {
+    let passable = |tq: i32, tr: i32| {
+        GameSession::hex_distance(q, r, tq, tr) <= area_radius
+            && terrain.get(&(tq, tr)).copied().and_then(GameSession::movement_cost).is_some()
+    };
+    if !passable(q, r) {
+        return 0;
+    }
+    let mut seen = HashSet::from([(q, r)]);
+    let mut queue = VecDeque::from([(q, r)]);
+    while let Some((cq, cr)) = queue.pop_front() {
+        for (dq, dr) in HEX_DIRECTIONS {
+            let next = (cq + dq, cr + dr);
+            if !seen.contains(&next) && passable(next.0, next.1) {
+                seen.insert(next);
+                queue.push_back(next);
+            }
+        }
+    }
+    // The capital's own tile doesn't count
+    seen.len() as u32 - 1
+}
@@ -0,0 +1,141 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use crate::{
+    first_unit_id, City, DrawOffer, GameCommand, GameError, GameEvent, GameMap, GameSession, GameStatus, Intel,
+    Player, SeenCity, SeenUnit, Unit,
+};
+
+/// Everything one player is allowed to know about a game: their own units, cities and gold,
+/// enemy units and cities they can currently see, what they remember of enemies out of sight,
+/// and the public state (map, players, turn order, clocks).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PlayerView {
+    pub id: String,
+    pub seed: u32,
+    /// The player this view belongs to
+    pub player_id: String,
+    pub map: GameMap,
+    pub players: Vec<Player>,
+    pub units: Vec<Unit>,
+    pub cities: Vec<City>,
+    /// Enemy cities and units out of sight, as last seen
+    pub last_seen_cities: Vec<SeenCity>,
+    pub last_seen_units: Vec<SeenUnit>,
+    pub current_turn: usize,
+    pub turn_number: u32,
+    pub status: GameStatus,
+    pub eliminated_players: Vec<String>,
+    /// Players who have offered or accepted a draw that is still open
+    pub draw_offered_by: Vec<String>,
+    pub host_id: String,
+    pub player_times_ms: Vec<u64>,
+    pub gold: u64,
+    pub explored_tiles: HashSet<(i32, i32)>,
+    pub visible_tiles: HashSet<(i32, i32)>,
+    pub turn_started_at_ms: u64,
+    pub base_time_ms: u64,
+    pub increment_ms: u64,
+}
+
+impl GameSession {
+    pub fn gold_of(&self, player_id: &str) -> u64 {
+        self.players
+            .iter()
+            .position(|p| p.id == player_id)
+            .map_or(0, |idx| self.player_gold[idx])
+    }
+
+    pub fn view_for(&self, player_id: &str) -> PlayerView {
+        PlayerView {
+            id: self.id.clone(),
+            seed: self.seed,
+            player_id: player_id.to_string(),
+            map: self.map.clone(),
+            players: self.players.clone(),
+            units: self.visible_units(player_id),
+            cities: self.known_cities(player_id),
+            last_seen_cities: self.last_seen_cities(player_id),
+            last_seen_units: self.last_seen_units(player_id),
+            current_turn: self.current_turn,
+            turn_number: self.turn_number,
+            status: self.status.clone(),
+            eliminated_players: self.eliminated_players.clone(),
+            draw_offered_by: self.draw_offered_by(),
+            host_id: self.host_id.clone(),
+            player_times_ms: self.player_times_ms.clone(),
+            gold: self.gold_of(player_id),
+            explored_tiles: self.get_explored_tiles(player_id),
+            visible_tiles: self.get_visible_tiles(player_id),
+            turn_started_at_ms: self.turn_started_at_ms,
+            base_time_ms: self.base_time_ms,
+            increment_ms: self.increment_ms,
+        }
+    }
+}
+
+impl PlayerView {
+    /// The game as far as this player knows it: their own units and cities, the enemies in sight
+    /// and what they remember of the rest. Movement, pathfinding and attack rules run on it agree
+    /// with the server for all of those, so clients can use them without the full game. The one
+    /// thing it can't reproduce is the server's unit id counter, which would tell how many units
+    /// everyone has bought: units it creates get ids the server won't use.
+    pub fn known_session(&self) -> GameSession {
+        let me = self.players.iter().position(|p| p.id == self.player_id);
+        let players = 0..self.players.len();
+        let mut intel = vec![Intel::default(); self.players.len()];
+        if let Some(mine) = me.map(|i| &mut intel[i]) {
+            mine.cities = self.last_seen_cities.iter().map(|s| (s.city.id.clone(), s.clone())).collect();
+            mine.units = self.last_seen_units.iter().map(|s| (s.unit.id.clone(), s.clone())).collect();
+        }
+        // When the offer was made isn't shown, so it is taken to be this turn
+        let draw_offer = (!self.draw_offered_by.is_empty()).then(|| DrawOffer {
+            players: self.draw_offered_by.clone(),
+            turn_number: self.turn_number,
+        });
+        let mut session = GameSession {
+            id: self.id.clone(),
+            seed: self.seed,
+            map: self.map.clone(),
+            players: self.players.clone(),
+            host_id: self.host_id.clone(),
+            cities: self.cities.clone(),
+            units: self.units.clone(),
+            current_turn: self.current_turn,
+            status: self.status.clone(),
+            eliminated_players: self.eliminated_players.clone(),
+            player_times_ms: self.player_times_ms.clone(),
+            player_gold: players.clone().map(|i| if Some(i) == me { self.gold } else { 0 }).collect(),
+            explored_tiles: players
+                .map(|i| if Some(i) == me { self.explored_tiles.clone() } else { HashSet::new() })
+                .collect(),
+            intel,
+            turn_number: self.turn_number,
+            draw_offer,
+            turn_started_at_ms: self.turn_started_at_ms,
+            base_time_ms: self.base_time_ms,
+            increment_ms: self.increment_ms,
+            next_unit_id: first_unit_id(),
+            unit_index: Default::default(),
+        };
+        session.reindex();
+        session
+    }
+    /// Apply one of this player's commands to their view, the way the server would apply it to
+    /// the game, as an optimistic update. Enemies the player can't see aren't taken into account,
+    /// so the server's answer may still differ; its next delta corrects the view.
+    ///
+    /// Purchases are checked but not predicted: the new unit's id is only known to the server,
+    /// so a valid `BuyUnit` leaves the view as it is and returns no events, and the unit arrives
+    /// with the server's delta.
+    pub fn apply(&mut self, command: GameCommand) -> Result<Vec<GameEvent>, GameError> {
+        let mut session = self.known_session();
+        let buying = matches!(command, GameCommand::BuyUnit { .. });
+        let events = session.apply(command)?;
+        if buying {
+            return Ok(Vec::new());
+        }
+        *self = session.view_for(&self.player_id);
+        Ok(events)
+    }
+}
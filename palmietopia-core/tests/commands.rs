@@ -0,0 +1,92 @@
+mod common;
+
+use palmietopia_core::{GameCommand, GameError, GameEvent, GameSession, GameStatus, MapSize};
+
+fn two_player_game() -> GameSession {
+    game_with(&["p1", "p2"])
+}
+
+fn game_with(ids: &[&str]) -> GameSession {
+    common::game(ids, MapSize::Small, 7)
+}
+
+#[test]
+fn commands_out_of_turn_are_rejected_without_changes() {
+    let mut game = two_player_game();
+    let before = serde_json::to_string(&game.units).unwrap();
+
+    let result = game.apply(GameCommand::EndTurn { player_id: "p2".to_string(), time_used_ms: 0 });
+    assert_eq!(result, Err(GameError::NotYourTurn));
+
+    let p2_unit = game.units.iter().find(|u| u.owner_id == "p2").unwrap().id.clone();
+    let result = game.apply(GameCommand::FortifyUnit { player_id: "p1".to_string(), unit_id: p2_unit });
+    assert_eq!(result, Err(GameError::NotYourUnit));
+
+    assert_eq!(game.current_turn, 0);
+    assert_eq!(serde_json::to_string(&game.units).unwrap(), before);
+}
+
+#[test]
+fn applied_commands_report_their_events() {
+    let mut game = two_player_game();
+    let unit_id = game.units.iter().find(|u| u.owner_id == "p1").unwrap().id.clone();
+
+    let events = game
+        .apply(GameCommand::FortifyUnit { player_id: "p1".to_string(), unit_id: unit_id.clone() })
+        .unwrap();
+    let hp = game.units.iter().find(|u| u.id == unit_id).unwrap().hp;
+    assert_eq!(events, vec![GameEvent::UnitFortified { unit_id, new_hp: hp }]);
+
+    let events = game
+        .apply(GameCommand::EndTurn { player_id: "p1".to_string(), time_used_ms: 1_000 })
+        .unwrap();
+    assert_eq!(events, vec![GameEvent::TurnEnded { current_turn: 1 }]);
+    assert_eq!(game.current_turn, 1);
+}
+
+#[test]
+fn resigning_on_your_turn_passes_it_on() {
+    let mut game = game_with(&["p1", "p2", "p3"]);
+
+    let events = game.apply(GameCommand::Resign { player_id: "p1".to_string() }).unwrap();
+    assert_eq!(
+        events,
+        vec![
+            GameEvent::PlayerResigned { player_id: "p1".to_string() },
+            GameEvent::TurnEnded { current_turn: 1 },
+        ]
+    );
+    assert!(game.units.iter().all(|u| u.owner_id != "p1"));
+    assert!(game.cities.iter().any(|c| c.owner_id == "p1"), "their cities are left behind");
+    assert_eq!(game.status, GameStatus::InProgress);
+
+    let result = game.apply(GameCommand::Resign { player_id: "p1".to_string() });
+    assert_eq!(result, Err(GameError::PlayerEliminated));
+}
+
+#[test]
+fn resigning_out_of_turn_can_end_the_game() {
+    let mut game = two_player_game();
+    let events = game.apply(GameCommand::Resign { player_id: "p2".to_string() }).unwrap();
+    assert_eq!(
+        events,
+        vec![
+            GameEvent::PlayerResigned { player_id: "p2".to_string() },
+            GameEvent::GameWon { winner_id: "p1".to_string() },
+        ]
+    );
+    assert_eq!(game.status, GameStatus::Victory { winner_id: "p1".to_string() });
+}
+
+#[test]
+fn sessions_saved_before_seeds_still_load() {
+    let mut json = serde_json::to_value(two_player_game()).unwrap();
+    let fields = json.as_object_mut().unwrap();
+    fields.remove("seed");
+    fields.remove("next_unit_id");
+
+    let mut game: GameSession = serde_json::from_value(json).unwrap();
+    assert_eq!((game.seed, game.next_unit_id), (0, 1));
+    game.reindex();
+    assert!(game.apply(GameCommand::EndTurn { player_id: "p1".to_string(), time_used_ms: 0 }).is_ok());
+}
@@ -0,0 +1,22 @@
+//! Games for the tests and benchmarks to start from. Not every test file uses every helper.
+#![allow(dead_code)]
+
+use palmietopia_core::{GameSession, Lobby, MapSize, Player, PlayerColor};
+
+/// A lobby with `players` in seat order, the first hosting, each named after their id and
+/// coloured by their seat
+pub fn lobby(players: &[&str], size: MapSize, seed: u32) -> Lobby {
+    let player = |i: usize| Player {
+        id: players[i].to_string(),
+        name: players[i].to_string(),
+        color: PlayerColor::from_index(i),
+    };
+    let mut lobby = Lobby::with_seed("game".to_string(), player(0), size, seed);
+    lobby.players.extend((1..players.len()).map(player));
+    lobby
+}
+
+/// A game just started from `lobby`
+pub fn game(players: &[&str], size: MapSize, seed: u32) -> GameSession {
+    GameSession::from_lobby(&lobby(players, size, seed))
+}
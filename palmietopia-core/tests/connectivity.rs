@@ -0,0 +1,87 @@
+mod common;
+
+use palmietopia_core::{
+    balanced_starting_positions, evaluate_starts, start_spread, GameMap, GameSession, Lobby, MapSize,
+    StartConfig, Terrain, Tile,
+};
+
+const SEEDS_PER_SIZE: u32 = 1000;
+
+fn lobby(map_size: MapSize, players: usize, seed: u32) -> Lobby {
+    common::lobby(&["p0", "p1", "p2", "p3", "p4", "p5"][..players], map_size, seed)
+}
+
+#[test]
+fn capitals_are_connected_over_land_for_every_map_size() {
+    for map_size in [MapSize::Tiny, MapSize::Small, MapSize::Medium, MapSize::Large, MapSize::Huge] {
+        for seed in 0..SEEDS_PER_SIZE {
+            let players = 2 + (seed as usize % 4);
+            let game = GameSession::from_lobby(&lobby(map_size, players, seed));
+            let capitals: Vec<(i32, i32)> = game.cities.iter().map(|c| (c.q, c.r)).collect();
+
+            assert_eq!(capitals.len(), players, "{:?} seed {}: missing capitals", map_size, seed);
+            assert!(
+                game.map.all_connected(&capitals),
+                "{:?} seed {}: capitals {:?} are not connected over land",
+                map_size, seed, capitals
+            );
+
+            let land: Vec<(i32, i32)> = game.map.tiles.iter()
+                .filter(|t| GameSession::movement_cost(t.terrain).is_some())
+                .map(|t| (t.q, t.r))
+                .collect();
+            assert!(game.map.all_connected(&land), "{:?} seed {}: land is split", map_size, seed);
+        }
+    }
+}
+
+#[test]
+fn same_seed_builds_the_same_game() {
+    let a = GameSession::from_lobby(&lobby(MapSize::Large, 4, 1234));
+    let b = GameSession::from_lobby(&lobby(MapSize::Large, 4, 1234));
+    let json = |g: &GameSession| {
+        serde_json::to_string(&(&g.map, &g.cities, &g.units)).unwrap()
+    };
+    assert_eq!(json(&a), json(&b));
+}
+
+#[test]
+fn uneven_first_maps_are_rerolled() {
+    // The first map for this seed can't seat five players evenly
+    let (map_size, players, seed) = (MapSize::Small, 5, 7);
+    let config = StartConfig::default();
+    let first = GameMap::generate(map_size.radius(), seed);
+    let first_positions = balanced_starting_positions(&first, players, &config);
+    assert!(start_spread(&evaluate_starts(&first, &first_positions, &config)) > config.tolerance);
+
+    let game = GameSession::from_lobby(&lobby(map_size, players, seed));
+    let capitals: Vec<(i32, i32)> = game.cities.iter().map(|c| (c.q, c.r)).collect();
+    let terrain = |map: &GameMap| map.tiles.iter().map(|t| t.terrain).collect::<Vec<_>>();
+    assert_ne!(terrain(&game.map), terrain(&first), "the first map should have been rejected");
+    assert!(game.map.all_connected(&capitals));
+    assert!(start_spread(&evaluate_starts(&game.map, &capitals, &config)) <= config.tolerance);
+}
+
+#[test]
+fn land_is_carved_between_cut_off_positions() {
+    let tiles = (-3..=3)
+        .flat_map(|q: i32| (-3..=3).map(move |r: i32| (q, r)))
+        .filter(|&(q, r)| (q + r).abs() <= 3)
+        .map(|(q, r)| {
+            let terrain = if (q, r) == (-3, 0) || (q, r) == (3, 0) || (q, r) == (0, 3) {
+                Terrain::Grassland
+            } else {
+                Terrain::Water
+            };
+            Tile { q, r, terrain }
+        })
+        .collect();
+    let mut map = GameMap::new(tiles, 3);
+    let positions = [(-3, 0), (3, 0), (0, 3)];
+    assert!(!map.all_connected(&positions));
+
+    map.join_over_land(&positions);
+    assert!(map.all_connected(&positions));
+    assert_eq!(map.terrain_at(0, 0), Some(Terrain::Grassland));
+    assert_eq!(map.terrain_at(0, -3), Some(Terrain::Water), "tiles off the lines are left alone");
+}
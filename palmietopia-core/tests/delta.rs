@@ -0,0 +1,85 @@
+mod common;
+
+use palmietopia_core::{GameCommand, GameSession, MapSize, PlayerView, SequencedMessage, ServerMessage};
+
+fn two_player_game() -> GameSession {
+    common::game(&["p1", "p2"], MapSize::Huge, 11)
+}
+
+/// The parts of a view that deltas carry, in an order-independent form
+fn state(view: &PlayerView) -> String {
+    let mut units = view.units.clone();
+    units.sort_by(|a, b| a.id.cmp(&b.id));
+    let mut cities = view.cities.clone();
+    cities.sort_by(|a, b| a.id.cmp(&b.id));
+    let mut last_seen_units = view.last_seen_units.clone();
+    last_seen_units.sort_by(|a, b| a.unit.id.cmp(&b.unit.id));
+    let mut explored: Vec<_> = view.explored_tiles.iter().collect();
+    explored.sort();
+    let mut visible: Vec<_> = view.visible_tiles.iter().collect();
+    visible.sort();
+    serde_json::to_string(&(
+        units,
+        cities,
+        last_seen_units,
+        explored,
+        visible,
+        view.gold,
+        view.current_turn,
+        view.turn_number,
+        &view.player_times_ms,
+        &view.status,
+    ))
+    .unwrap()
+}
+
+#[test]
+fn deltas_keep_client_views_in_step() {
+    let mut game = two_player_game();
+    let mut client_views: Vec<PlayerView> = game.players.iter().map(|p| game.view_for(&p.id)).collect();
+
+    let mut check = |game: &GameSession| {
+        for view in &mut client_views {
+            let latest = game.view_for(&view.player_id);
+            let delta = view.diff(&latest);
+            let full = serde_json::to_string(&latest).unwrap().len();
+            assert!(serde_json::to_string(&delta).unwrap().len() < full / 10, "deltas are a fraction of the view");
+
+            // Deltas go over the wire as JSON
+            let delta = serde_json::from_str(&serde_json::to_string(&delta).unwrap()).unwrap();
+            view.apply_delta(&delta);
+            assert_eq!(state(view), state(&latest));
+            assert!(view.diff(&latest).is_empty());
+        }
+    };
+
+    for _ in 0..6 {
+        let player_id = game.players[game.current_turn].id.clone();
+        let unit_ids: Vec<String> = game.units.iter().filter(|u| u.owner_id == player_id).map(|u| u.id.clone()).collect();
+        for unit_id in unit_ids {
+            if let Some(tile) = game.reachable_tiles(&unit_id).first() {
+                let (to_q, to_r) = (tile.q, tile.r);
+                game.apply(GameCommand::MoveUnit { player_id: player_id.clone(), unit_id, to_q, to_r }).unwrap();
+                check(&game);
+            }
+        }
+        game.apply(GameCommand::EndTurn { player_id, time_used_ms: 1_000 }).unwrap();
+        check(&game);
+    }
+}
+
+#[test]
+fn sequenced_messages_are_flat() {
+    let msg = SequencedMessage {
+        seq: 7,
+        message: ServerMessage::TurnChanged { current_turn: 1, player_times_ms: vec![1_000, 2_000] },
+    };
+    let json = serde_json::to_value(&msg).unwrap();
+    assert_eq!(json["seq"], 7);
+    assert_eq!(json["type"], "TurnChanged");
+    assert_eq!(json["current_turn"], 1);
+
+    let back: SequencedMessage = serde_json::from_value(json).unwrap();
+    assert_eq!(back.seq, 7);
+    assert!(matches!(back.message, ServerMessage::TurnChanged { current_turn: 1, .. }));
+}
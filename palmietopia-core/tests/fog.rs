@@ -0,0 +1,95 @@
+mod common;
+
+use palmietopia_core::{GameSession, MapSize};
+
+fn three_player_game() -> GameSession {
+    common::game(&["p1", "p2", "p3"], MapSize::Large, 3)
+}
+
+#[test]
+fn player_view_only_holds_what_the_player_sees() {
+    let game = three_player_game();
+    for (idx, player) in game.players.iter().enumerate() {
+        let view = game.view_for(&player.id);
+        let visible = game.get_visible_tiles(&player.id);
+        let explored = game.get_explored_tiles(&player.id);
+
+        for unit in &view.units {
+            assert!(unit.owner_id == player.id || visible.contains(&(unit.q, unit.r)));
+        }
+        for city in &view.cities {
+            assert!(city.owner_id == player.id || explored.contains(&(city.q, city.r)));
+        }
+        let own_units = game.units.iter().filter(|u| u.owner_id == player.id).count();
+        assert_eq!(view.units.iter().filter(|u| u.owner_id == player.id).count(), own_units);
+
+        assert_eq!(view.explored_tiles, explored);
+        assert_eq!(view.gold, game.player_gold[idx]);
+
+        // Nothing from another player's private state makes it into the serialized view
+        let json = serde_json::to_value(&view).unwrap();
+        assert!(json.get("player_gold").is_none());
+        assert!(json.get("next_unit_id").is_none());
+    }
+}
+
+#[test]
+fn enemies_out_of_sight_are_remembered_where_last_seen() {
+    let mut game = three_player_game();
+    let capital = game.cities.iter().find(|c| c.owner_id == "p1").map(|c| (c.q, c.r)).unwrap();
+    let far = |&(q, r): &(i32, i32)| GameSession::hex_distance(q, r, capital.0, capital.1) >= 6;
+
+    // Send p1's conscript away from its capital and put an enemy right next to it
+    let (scout_q, scout_r) = game.map.tiles.iter()
+        .map(|t| (t.q, t.r))
+        .find(|t| far(t) && game.map.contains(t.0 + 1, t.1) && far(&(t.0 + 1, t.1)))
+        .unwrap();
+    let scout_idx = game.units.iter().position(|u| u.owner_id == "p1").unwrap();
+    let enemy_idx = game.units.iter().position(|u| u.owner_id == "p2").unwrap();
+    let enemy_id = game.units[enemy_idx].id.clone();
+    game.units[scout_idx].q = scout_q;
+    game.units[scout_idx].r = scout_r;
+    game.units[enemy_idx].q = scout_q + 1;
+    game.units[enemy_idx].r = scout_r;
+    game.reindex();
+    game.update_exploration("p1");
+    assert!(game.last_seen_units("p1").is_empty(), "the enemy is in plain sight");
+    assert!(game.view_for("p1").units.iter().any(|u| u.id == enemy_id));
+
+    // Walk back home: the enemy is out of sight but remembered where it stood
+    game.end_current_turn(0);
+    game.units[scout_idx].q = capital.0;
+    game.units[scout_idx].r = capital.1;
+    game.reindex();
+    game.update_exploration("p1");
+    let view = game.view_for("p1");
+    assert!(!view.units.iter().any(|u| u.id == enemy_id));
+    assert_eq!(view.last_seen_units.len(), 1);
+    let seen = &view.last_seen_units[0];
+    assert_eq!((seen.unit.id.as_str(), seen.unit.q, seen.unit.r), (enemy_id.as_str(), scout_q + 1, scout_r));
+    assert_eq!(seen.seen_turn, 0);
+}
+
+#[test]
+fn views_turn_back_into_the_game_as_the_player_knows_it() {
+    let mut game = three_player_game();
+    let capital = game.cities.iter().find(|c| c.owner_id == "p2").map(|c| (c.q, c.r)).unwrap();
+    let scout = game.units.iter().position(|u| u.owner_id == "p1").unwrap();
+    game.units[scout].q = capital.0 + 1;
+    game.units[scout].r = capital.1;
+    game.reindex();
+    game.update_exploration("p1");
+
+    for player in &game.players {
+        let view = game.view_for(&player.id);
+        let again = view.known_session().view_for(&player.id);
+        assert_eq!(again.units, view.units);
+        assert_eq!(again.cities, view.cities);
+        assert_eq!(again.last_seen_cities, view.last_seen_cities);
+        assert_eq!(again.last_seen_units, view.last_seen_units);
+        assert_eq!(again.explored_tiles, view.explored_tiles);
+        assert_eq!(again.visible_tiles, view.visible_tiles);
+        assert_eq!(again.gold, view.gold);
+        assert_eq!(again.draw_offered_by, view.draw_offered_by);
+    }
+}
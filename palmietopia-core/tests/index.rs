@@ -0,0 +1,89 @@
+mod common;
+
+use palmietopia_core::{City, GameSession, MapSize, Terrain, Unit, UnitType};
+
+/// Three players on an all-grassland map. p1's Knight stands at the origin next to p2's capital
+/// at (1, 0) and a p2 Conscript at (0, 1); p3's Conscript sits after them in `units` so removing
+/// p2's units shifts it.
+fn crowded_field() -> GameSession {
+    let mut game = common::game(&["p1", "p2", "p3"], MapSize::Medium, 5);
+    for tile in &mut game.map.tiles {
+        tile.terrain = Terrain::Grassland;
+    }
+    game.cities = vec![City {
+        id: "capital".to_string(),
+        owner_id: "p2".to_string(),
+        q: 1,
+        r: 0,
+        name: "p2's Capital".to_string(),
+        is_capitol: true,
+        produced_this_turn: false,
+    }];
+    game.units = vec![
+        Unit::new("knight".to_string(), "p1".to_string(), UnitType::Knight, 0, 0),
+        Unit::new("conscript".to_string(), "p2".to_string(), UnitType::Conscript, 0, 1),
+        Unit::new("bystander".to_string(), "p3".to_string(), UnitType::Conscript, -3, 0),
+    ];
+    game.reindex();
+    game
+}
+
+/// Every unit is found by id and by position, and `gone` units and `empty` tiles are not
+fn assert_indexed(game: &GameSession, gone: &[&str], empty: &[(i32, i32)]) {
+    for unit in &game.units {
+        assert_eq!(game.unit(&unit.id), Some(unit), "{} by id", unit.id);
+        assert_eq!(game.unit_at(unit.q, unit.r), Some(unit), "{} by position", unit.id);
+    }
+    for id in gone {
+        assert_eq!(game.unit(id), None, "{} is gone", id);
+    }
+    for &(q, r) in empty {
+        assert!(!game.is_occupied(q, r), "({}, {}) is empty", q, r);
+    }
+}
+
+#[test]
+fn index_follows_moves() {
+    let mut game = crowded_field();
+    game.move_unit("knight", -1, 0).unwrap();
+    assert_indexed(&game, &[], &[(0, 0)]);
+    assert_eq!(game.unit_at(-1, 0).map(|u| u.id.as_str()), Some("knight"));
+}
+
+#[test]
+fn index_drops_units_that_die() {
+    let mut game = crowded_field();
+    game.units[1].hp = 1;
+    let outcome = game.resolve_combat("knight", "conscript").unwrap();
+    assert!(outcome.defender_died);
+
+    // The Knight advances into the empty tile
+    assert_indexed(&game, &["conscript"], &[(0, 0)]);
+    assert_eq!(game.unit_at(0, 1).map(|u| u.id.as_str()), Some("knight"));
+}
+
+#[test]
+fn index_drops_units_of_players_whose_capital_falls() {
+    let mut game = crowded_field();
+    let outcome = game.move_unit("knight", 1, 0).unwrap();
+    assert_eq!(outcome.eliminated_player.as_deref(), Some("p2"));
+
+    assert_indexed(&game, &["conscript"], &[(0, 0), (0, 1)]);
+    assert_eq!(game.units.len(), 2);
+}
+
+#[test]
+fn deserialized_games_answer_lookups_before_and_after_reindexing() {
+    let game = crowded_field();
+    let mut loaded: GameSession = serde_json::from_str(&serde_json::to_string(&game).unwrap()).unwrap();
+
+    // The index isn't serialized, so lookups fall back to scanning until it is rebuilt
+    assert_indexed(&loaded, &["nobody"], &[(0, 2)]);
+    loaded.units.remove(1);
+    assert_indexed(&loaded, &["conscript"], &[(0, 1)]);
+
+    loaded.reindex();
+    assert_indexed(&loaded, &["conscript"], &[(0, 1)]);
+    loaded.move_unit("knight", 0, 1).unwrap();
+    assert_indexed(&loaded, &["conscript"], &[(0, 0)]);
+}
@@ -0,0 +1,244 @@
+mod common;
+
+use palmietopia_core::{
+    City, GameCommand, GameError, GameSession, MapSize, Terrain, Unit, UnitType,
+};
+
+/// Two players on an all-grassland map with no cities, p1's `unit` at the origin and a p2
+/// Conscript far away at (5, -5)
+fn open_field(unit: UnitType) -> GameSession {
+    let mut game = common::game(&["p1", "p2"], MapSize::Medium, 5);
+    for tile in &mut game.map.tiles {
+        tile.terrain = Terrain::Grassland;
+    }
+    game.cities.clear();
+    game.units = vec![
+        Unit::new("mine".to_string(), "p1".to_string(), unit, 0, 0),
+        Unit::new("enemy".to_string(), "p2".to_string(), UnitType::Conscript, 5, -5),
+    ];
+    game.reindex();
+    game
+}
+
+fn set_terrain(game: &mut GameSession, q: i32, r: i32, terrain: Terrain) {
+    game.map.tiles.iter_mut().find(|t| t.q == q && t.r == r).unwrap().terrain = terrain;
+}
+
+#[test]
+fn paths_go_around_water_and_mountains() {
+    let mut game = open_field(UnitType::Conscript);
+    set_terrain(&mut game, 1, 0, Terrain::Water);
+    set_terrain(&mut game, 2, 0, Terrain::Water);
+    set_terrain(&mut game, 1, -1, Terrain::Mountain);
+
+    let path = game.find_path("mine", 3, 0).unwrap();
+    assert_eq!(path.cost, 4, "the way round below is cheaper than climbing the mountain");
+    assert_eq!(path.steps.len(), 4);
+    assert_eq!(path.steps.last(), Some(&(3, 0)));
+    let mut previous = (0, 0);
+    for &(q, r) in &path.steps {
+        assert_eq!(GameSession::hex_distance(previous.0, previous.1, q, r), 1);
+        assert_eq!(game.get_terrain_at(q, r), Some(Terrain::Grassland), "{:?}", path.steps);
+        previous = (q, r);
+    }
+}
+
+#[test]
+fn unreachable_targets_have_no_path() {
+    let mut game = open_field(UnitType::Conscript);
+    for (q, r) in [(4, 0), (4, -1), (3, -1), (2, 0), (2, 1), (3, 1)] {
+        set_terrain(&mut game, q, r, Terrain::Water);
+    }
+    assert_eq!(game.find_path("mine", 3, 0), None, "the target is cut off by water");
+    assert_eq!(game.find_path("mine", 2, 0), None, "the target is water");
+    assert_eq!(game.find_path("mine", 5, -5), None, "the target is occupied");
+    assert_eq!(game.find_path("mine", 20, 0), None, "the target is off the map");
+}
+
+#[test]
+fn path_moves_stop_when_movement_runs_out() {
+    let mut game = open_field(UnitType::Conscript);
+    let path = game.find_path("mine", 4, 0).unwrap();
+    assert_eq!(path.cost, 4, "a Conscript needs two turns for this");
+
+    let outcome = game.move_unit_along_path("mine", &path.steps).unwrap();
+    assert_eq!(outcome.path, path.steps[..2].to_vec());
+    assert_eq!(outcome.movement_remaining, 0);
+    let unit = game.unit("mine").unwrap();
+    assert_eq!((unit.q, unit.r), path.steps[1]);
+    assert_eq!(game.unit_at(0, 0), None);
+}
+
+#[test]
+fn path_moves_stop_before_an_unaffordable_mountain() {
+    let mut game = open_field(UnitType::Conscript);
+    set_terrain(&mut game, 2, 0, Terrain::Mountain);
+
+    let outcome = game.move_unit_along_path("mine", &[(1, 0), (2, 0)]).unwrap();
+    assert_eq!(outcome.path, vec![(1, 0)]);
+    assert_eq!(outcome.movement_remaining, 1);
+}
+
+#[test]
+fn invalid_paths_change_nothing() {
+    let mut game = open_field(UnitType::Conscript);
+    set_terrain(&mut game, 2, 0, Terrain::Water);
+
+    let not_adjacent = game.move_unit_along_path("mine", &[(1, 0), (3, 0)]);
+    assert!(matches!(not_adjacent, Err(GameError::NotAdjacent)));
+    let into_water = game.move_unit_along_path("mine", &[(1, 0), (2, 0)]);
+    assert!(matches!(into_water, Err(GameError::ImpassableTerrain)));
+    let unit = game.unit("mine").unwrap();
+    assert_eq!((unit.q, unit.r, unit.movement_remaining), (0, 0, 2));
+}
+
+#[test]
+fn players_plan_paths_around_the_units_they_can_see() {
+    let mut game = open_field(UnitType::Conscript);
+    game.units[1].q = 1;
+    game.units[1].r = 0;
+    game.reindex();
+
+    let known = game.view_for("p1").known_session();
+    assert_eq!(known.find_path("mine", 3, 0), game.find_path("mine", 3, 0));
+    assert!(!known.find_path("mine", 3, 0).unwrap().steps.contains(&(1, 0)));
+}
+
+fn reachable(game: &GameSession) -> Vec<(i32, i32, u32)> {
+    game.reachable_tiles("mine").into_iter().map(|t| (t.q, t.r, t.cost)).collect()
+}
+
+#[test]
+fn mountains_cost_two_movement() {
+    let mut game = open_field(UnitType::Conscript);
+    set_terrain(&mut game, 1, 0, Terrain::Mountain);
+    set_terrain(&mut game, 0, 1, Terrain::Mountain);
+
+    let tiles = reachable(&game);
+    assert!(tiles.contains(&(1, 0, 2)));
+    assert!(!tiles.iter().any(|&(q, r, _)| (q, r) == (2, 0)), "nothing is left after climbing");
+    assert!(tiles.contains(&(2, -1, 2)), "the way round the mountains is open");
+    assert!(tiles.windows(2).all(|w| w[0].2 <= w[1].2), "cheapest tiles come first");
+}
+
+#[test]
+fn water_and_occupied_tiles_are_impassable() {
+    let mut game = open_field(UnitType::Conscript);
+    set_terrain(&mut game, 1, 0, Terrain::Water);
+    game.units.push(Unit::new("friend".to_string(), "p1".to_string(), UnitType::Conscript, 0, 1));
+    game.units[1].q = -1;
+    game.units[1].r = 0;
+    game.reindex();
+
+    let tiles: Vec<(i32, i32)> = reachable(&game).into_iter().map(|(q, r, _)| (q, r)).collect();
+    for blocked in [(1, 0), (0, 1), (-1, 0)] {
+        assert!(!tiles.contains(&blocked), "{:?} is blocked", blocked);
+    }
+    assert!(tiles.contains(&(2, -1)), "tiles behind the water can be walked round to");
+}
+
+#[test]
+fn units_on_the_map_edge_only_reach_tiles_on_the_map() {
+    let mut game = open_field(UnitType::Conscript);
+    game.units[0].q = 6;
+    game.units[0].r = 0;
+    game.reindex();
+
+    let tiles = reachable(&game);
+    assert!(tiles.iter().all(|&(q, r, _)| game.map.contains(q, r)));
+    // Within two steps of a corner of a radius 6 map: 3 neighbours, then 5 more tiles
+    assert_eq!(tiles.len(), 8, "{:?}", tiles);
+}
+
+#[test]
+fn bowmen_shoot_two_tiles_and_melee_units_one() {
+    let mut game = open_field(UnitType::Bowman);
+    game.units[1].q = 2;
+    game.units[1].r = 0;
+    game.reindex();
+    assert_eq!(game.attackable_units("mine"), vec!["enemy".to_string()]);
+
+    game.units[1].q = 3;
+    game.reindex();
+    assert!(game.attackable_units("mine").is_empty(), "three tiles is out of range");
+
+    let mut game = open_field(UnitType::Conscript);
+    game.units[1].q = 2;
+    game.units[1].r = 0;
+    game.reindex();
+    assert!(game.attackable_units("mine").is_empty());
+    game.units[1].q = 1;
+    game.reindex();
+    assert_eq!(game.attackable_units("mine"), vec!["enemy".to_string()]);
+
+    game.units[0].movement_remaining = 0;
+    assert!(game.attackable_units("mine").is_empty(), "attacking needs movement left");
+}
+
+#[test]
+fn views_highlight_the_same_moves_and_targets_as_the_server() {
+    let mut game = open_field(UnitType::Bowman);
+    set_terrain(&mut game, 1, 0, Terrain::Mountain);
+    set_terrain(&mut game, 0, 1, Terrain::Water);
+    // In range but hidden behind the mountain, and in range and in sight
+    game.units[1].q = 2;
+    game.units[1].r = 0;
+    game.units.push(Unit::new("target".to_string(), "p2".to_string(), UnitType::Conscript, -2, 0));
+    game.reindex();
+
+    let known = game.view_for("p1").known_session();
+    assert_eq!(known.reachable_tiles("mine"), game.reachable_tiles("mine"));
+    assert_eq!(known.attackable_units("mine"), vec!["target".to_string()]);
+    assert_eq!(game.attackable_units("mine"), vec!["target".to_string()]);
+}
+
+#[test]
+fn commands_applied_to_a_view_match_the_server() {
+    let mut game = open_field(UnitType::Conscript);
+    let mut view = game.view_for("p1");
+    let command = GameCommand::MoveUnitPath {
+        player_id: "p1".to_string(),
+        unit_id: "mine".to_string(),
+        path: vec![(1, 0), (2, 0)],
+    };
+
+    let predicted = view.apply(command.clone()).unwrap();
+    assert_eq!(predicted, game.apply(command).unwrap());
+    let served = game.view_for("p1");
+    assert_eq!(view.units, served.units);
+    assert_eq!(view.explored_tiles, served.explored_tiles);
+    assert_eq!(view.visible_tiles, served.visible_tiles);
+
+    let out_of_turn = GameCommand::EndTurn { player_id: "p2".to_string(), time_used_ms: 0 };
+    assert!(view.apply(out_of_turn).is_err());
+}
+
+#[test]
+fn purchases_are_checked_but_left_to_the_server() {
+    let mut game = open_field(UnitType::Conscript);
+    game.cities.push(City {
+        id: "home".to_string(),
+        owner_id: "p1".to_string(),
+        q: 0,
+        r: 1,
+        name: "Home".to_string(),
+        is_capitol: true,
+        produced_this_turn: false,
+    });
+    let buy = |unit_type| GameCommand::BuyUnit {
+        player_id: "p1".to_string(),
+        city_id: "home".to_string(),
+        unit_type,
+    };
+    game.player_gold[0] = UnitType::Conscript.cost();
+    let mut view = game.view_for("p1");
+
+    let broke = view.clone().apply(buy(UnitType::Knight));
+    assert!(matches!(broke, Err(GameError::NotEnoughGold { .. })));
+
+    // The unit's id is the server's to give, so the view waits for it
+    assert_eq!(view.apply(buy(UnitType::Conscript)).unwrap(), vec![]);
+    assert_eq!((view.units.len(), view.gold), (1, UnitType::Conscript.cost()));
+    game.apply(buy(UnitType::Conscript)).unwrap();
+    assert!(game.view_for("p1").units.iter().any(|u| u.q == 0 && u.r == 1));
+}
@@ -0,0 +1,113 @@
+mod common;
+
+use palmietopia_core::{GameCommand, GameError, GameEvent, GameSession, GameStatus, MapSize};
+
+fn game_with(ids: &[&str]) -> GameSession {
+    common::game(ids, MapSize::Small, 3)
+}
+
+fn offer(id: &str) -> GameCommand {
+    GameCommand::OfferDraw { player_id: id.to_string() }
+}
+
+fn accept(id: &str) -> GameCommand {
+    GameCommand::AcceptDraw { player_id: id.to_string() }
+}
+
+fn end_turn(id: &str) -> GameCommand {
+    GameCommand::EndTurn { player_id: id.to_string(), time_used_ms: 0 }
+}
+
+#[test]
+fn a_draw_needs_every_player_still_in() {
+    let mut game = game_with(&["p1", "p2", "p3"]);
+    assert_eq!(game.apply(accept("p2")), Err(GameError::NoDrawOffer));
+
+    // Offers and answers don't have to wait for a turn
+    game.apply(offer("p2")).unwrap();
+    assert_eq!(game.apply(offer("p1")), Err(GameError::DrawAlreadyOffered));
+    assert_eq!(game.apply(accept("p1")).unwrap(), vec![GameEvent::DrawAccepted { player_id: "p1".to_string() }]);
+    assert_eq!(game.view_for("p3").draw_offered_by, vec!["p2".to_string(), "p1".to_string()]);
+    assert_eq!(game.status, GameStatus::InProgress);
+
+    let events = game.apply(accept("p3")).unwrap();
+    assert_eq!(events, vec![GameEvent::DrawAccepted { player_id: "p3".to_string() }, GameEvent::GameDrawn]);
+    assert_eq!(game.status, GameStatus::Draw);
+    assert_eq!(game.apply(end_turn("p1")), Err(GameError::GameFinished));
+}
+
+#[test]
+fn draw_offers_lapse_after_the_next_turn() {
+    let mut game = game_with(&["p1", "p2"]);
+    game.apply(offer("p1")).unwrap();
+    game.apply(end_turn("p1")).unwrap();
+    assert_eq!(game.draw_offered_by(), vec!["p1".to_string()], "p2 gets their whole turn to answer");
+    game.apply(end_turn("p2")).unwrap();
+    assert!(game.draw_offer.is_none());
+    assert_eq!(game.apply(accept("p2")), Err(GameError::NoDrawOffer));
+}
+
+fn resign(id: &str) -> GameCommand {
+    GameCommand::Resign { player_id: id.to_string() }
+}
+
+#[test]
+fn resigning_settles_the_draw_offer() {
+    // The only player who offered resigns: the offer goes with them
+    let mut game = game_with(&["p1", "p2", "p3"]);
+    game.apply(offer("p3")).unwrap();
+    game.apply(resign("p3")).unwrap();
+    assert!(game.draw_offer.is_none());
+    assert!(game.view_for("p1").draw_offered_by.is_empty());
+    game.apply(offer("p2")).unwrap();
+
+    // Everyone but the resigning player had agreed: the game is drawn
+    let mut game = game_with(&["p1", "p2", "p3", "p4"]);
+    game.apply(offer("p1")).unwrap();
+    game.apply(accept("p2")).unwrap();
+    game.apply(accept("p3")).unwrap();
+    let events = game.apply(resign("p4")).unwrap();
+    assert_eq!(events, vec![GameEvent::PlayerResigned { player_id: "p4".to_string() }, GameEvent::GameDrawn]);
+    assert_eq!(game.status, GameStatus::Draw);
+    assert!(game.draw_offer.is_none());
+
+    // Someone still has to answer: the offer stands without the resigning player
+    let mut game = game_with(&["p1", "p2", "p3"]);
+    game.apply(offer("p1")).unwrap();
+    game.apply(accept("p2")).unwrap();
+    game.apply(resign("p2")).unwrap();
+    assert_eq!(game.draw_offered_by(), vec!["p1".to_string()]);
+    assert_eq!(game.status, GameStatus::InProgress);
+}
+
+#[test]
+fn resigning_on_your_own_turn_passes_it_on_unpaid() {
+    let mut game = game_with(&["p1", "p2", "p3"]);
+    let (gold, time) = (game.player_gold[0], game.player_times_ms[0]);
+    let events = game.apply(resign("p1")).unwrap();
+    assert_eq!(events.last(), Some(&GameEvent::TurnEnded { current_turn: 1 }));
+    assert_eq!((game.player_gold[0], game.player_times_ms[0]), (gold, time), "no income or increment");
+    assert_eq!(game.turn_number, 1);
+}
+
+#[test]
+fn only_the_host_can_abort() {
+    let mut game = game_with(&["p1", "p2"]);
+    assert_eq!(game.host_id, "p1");
+    assert_eq!(game.apply(GameCommand::Abort { player_id: "p2".to_string() }), Err(GameError::NotHost));
+
+    let events = game.apply(GameCommand::Abort { player_id: "p1".to_string() }).unwrap();
+    assert_eq!(events, vec![GameEvent::GameAborted { player_id: Some("p1".to_string()) }]);
+    assert_eq!(game.status, GameStatus::Finished);
+}
+
+#[test]
+fn games_can_be_abandoned_after_the_host_resigns() {
+    let mut game = game_with(&["p1", "p2", "p3"]);
+    game.apply(resign("p1")).unwrap();
+    assert_eq!(game.apply(GameCommand::Abort { player_id: "p1".to_string() }), Err(GameError::PlayerEliminated));
+
+    assert_eq!(game.abandon(), vec![GameEvent::GameAborted { player_id: None }]);
+    assert_eq!(game.status, GameStatus::Finished);
+    assert_eq!(game.abandon(), vec![], "only once");
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use palmietopia_core::{GameCommand, GameSession, MapSize, Replay};
+
+fn two_player_game() -> GameSession {
+    common::game(&["p1", "p2"], MapSize::Medium, 11)
+}
+
+/// The parts of a session that commands change
+fn snapshot(game: &GameSession) -> String {
+    serde_json::to_string(&(&game.units, &game.cities, game.current_turn, &game.player_gold, &game.player_times_ms))
+        .unwrap()
+}
+
+#[test]
+fn replay_rebuilds_every_turn() {
+    let mut game = two_player_game();
+    let mut replay = Replay::new(game.clone(), 0);
+    let mut turn_snapshots = vec![snapshot(&game)];
+
+    for turn in 0..6u64 {
+        let player_id = game.players[game.current_turn].id.clone();
+
+        // Walk every unit one step along the cheapest reachable tile, if any
+        let unit_ids: Vec<String> = game.units.iter().filter(|u| u.owner_id == player_id).map(|u| u.id.clone()).collect();
+        for unit_id in unit_ids {
+            if let Some(tile) = game.reachable_tiles(&unit_id).first() {
+                let command = GameCommand::MoveUnit {
+                    player_id: player_id.clone(),
+                    unit_id,
+                    to_q: tile.q,
+                    to_r: tile.r,
+                };
+                game.apply(command.clone()).unwrap();
+                replay.record(turn * 1_000 + 500, command);
+            }
+        }
+
+        let command = GameCommand::EndTurn { player_id, time_used_ms: 1_000 };
+        game.apply(command.clone()).unwrap();
+        replay.record((turn + 1) * 1_000, command);
+        turn_snapshots.push(snapshot(&game));
+    }
+
+    assert_eq!(replay.turn_count().unwrap(), 6);
+    for (turn, expected) in turn_snapshots.iter().enumerate() {
+        assert_eq!(&snapshot(&replay.session_at_turn(turn).unwrap()), expected, "turn {}", turn);
+    }
+    assert_eq!(snapshot(&replay.final_session().unwrap()), snapshot(&game));
+
+    // Replays survive a round trip through JSON, as they do when stored
+    let restored: Replay = serde_json::from_str(&serde_json::to_string(&replay).unwrap()).unwrap();
+    assert_eq!(snapshot(&restored.final_session().unwrap()), snapshot(&game));
+    assert_eq!(snapshot(&restored.session_after(0).unwrap()), turn_snapshots[0]);
+}
+
+#[test]
+fn resigning_on_your_own_turn_counts_as_a_turn() {
+    let mut game = common::game(&["p1", "p2", "p3"], MapSize::Medium, 11);
+    let mut replay = Replay::new(game.clone(), 0);
+    let commands = [
+        GameCommand::EndTurn { player_id: "p1".to_string(), time_used_ms: 0 },
+        GameCommand::Resign { player_id: "p2".to_string() },
+        GameCommand::EndTurn { player_id: "p3".to_string(), time_used_ms: 0 },
+    ];
+    for (at_ms, command) in commands.into_iter().enumerate() {
+        game.apply(command.clone()).unwrap();
+        replay.record(at_ms as u64, command);
+    }
+
+    assert_eq!(replay.turn_count().unwrap(), game.turn_number as usize);
+    assert_eq!(replay.session_at_turn(2).unwrap().turn_number, 2);
+    assert_eq!(replay.session_at_turn(2).unwrap().current_turn, 2, "p3 is up after p2 resigned");
+}
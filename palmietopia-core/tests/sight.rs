@@ -0,0 +1,84 @@
+mod common;
+
+use palmietopia_core::{
+    hex_line, GameCommand, GameError, GameSession, MapSize, Terrain, Unit, UnitType,
+};
+
+/// Two players on an all-grassland map with no cities, p1's Bowman at the origin and a p2
+/// Conscript at `enemy`
+fn open_field(enemy: (i32, i32)) -> GameSession {
+    let mut game = common::game(&["p1", "p2"], MapSize::Medium, 5);
+    for tile in &mut game.map.tiles {
+        tile.terrain = Terrain::Grassland;
+    }
+    game.cities.clear();
+    game.units = vec![
+        Unit::new("bowman".to_string(), "p1".to_string(), UnitType::Bowman, 0, 0),
+        Unit::new("enemy".to_string(), "p2".to_string(), UnitType::Conscript, enemy.0, enemy.1),
+    ];
+    game.reindex();
+    game
+}
+
+fn set_terrain(game: &mut GameSession, q: i32, r: i32, terrain: Terrain) {
+    game.map.tiles.iter_mut().find(|t| t.q == q && t.r == r).unwrap().terrain = terrain;
+}
+
+#[test]
+fn hex_lines_step_through_neighbours() {
+    for to in [(3, 0), (-2, 3), (4, -1), (1, 1)] {
+        let line = hex_line((0, 0), to);
+        assert_eq!(line.first(), Some(&(0, 0)));
+        assert_eq!(line.last(), Some(&to));
+        assert_eq!(line.len() as i32, GameSession::hex_distance(0, 0, to.0, to.1) + 1);
+        for pair in line.windows(2) {
+            assert_eq!(GameSession::hex_distance(pair[0].0, pair[0].1, pair[1].0, pair[1].1), 1);
+        }
+    }
+}
+
+#[test]
+fn mountains_block_sight_and_forests_shorten_it() {
+    let mut game = open_field((5, -5));
+    let visible = game.get_visible_tiles("p1");
+    assert!(visible.contains(&(3, 0)), "open ground: the Bowman sees 3 tiles");
+
+    set_terrain(&mut game, 1, 0, Terrain::Mountain);
+    let visible = game.get_visible_tiles("p1");
+    assert!(visible.contains(&(1, 0)), "the mountain itself is visible");
+    assert!(!visible.contains(&(2, 0)) && !visible.contains(&(3, 0)), "nothing behind it is");
+    assert!(visible.contains(&(0, 3)), "other directions are unaffected");
+
+    set_terrain(&mut game, 1, 0, Terrain::Forest);
+    let visible = game.get_visible_tiles("p1");
+    assert!(visible.contains(&(2, 0)));
+    assert!(!visible.contains(&(3, 0)), "the forest costs a tile of range");
+}
+
+#[test]
+fn high_ground_extends_vision_over_forests() {
+    let mut game = open_field((5, -5));
+    set_terrain(&mut game, 1, 0, Terrain::Forest);
+    set_terrain(&mut game, 0, 0, Terrain::Mountain);
+    let visible = game.get_visible_tiles("p1");
+    assert!(visible.contains(&(4, 0)), "a Bowman on a mountain sees 4 tiles, over the forest");
+    assert!(!visible.contains(&(5, 0)));
+}
+
+#[test]
+fn ranged_attacks_need_line_of_sight() {
+    let attack = GameCommand::AttackUnit {
+        player_id: "p1".to_string(),
+        attacker_id: "bowman".to_string(),
+        defender_id: "enemy".to_string(),
+    };
+
+    let mut game = open_field((2, 0));
+    set_terrain(&mut game, 1, 0, Terrain::Mountain);
+    assert!(game.attackable_units("bowman").is_empty());
+    assert_eq!(game.apply(attack.clone()), Err(GameError::NoLineOfSight));
+
+    let mut game = open_field((2, 0));
+    assert_eq!(game.attackable_units("bowman"), vec!["enemy".to_string()]);
+    assert!(game.apply(attack).is_ok());
+}
@@ -0,0 +1,63 @@
+mod common;
+
+use palmietopia_core::{
+    balanced_starting_positions, evaluate_starts, start_spread, GameMap, GameSession, MapGenConfig, MapSize,
+    StartConfig, Terrain,
+};
+use std::collections::HashSet;
+
+#[test]
+fn capitals_are_distinct_on_every_map_size() {
+    let config = StartConfig::default();
+    for map_size in [MapSize::Tiny, MapSize::Small, MapSize::Medium, MapSize::Large, MapSize::Huge] {
+        for seed in 0..100 {
+            let map = GameMap::generate(map_size.radius(), seed);
+            let players = 2 + (seed as usize % 4);
+            let positions = balanced_starting_positions(&map, players, &config);
+            let unique: HashSet<_> = positions.iter().collect();
+            assert_eq!(unique.len(), players, "{:?} seed {}: {:?}", map_size, seed, positions);
+        }
+    }
+}
+
+#[test]
+fn starts_are_balanced_within_tolerance_on_medium_and_larger_maps() {
+    let config = StartConfig::default();
+    for map_size in [MapSize::Medium, MapSize::Large, MapSize::Huge] {
+        for seed in 0..100 {
+            let map = GameMap::generate(map_size.radius(), seed);
+            let players = 2 + (seed as usize % 4);
+            let positions = balanced_starting_positions(&map, players, &config);
+            let scores = evaluate_starts(&map, &positions, &config);
+            let spread = start_spread(&scores);
+            assert!(
+                spread <= config.tolerance,
+                "{:?} seed {}: spread {:.3} exceeds tolerance, scores {:?}",
+                map_size, seed, spread, scores
+            );
+        }
+    }
+}
+
+#[test]
+fn games_are_generated_with_the_lobby_settings() {
+    let mut lobby = common::lobby(&["p0", "p1"], MapSize::Medium, 3);
+    lobby.map_config = MapGenConfig { water_ratio: 0.0, rivers_per_4_radius: 0, ..MapGenConfig::default() };
+    lobby.start_config = StartConfig { tolerance: 0.05, area_radius: 2 };
+
+    let game = GameSession::from_lobby(&lobby);
+    assert!(game.map.tiles.iter().all(|t| t.terrain != Terrain::Water));
+    let capitals: Vec<(i32, i32)> = game.cities.iter().map(|c| (c.q, c.r)).collect();
+    let spread = start_spread(&evaluate_starts(&game.map, &capitals, &lobby.start_config));
+    assert!(spread <= lobby.start_config.tolerance, "spread {:.3}", spread);
+}
+
+#[test]
+fn unplayable_settings_are_rejected() {
+    assert!(MapGenConfig::default().is_valid());
+    assert!(StartConfig::default().is_valid());
+    assert!(!MapGenConfig { water_ratio: 1.0, ..MapGenConfig::default() }.is_valid());
+    assert!(!MapGenConfig { forest_ratio: -0.1, ..MapGenConfig::default() }.is_valid());
+    assert!(!StartConfig { tolerance: f64::NAN, ..StartConfig::default() }.is_valid());
+    assert!(!StartConfig { area_radius: 0, ..StartConfig::default() }.is_valid());
+}
@@ -0,0 +1,189 @@
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap, StatusCode},
+    Json,
+};
+use palmietopia_core::{GameSession, GameStatus};
+use std::sync::Arc;
+
+use crate::state::AppState;
+use crate::store::StoreError;
+use crate::store_error;
+
+/// Who may use the routes that change saved games. They are served without CORS and need
+/// `Authorization: Bearer <ADMIN_TOKEN>`; with no token set they refuse everyone.
+#[derive(Clone, Default)]
+pub struct AdminConfig {
+    token: Option<String>,
+}
+
+impl AdminConfig {
+    /// Read `ADMIN_TOKEN`, leaving the admin routes off if it is unset or empty
+    pub fn from_env() -> Self {
+        let token = std::env::var("ADMIN_TOKEN").ok().filter(|token| !token.is_empty());
+        if token.is_none() {
+            tracing::info!("ADMIN_TOKEN is not set, the admin routes are off");
+        }
+        Self { token }
+    }
+
+    #[cfg(test)]
+    pub fn with_token(token: &str) -> Self {
+        Self { token: Some(token.to_string()) }
+    }
+
+    fn authorize(&self, headers: &HeaderMap) -> Result<(), (StatusCode, String)> {
+        let Some(token) = &self.token else {
+            return Err((StatusCode::FORBIDDEN, "Admin routes are off".to_string()));
+        };
+        let given = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        match given {
+            Some(given) if same_secret(given.as_bytes(), token.as_bytes()) => Ok(()),
+            _ => Err((StatusCode::UNAUTHORIZED, "Admin token required".to_string())),
+        }
+    }
+}
+
+/// Compare in full rather than stopping at the first difference, so how long a wrong guess
+/// takes doesn't give the token away
+fn same_secret(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Whether a saved game may be given `status`. Only a game still marked in progress, left
+/// behind by a server that stopped running it, can be settled, and only with a result: games
+/// that are over stay over, and a winner has to have played.
+fn check_status_change(game: &GameSession, status: &GameStatus) -> Result<(), (StatusCode, String)> {
+    if game.status != GameStatus::InProgress {
+        return Err((StatusCode::CONFLICT, "Game is already over".to_string()));
+    }
+    match status {
+        GameStatus::InProgress => {
+            Err((StatusCode::UNPROCESSABLE_ENTITY, "A game can only be settled with a result".to_string()))
+        }
+        GameStatus::Victory { winner_id } if !game.players.iter().any(|p| &p.id == winner_id) => {
+            Err((StatusCode::UNPROCESSABLE_ENTITY, "The winner didn't play in this game".to_string()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Saved games can only be changed behind the server's back once they aren't being played
+fn being_played() -> (StatusCode, String) {
+    (StatusCode::CONFLICT, "Game is being played".to_string())
+}
+
+pub async fn delete_game(
+    headers: HeaderMap,
+    Path(game_id): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    state.admin.authorize(&headers)?;
+    let deleted = state.game_manager.unless_running(&game_id, async || state.store.delete_game(&game_id).await).await;
+    deleted.ok_or_else(being_played)?.map_err(store_error)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn update_game_status(
+    headers: HeaderMap,
+    Path(game_id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    Json(status): Json<GameStatus>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    state.admin.authorize(&headers)?;
+    let updated = state.game_manager.unless_running(&game_id, async || {
+        let game = state.store.load_game(&game_id).await.map_err(store_error)?;
+        check_status_change(&game.ok_or_else(|| store_error(StoreError::NotFound))?, &status)?;
+        state.store.update_game_status(&game_id, status).await.map_err(store_error)
+    });
+    updated.await.ok_or_else(being_played)??;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::autosave::AutosaveConfig;
+    use crate::presence::PresenceConfig;
+    use crate::store::memory::InMemoryStore;
+    use crate::testing;
+
+    fn state_with(admin: AdminConfig) -> Arc<AppState> {
+        let mut state = AppState::new(Arc::new(InMemoryStore::new()), PresenceConfig::default(), AutosaveConfig::default());
+        state.admin = admin;
+        Arc::new(state)
+    }
+
+    fn game(id: &str) -> GameSession {
+        testing::game(id, &["p1", "p2"])
+    }
+
+    fn bearer(token: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, format!("Bearer {}", token).parse().unwrap());
+        headers
+    }
+
+    async fn set_status(state: &Arc<AppState>, headers: HeaderMap, id: &str, status: GameStatus) -> StatusCode {
+        let path = Path(id.to_string());
+        match update_game_status(headers, path, State(Arc::clone(state)), Json(status)).await {
+            Ok(code) | Err((code, _)) => code,
+        }
+    }
+
+    async fn delete(state: &Arc<AppState>, headers: HeaderMap, id: &str) -> StatusCode {
+        match delete_game(headers, Path(id.to_string()), State(Arc::clone(state))).await {
+            Ok(code) | Err((code, _)) => code,
+        }
+    }
+
+    #[tokio::test]
+    async fn saved_games_are_only_changed_with_the_admin_token() {
+        let state = state_with(AdminConfig::default());
+        testing::save(&*state.store, game("g1")).await;
+        assert_eq!(delete(&state, bearer("anything"), "g1").await, StatusCode::FORBIDDEN, "no token set");
+
+        let state = state_with(AdminConfig::with_token("secret"));
+        testing::save(&*state.store, game("g1")).await;
+        assert_eq!(delete(&state, HeaderMap::new(), "g1").await, StatusCode::UNAUTHORIZED);
+        assert_eq!(delete(&state, bearer("secreT"), "g1").await, StatusCode::UNAUTHORIZED);
+        assert_eq!(set_status(&state, bearer("guess"), "g1", GameStatus::Draw).await, StatusCode::UNAUTHORIZED);
+        assert!(state.store.load_game("g1").await.unwrap().is_some());
+
+        assert_eq!(delete(&state, bearer("secret"), "g1").await, StatusCode::NO_CONTENT);
+        assert_eq!(delete(&state, bearer("secret"), "g1").await, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn only_unfinished_games_can_be_settled() {
+        let state = state_with(AdminConfig::with_token("secret"));
+        testing::save(&*state.store, game("g1")).await;
+        let set = async |status| set_status(&state, bearer("secret"), "g1", status).await;
+
+        assert_eq!(set(GameStatus::InProgress).await, StatusCode::UNPROCESSABLE_ENTITY);
+        let stranger = GameStatus::Victory { winner_id: "p9".to_string() };
+        assert_eq!(set(stranger).await, StatusCode::UNPROCESSABLE_ENTITY);
+        let won = GameStatus::Victory { winner_id: "p2".to_string() };
+        assert_eq!(set(won.clone()).await, StatusCode::NO_CONTENT);
+
+        // Once over, it stays over
+        assert_eq!(set(GameStatus::InProgress).await, StatusCode::CONFLICT);
+        assert_eq!(set(GameStatus::Draw).await, StatusCode::CONFLICT);
+        assert_eq!(state.store.load_game("g1").await.unwrap().unwrap().status, won);
+        assert_eq!(set_status(&state, bearer("secret"), "missing", GameStatus::Draw).await, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn games_being_played_are_left_alone() {
+        let state = state_with(AdminConfig::with_token("secret"));
+        let running = game("g1");
+        state.game_manager.start_game(running).await;
+
+        assert_eq!(set_status(&state, bearer("secret"), "g1", GameStatus::Draw).await, StatusCode::CONFLICT);
+        assert_eq!(delete(&state, bearer("secret"), "g1").await, StatusCode::CONFLICT);
+        assert_eq!(state.store.load_game("g1").await.unwrap().unwrap().status, GameStatus::InProgress);
+    }
+}
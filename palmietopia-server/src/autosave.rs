@@ -0,0 +1,90 @@
+use palmietopia_core::{GameEvent, GameSession, GameStatus, Replay, ReplayEntry};
+use std::sync::Arc;
+
+use crate::store::GameStore;
+
+/// How many actions are logged between full snapshots of a game
+pub const DEFAULT_SNAPSHOT_INTERVAL: usize = 20;
+
+/// How running games are saved. Every accepted action is appended to the game's log as it
+/// happens; every `snapshot_interval` actions, and when the game ends, a full snapshot replaces
+/// the log.
+#[derive(Clone, Copy, Debug)]
+pub struct AutosaveConfig {
+    pub snapshot_interval: usize,
+}
+
+impl Default for AutosaveConfig {
+    fn default() -> Self {
+        Self {
+            snapshot_interval: DEFAULT_SNAPSHOT_INTERVAL,
+        }
+    }
+}
+
+impl AutosaveConfig {
+    /// Read `SNAPSHOT_INTERVAL`, falling back to the default if it is unset or invalid
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+        if let Ok(interval) = std::env::var("SNAPSHOT_INTERVAL") {
+            match interval.parse() {
+                Ok(interval) if interval > 0 => config.snapshot_interval = interval,
+                _ => tracing::warn!("Ignoring invalid SNAPSHOT_INTERVAL: {}", interval),
+            }
+        }
+        config
+    }
+}
+
+/// The game as it should be snapshotted: the current player is charged for their turn up to
+/// `now_ms`, as if it restarted then. Restoring it and restarting the turn then leaves their
+/// clock where it was.
+pub fn checkpoint(game: &GameSession, now_ms: u64) -> GameSession {
+    let mut saved = game.clone();
+    if saved.status == GameStatus::InProgress {
+        let elapsed = now_ms.saturating_sub(saved.turn_started_at_ms);
+        let current = saved.current_turn;
+        saved.player_times_ms[current] = saved.player_times_ms[current].saturating_sub(elapsed);
+        saved.turn_started_at_ms = now_ms;
+    }
+    saved
+}
+
+/// Write a full snapshot of the game and the replay up to the same point, in one go, dropping
+/// the actions logged before them. Together with the log they make up the whole game, so a
+/// resumed game keeps its replay from the start. Returns false if they couldn't be saved, in
+/// which case the log is as it was.
+pub async fn save_snapshot(store: &Arc<dyn GameStore>, game: &GameSession, replay: &Replay, now_ms: u64) -> bool {
+    match store.save_snapshot(checkpoint(game, now_ms), replay.clone()).await {
+        Ok(()) => true,
+        Err(e) => {
+            tracing::error!("Failed to save game {}: {}", game.id, e);
+            false
+        }
+    }
+}
+
+/// Rebuild a game from its last snapshot and the actions logged since, with the current
+/// player charged up to the last thing the server recorded. The actions that still apply are
+/// recorded in `replay`.
+pub fn rebuild(mut game: GameSession, actions: Vec<ReplayEntry>, replay: &mut Replay) -> GameSession {
+    let mut last_seen_ms = game.turn_started_at_ms;
+    for entry in actions {
+        match game.apply(entry.command.clone()) {
+            Ok(events) => {
+                if events.iter().any(|e| matches!(e, GameEvent::TurnEnded { .. })) {
+                    game.turn_started_at_ms = entry.at_ms;
+                }
+                last_seen_ms = last_seen_ms.max(entry.at_ms);
+                replay.entries.push(entry);
+            }
+            // The log only holds accepted commands, so this game's log is broken. Keep what
+            // could be rebuilt.
+            Err(e) => {
+                tracing::error!("Logged action for game {} no longer applies: {}", game.id, e);
+                break;
+            }
+        }
+    }
+    checkpoint(&game, last_seen_ms)
+}
@@ -1,36 +1,308 @@
-use palmietopia_core::{GameSession, ServerMessage};
-use std::collections::HashMap;
+use palmietopia_core::{
+    GameCommand, GameError, GameEvent, GameSession, PlayerView, Replay, ReplayEntry, SequencedMessage, ServerMessage,
+};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
-use tokio::sync::{broadcast, RwLock};
+use tokio::sync::{broadcast, mpsc, oneshot, RwLock};
 use tokio::time::{interval, Duration};
 
+use crate::autosave::{self, AutosaveConfig};
+use crate::presence::{DisconnectPolicy, PlayerPresence, PresenceConfig};
+use crate::store::{GameFilter, GameState, GameStore, StoreResult};
+
+/// How many recent messages each player's feed keeps for clients that fall behind
+const FEED_HISTORY: usize = 512;
+
+/// Everything sent on one player's game channel. Each message is stamped with the next sequence
+/// number and kept for a while, so a client that missed some can get them again.
+pub struct PlayerFeed {
+    pub tx: broadcast::Sender<String>,
+    pub seq: u64,
+    history: VecDeque<(u64, String)>,
+    /// The player's view as of the last delta, which the next one is computed against
+    view: PlayerView,
+}
+
+impl PlayerFeed {
+    fn send(&mut self, message: ServerMessage) {
+        self.seq += 1;
+        let json = serde_json::to_string(&SequencedMessage { seq: self.seq, message }).unwrap();
+        if self.history.len() == FEED_HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back((self.seq, json.clone()));
+        let _ = self.tx.send(json);
+    }
+
+    /// Messages after `since_seq`, or None if some of them are no longer kept
+    fn since(&self, since_seq: u64) -> Option<Vec<String>> {
+        let oldest = self.history.front().map_or(self.seq + 1, |(seq, _)| *seq);
+        if since_seq + 1 < oldest || since_seq > self.seq {
+            return None;
+        }
+        Some(self.history.iter().filter(|(seq, _)| *seq > since_seq).map(|(_, json)| json.clone()).collect())
+    }
+}
+
+/// How a client that fell behind catches up
+pub enum Resync {
+    /// The messages it missed, in order
+    Missed(Vec<String>),
+    /// Too far behind: a full view with the sequence number it is current as of
+    Snapshot(u64, Box<PlayerView>),
+}
+
+/// A write to the store on behalf of a running game
+enum Save {
+    /// A snapshot of the game as of `at_ms` and its replay up to it. If the snapshot can't be
+    /// written, `pending` is logged instead so no action is lost.
+    Snapshot {
+        game: Box<GameSession>,
+        replay: Box<Replay>,
+        at_ms: u64,
+        pending: Vec<ReplayEntry>,
+    },
+    /// Actions to append to the game's log
+    Actions(Vec<ReplayEntry>),
+}
+
+/// Carry out a game's saves one at a time, in the order they were queued, signalling each one
+/// once it is done. Runs until the game's `ActiveGame` is dropped and its queue is drained.
+async fn run_saver(
+    store: Arc<dyn GameStore>,
+    game_id: String,
+    mut saves: mpsc::UnboundedReceiver<(Save, oneshot::Sender<()>)>,
+) {
+    while let Some((save, done)) = saves.recv().await {
+        let pending = match save {
+            Save::Snapshot { game, replay, at_ms, pending } => {
+                if autosave::save_snapshot(&store, &game, &replay, at_ms).await {
+                    Vec::new()
+                } else {
+                    pending
+                }
+            }
+            Save::Actions(pending) => pending,
+        };
+        for entry in pending {
+            if let Err(e) = store.append_action(&game_id, entry).await {
+                tracing::error!("Failed to log action for game {}: {}", game_id, e);
+            }
+        }
+        let _ = done.send(());
+    }
+}
+
 pub struct ActiveGame {
     pub game: GameSession,
-    pub channel: broadcast::Sender<String>,
+    /// One feed per player so each only receives what they are allowed to see
+    pub feeds: HashMap<String, PlayerFeed>,
+    pub presence: HashMap<String, PlayerPresence>,
+    pub replay: Replay,
+    /// How many of the replay's entries are in the store, one way or another
+    saved_entries: usize,
+    /// Actions in the store's log since the last snapshot
+    logged_actions: usize,
+    /// The game's saver task, which does the writing so the store is never waited on with
+    /// the game locked
+    saver: mpsc::UnboundedSender<(Save, oneshot::Sender<()>)>,
+}
+
+impl ActiveGame {
+    /// A game going live, with its replay so far, saved to `store`
+    fn new(game: GameSession, replay: Replay, store: Arc<dyn GameStore>) -> Self {
+        let feeds = game.players
+            .iter()
+            .map(|p| {
+                let feed = PlayerFeed {
+                    tx: broadcast::channel(100).0,
+                    seq: 0,
+                    history: VecDeque::new(),
+                    view: game.view_for(&p.id),
+                };
+                (p.id.clone(), feed)
+            })
+            .collect();
+        let now = current_time_ms();
+        let presence = game.players.iter().map(|p| (p.id.clone(), PlayerPresence::joining(now))).collect();
+        let (saver, saves) = mpsc::unbounded_channel();
+        tokio::spawn(run_saver(store, game.id.clone(), saves));
+        Self {
+            game,
+            feeds,
+            presence,
+            saved_entries: replay.entries.len(),
+            replay,
+            logged_actions: 0,
+            saver,
+        }
+    }
+
+    /// Persist the commands accepted since the last call: append them to the game's log, or
+    /// write a snapshot instead once the log would reach the snapshot interval or the game is
+    /// over. The save is queued, to be written in order after the ones before it; the
+    /// returned receiver fires once it has been.
+    fn autosave(&mut self, config: AutosaveConfig) -> Option<oneshot::Receiver<()>> {
+        let pending = self.replay.entries[self.saved_entries..].to_vec();
+        if pending.is_empty() {
+            return None;
+        }
+        self.saved_entries = self.replay.entries.len();
+        let over = self.game.status != palmietopia_core::GameStatus::InProgress;
+        if over || self.logged_actions + pending.len() >= config.snapshot_interval {
+            self.logged_actions = 0;
+            Some(self.save_snapshot(pending))
+        } else {
+            self.logged_actions += pending.len();
+            Some(self.queue(Save::Actions(pending)))
+        }
+    }
+
+    /// Queue a snapshot of the game and its replay as they are now
+    fn save_snapshot(&self, pending: Vec<ReplayEntry>) -> oneshot::Receiver<()> {
+        self.queue(Save::Snapshot {
+            game: Box::new(self.game.clone()),
+            replay: Box::new(self.replay.clone()),
+            at_ms: current_time_ms(),
+            pending,
+        })
+    }
+
+    fn queue(&self, save: Save) -> oneshot::Receiver<()> {
+        let (done, saved) = oneshot::channel();
+        if self.saver.send((save, done)).is_err() {
+            tracing::error!("Saver for game {} has stopped", self.game.id);
+        }
+        saved
+    }
+
+    /// The player's full view and the sequence number of the last message it reflects
+    fn snapshot(&self, player_id: &str) -> Option<(u64, PlayerView)> {
+        self.feeds.get(player_id).map(|feed| (feed.seq, feed.view.clone()))
+    }
+
+    fn resync(&self, player_id: &str, since_seq: u64) -> Option<Resync> {
+        let feed = self.feeds.get(player_id)?;
+        Some(match feed.since(since_seq) {
+            Some(missed) => Resync::Missed(missed),
+            None => Resync::Snapshot(feed.seq, Box::new(feed.view.clone())),
+        })
+    }
+
+    /// Send each player what changed in their view since the last delta
+    fn send_deltas(&mut self) {
+        for player in &self.game.players {
+            let view = self.game.view_for(&player.id);
+            let Some(feed) = self.feeds.get_mut(&player.id) else {
+                continue;
+            };
+            let delta = feed.view.diff(&view);
+            if delta.is_empty() {
+                continue;
+            }
+            feed.view = view;
+            feed.send(ServerMessage::StateDelta { delta });
+        }
+    }
+
+    fn send_to(&mut self, player_id: &str, msg: ServerMessage) {
+        if let Some(feed) = self.feeds.get_mut(player_id) {
+            feed.send(msg);
+        }
+    }
+
+    fn send_all(&mut self, msg: &ServerMessage) {
+        for feed in self.feeds.values_mut() {
+            feed.send(msg.clone());
+        }
+    }
+
+    /// Send everyone a message that is only good until the next one of its kind, like a clock
+    /// tick. It carries no sequence number and isn't kept, so it can't crowd real updates out
+    /// of the history and a client that misses one has nothing to catch up on.
+    fn send_all_unsequenced(&self, msg: &ServerMessage) {
+        let json = serde_json::to_string(msg).unwrap();
+        for feed in self.feeds.values() {
+            let _ = feed.tx.send(json.clone());
+        }
+    }
+
+    fn presence_message(&self, player_id: &str, grace_period: Duration) -> Option<ServerMessage> {
+        let presence = self.presence.get(player_id)?;
+        Some(ServerMessage::PresenceChanged {
+            player_id: player_id.to_string(),
+            presence: presence.presence,
+            grace_ends_at_ms: presence.grace_ends_at_ms(grace_period),
+        })
+    }
+
+    /// Tell everyone about a change in a player's presence
+    fn broadcast_presence(&mut self, player_id: &str, grace_period: Duration) {
+        if let Some(msg) = self.presence_message(player_id, grace_period) {
+            self.send_all(&msg);
+        }
+    }
 }
 
 pub struct GameManager {
     pub active_games: Arc<RwLock<HashMap<String, ActiveGame>>>,
+    store: Arc<dyn GameStore>,
+    presence_config: PresenceConfig,
+    autosave_config: AutosaveConfig,
 }
 
 impl GameManager {
-    pub fn new() -> Self {
+    pub fn new(store: Arc<dyn GameStore>, presence_config: PresenceConfig, autosave_config: AutosaveConfig) -> Self {
         Self {
             active_games: Arc::new(RwLock::new(HashMap::new())),
+            store,
+            presence_config,
+            autosave_config,
         }
     }
 
-    pub async fn start_game(&self, mut game: GameSession, channel: broadcast::Sender<String>) {
-        let game_id = game.id.clone();
-        
+    pub async fn start_game(&self, mut game: GameSession) {
         // Set the turn start time
         game.turn_started_at_ms = current_time_ms();
+        let replay = Replay::new(game.clone(), game.turn_started_at_ms);
+        autosave::save_snapshot(&self.store, &game, &replay, game.turn_started_at_ms).await;
+        self.run(game, replay).await;
+    }
 
-        let active_game = ActiveGame {
-            game: game.clone(),
-            channel: channel.clone(),
-        };
+    /// Pick up the games that were in progress when the server last stopped, rebuilt from their
+    /// snapshots and action logs, with their replays picked up where they were. Clocks are
+    /// charged up to the last action saved, so the current turn restarts now and nobody loses
+    /// time to the downtime. Everyone has the grace period to rejoin.
+    pub async fn resume_games(&self) -> StoreResult<usize> {
+        let in_progress = self.store.list_games(&GameFilter::with_status(GameState::InProgress)).await?;
+        let mut count = 0;
+        for summary in in_progress {
+            let Some(snapshot) = self.store.load_game(&summary.id).await? else {
+                continue;
+            };
+            let actions = self.store.load_actions(&snapshot.id).await?;
+            let mut replay = match self.store.get_replay(&snapshot.id).await? {
+                Some(replay) => replay,
+                None => {
+                    tracing::warn!("No replay saved for game {}, starting one from its snapshot", snapshot.id);
+                    Replay::new(snapshot.clone(), snapshot.turn_started_at_ms)
+                }
+            };
+            let mut game = autosave::rebuild(snapshot, actions, &mut replay);
+            tracing::info!("Resuming game {} at turn {}", game.id, game.turn_number);
+            game.turn_started_at_ms = current_time_ms();
+            // Start the resumed game off with a clean log
+            autosave::save_snapshot(&self.store, &game, &replay, game.turn_started_at_ms).await;
+            self.run(game, replay).await;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    async fn run(&self, game: GameSession, replay: Replay) {
+        let game_id = game.id.clone();
+        let active_game = ActiveGame::new(game, replay, Arc::clone(&self.store));
 
         {
             let mut games = self.active_games.write().await;
@@ -39,275 +311,293 @@ impl GameManager {
 
         // Spawn timer task for this game
         let games_ref = Arc::clone(&self.active_games);
+        let presence_config = self.presence_config;
+        let autosave_config = self.autosave_config;
         tokio::spawn(async move {
-            run_game_timer(game_id, games_ref).await;
+            run_game_timer(game_id, games_ref, presence_config, autosave_config).await;
         });
     }
 
-    pub async fn end_turn(&self, game_id: &str, player_id: &str) -> Result<GameSession, String> {
+    /// End the current player's turn, charging them the time since the turn started
+    pub async fn end_turn(&self, game_id: &str, player_id: &str) -> Result<Vec<GameEvent>, GameError> {
         tracing::info!("end_turn called: game_id={}, player_id={}", game_id, player_id);
-        
+
         let mut games = self.active_games.write().await;
         let active_game = games.get_mut(game_id).ok_or_else(|| {
             tracing::error!("Game not found: {}", game_id);
-            "Game not found".to_string()
+            GameError::GameNotFound
         })?;
 
-        // Verify it's this player's turn
-        let current_player = &active_game.game.players[active_game.game.current_turn];
-        tracing::info!("Current player: id={}, name={}", current_player.id, current_player.name);
-        
-        if current_player.id != player_id {
-            tracing::error!("Not your turn: expected={}, got={}", current_player.id, player_id);
-            return Err(format!("Not your turn (expected {}, got {})", current_player.id, player_id));
-        }
-
-        // Calculate time used
-        let now = current_time_ms();
-        let time_used = now.saturating_sub(active_game.game.turn_started_at_ms);
-        tracing::info!("Time used: {}ms", time_used);
-
-        // End turn (subtracts time used, adds increment, advances to next player)
-        active_game.game.end_current_turn(time_used);
-        active_game.game.turn_started_at_ms = now;
-
-        tracing::info!("Turn ended. New turn: {}, player_times: {:?}", 
-            active_game.game.current_turn, active_game.game.player_times_ms);
-
-        // Broadcast turn change to all subscribed clients
-        // Visibility is calculated client-side using explored_tiles from GameSession
-        let msg = ServerMessage::TurnChanged {
-            current_turn: active_game.game.current_turn,
-            player_times_ms: active_game.game.player_times_ms.clone(),
-            player_gold: active_game.game.player_gold.clone(),
-            units: active_game.game.units.clone(),
-            cities: active_game.game.cities.clone(),
-            explored_tiles: active_game.game.explored_tiles.clone(),
+        // The server's clock is authoritative for time used
+        let time_used_ms = current_time_ms().saturating_sub(active_game.game.turn_started_at_ms);
+        tracing::info!("Time used: {}ms", time_used_ms);
+        let command = GameCommand::EndTurn {
+            player_id: player_id.to_string(),
+            time_used_ms,
         };
-        let _ = active_game.channel.send(serde_json::to_string(&msg).unwrap());
-
-        Ok(active_game.game.clone())
+        let events = apply_and_broadcast(active_game, command)?;
+        let saved = active_game.autosave(self.autosave_config);
+        drop(games);
+        wait_for(saved).await;
+        Ok(events)
     }
 
-    pub async fn get_game(&self, game_id: &str) -> Option<GameSession> {
-        let games = self.active_games.read().await;
-        games.get(game_id).map(|g| g.game.clone())
-    }
+    /// Validate and apply a player command, then broadcast the resulting events to everyone
+    /// in the game
+    pub async fn apply_command(&self, game_id: &str, command: GameCommand) -> Result<Vec<GameEvent>, GameError> {
+        tracing::info!("apply_command called: game_id={}, command={:?}", game_id, command);
 
-    pub async fn move_unit(&self, game_id: &str, player_id: &str, unit_id: &str, to_q: i32, to_r: i32) -> Result<palmietopia_core::MoveOutcome, String> {
-        tracing::info!("move_unit called: game_id={}, player_id={}, unit_id={}", game_id, player_id, unit_id);
-        
         let mut games = self.active_games.write().await;
         let active_game = games.get_mut(game_id).ok_or_else(|| {
             tracing::error!("Game not found: {}", game_id);
-            "Game not found".to_string()
+            GameError::GameNotFound
         })?;
 
-        // Verify it's this player's turn
-        let current_player = &active_game.game.players[active_game.game.current_turn];
-        if current_player.id != player_id {
-            tracing::error!("Not your turn: expected={}, got={}", current_player.id, player_id);
-            return Err("Not your turn".to_string());
-        }
-
-        // Verify the unit belongs to the player
-        let unit = active_game.game.units.iter().find(|u| u.id == unit_id)
-            .ok_or("Unit not found")?;
-        if unit.owner_id != player_id {
-            return Err("Not your unit".to_string());
-        }
-
-        // Perform the move (validates and updates position, may capture city)
-        let outcome = active_game.game.move_unit(unit_id, to_q, to_r)?;
-
-        // Broadcast the move to all players (includes updated exploration)
-        let msg = ServerMessage::UnitMoved {
-            unit_id: unit_id.to_string(),
-            to_q,
-            to_r,
-            movement_remaining: outcome.movement_remaining,
-            explored_tiles: active_game.game.explored_tiles.clone(),
-        };
-        let _ = active_game.channel.send(serde_json::to_string(&msg).unwrap());
-
-        // If a player was eliminated, broadcast that
-        if let Some(ref eliminated_id) = outcome.eliminated_player {
-            let elim_msg = ServerMessage::PlayerEliminated {
-                player_id: eliminated_id.clone(),
-                conquerer_id: player_id.to_string(),
-            };
-            let _ = active_game.channel.send(serde_json::to_string(&elim_msg).unwrap());
-
-            // Broadcast updated cities
-            let cities_msg = ServerMessage::CitiesCaptured {
-                cities: active_game.game.cities.clone(),
-            };
-            let _ = active_game.channel.send(serde_json::to_string(&cities_msg).unwrap());
-        } else if outcome.captured_city.is_some() {
-            // Just a regular city capture (non-capitol)
-            let cities_msg = ServerMessage::CitiesCaptured {
-                cities: active_game.game.cities.clone(),
-            };
-            let _ = active_game.channel.send(serde_json::to_string(&cities_msg).unwrap());
-        }
+        let events = apply_and_broadcast(active_game, command)?;
+        let saved = active_game.autosave(self.autosave_config);
+        drop(games);
+        wait_for(saved).await;
+        Ok(events)
+    }
 
-        // If game is over, broadcast victory
-        if let palmietopia_core::GameStatus::Victory { ref winner_id } = active_game.game.status {
-            let victory_msg = ServerMessage::GameOver {
-                winner_id: winner_id.clone(),
-            };
-            let _ = active_game.channel.send(serde_json::to_string(&victory_msg).unwrap());
+    /// Run `change` on a saved game unless it is being played. None if the game is running.
+    /// Only the check is made with the games locked, so the store is never waited on while every
+    /// game is held up. Games only start from a lobby or as the server starts, so a saved game
+    /// can't come alive while `change` runs.
+    pub async fn unless_running<T>(&self, game_id: &str, change: impl AsyncFnOnce() -> T) -> Option<T> {
+        if self.active_games.read().await.contains_key(game_id) {
+            return None;
         }
-
-        Ok(outcome)
+        Some(change().await)
     }
 
-    pub async fn attack_unit(&self, game_id: &str, player_id: &str, attacker_id: &str, defender_id: &str) -> Result<palmietopia_core::CombatOutcome, String> {
-        tracing::info!("attack_unit called: game_id={}, player_id={}, attacker={}, defender={}", 
-            game_id, player_id, attacker_id, defender_id);
-        
-        let mut games = self.active_games.write().await;
-        let active_game = games.get_mut(game_id).ok_or_else(|| {
-            tracing::error!("Game not found: {}", game_id);
-            "Game not found".to_string()
-        })?;
-
-        // Verify it's this player's turn
-        let current_player = &active_game.game.players[active_game.game.current_turn];
-        if current_player.id != player_id {
-            return Err("Not your turn".to_string());
-        }
+    pub async fn get_game(&self, game_id: &str) -> Option<GameSession> {
+        let games = self.active_games.read().await;
+        games.get(game_id).map(|g| g.game.clone())
+    }
 
-        // Verify the attacker belongs to the player
-        let attacker = active_game.game.units.iter().find(|u| u.id == attacker_id)
-            .ok_or("Attacker not found")?;
-        if attacker.owner_id != player_id {
-            return Err("Not your unit".to_string());
-        }
+    /// A player's full view of the game with the sequence number it is current as of, sent on
+    /// join and to clients too far behind to catch up message by message
+    pub async fn snapshot(&self, game_id: &str, player_id: &str) -> Option<(u64, PlayerView)> {
+        let games = self.active_games.read().await;
+        games.get(game_id).and_then(|g| g.snapshot(player_id))
+    }
 
-        // Resolve combat
-        let outcome = active_game.game.resolve_combat(attacker_id, defender_id)?;
-
-        // Broadcast combat result
-        let msg = ServerMessage::CombatResult {
-            attacker_id: attacker_id.to_string(),
-            defender_id: defender_id.to_string(),
-            attacker_hp: outcome.attacker_hp,
-            defender_hp: outcome.defender_hp,
-            damage_to_attacker: outcome.damage_to_attacker,
-            damage_to_defender: outcome.damage_to_defender,
-            attacker_died: outcome.attacker_died,
-            defender_died: outcome.defender_died,
-            attacker_new_q: outcome.attacker_new_q,
-            attacker_new_r: outcome.attacker_new_r,
-        };
-        let _ = active_game.channel.send(serde_json::to_string(&msg).unwrap());
+    pub async fn player_channel(&self, game_id: &str, player_id: &str) -> Option<broadcast::Sender<String>> {
+        let games = self.active_games.read().await;
+        games.get(game_id).and_then(|g| g.feeds.get(player_id).map(|feed| feed.tx.clone()))
+    }
 
-        // If a player was eliminated, broadcast that too
-        if let Some(ref eliminated_id) = outcome.eliminated_player {
-            let elim_msg = ServerMessage::PlayerEliminated {
-                player_id: eliminated_id.clone(),
-                conquerer_id: player_id.to_string(),
-            };
-            let _ = active_game.channel.send(serde_json::to_string(&elim_msg).unwrap());
+    /// What a client needs to catch up after the last message it got was `since_seq`
+    pub async fn resync(&self, game_id: &str, player_id: &str, since_seq: u64) -> Option<Resync> {
+        let games = self.active_games.read().await;
+        games.get(game_id).and_then(|g| g.resync(player_id, since_seq))
+    }
 
-            // Broadcast updated cities
-            let cities_msg = ServerMessage::CitiesCaptured {
-                cities: active_game.game.cities.clone(),
-            };
-            let _ = active_game.channel.send(serde_json::to_string(&cities_msg).unwrap());
-        }
+    /// A socket joined the game as this player
+    pub async fn player_connected(&self, game_id: &str, player_id: &str) {
+        self.update_presence(game_id, player_id, |p| p.connect()).await;
+    }
 
-        // If game is over, broadcast victory
-        if let palmietopia_core::GameStatus::Victory { ref winner_id } = active_game.game.status {
-            let victory_msg = ServerMessage::GameOver {
-                winner_id: winner_id.clone(),
-            };
-            let _ = active_game.channel.send(serde_json::to_string(&victory_msg).unwrap());
-        }
+    /// A socket that joined the game as this player closed
+    pub async fn player_disconnected(&self, game_id: &str, player_id: &str) {
+        self.update_presence(game_id, player_id, |p| p.disconnect(current_time_ms())).await;
+    }
 
-        Ok(outcome)
+    pub async fn set_away(&self, game_id: &str, player_id: &str, away: bool) {
+        self.update_presence(game_id, player_id, |p| p.set_away(away)).await;
     }
 
-    pub async fn fortify_unit(&self, game_id: &str, player_id: &str, unit_id: &str) -> Result<u32, String> {
-        tracing::info!("fortify_unit called: game_id={}, player_id={}, unit_id={}", game_id, player_id, unit_id);
-        
+    async fn update_presence(&self, game_id: &str, player_id: &str, update: impl FnOnce(&mut PlayerPresence) -> bool) {
         let mut games = self.active_games.write().await;
-        let active_game = games.get_mut(game_id).ok_or_else(|| {
-            tracing::error!("Game not found: {}", game_id);
-            "Game not found".to_string()
-        })?;
-
-        // Verify it's this player's turn
-        let current_player = &active_game.game.players[active_game.game.current_turn];
-        if current_player.id != player_id {
-            return Err("Not your turn".to_string());
-        }
-
-        // Verify the unit belongs to the player
-        let unit = active_game.game.units.iter().find(|u| u.id == unit_id)
-            .ok_or("Unit not found")?;
-        if unit.owner_id != player_id {
-            return Err("Not your unit".to_string());
+        let Some(active_game) = games.get_mut(game_id) else {
+            return;
+        };
+        let Some(presence) = active_game.presence.get_mut(player_id) else {
+            return;
+        };
+        if update(presence) {
+            tracing::info!("Player {} is now {:?} in game {}", player_id, presence.presence, game_id);
+            active_game.broadcast_presence(player_id, self.presence_config.grace_period);
         }
+    }
 
-        // Perform fortify
-        let new_hp = active_game.game.fortify_unit(unit_id)?;
-
-        // Broadcast the fortify to all players
-        let msg = ServerMessage::UnitFortified {
-            unit_id: unit_id.to_string(),
-            new_hp,
+    /// Presence of the players who aren't simply connected, for a client that just (re)joined
+    pub async fn presence_report(&self, game_id: &str) -> Vec<ServerMessage> {
+        let games = self.active_games.read().await;
+        let Some(active_game) = games.get(game_id) else {
+            return Vec::new();
         };
-        let _ = active_game.channel.send(serde_json::to_string(&msg).unwrap());
-
-        Ok(new_hp)
+        active_game.presence
+            .iter()
+            .filter(|(_, p)| p.presence != palmietopia_core::Presence::Connected)
+            .filter_map(|(id, _)| active_game.presence_message(id, self.presence_config.grace_period))
+            .collect()
     }
+}
 
-    pub async fn buy_unit(&self, game_id: &str, player_id: &str, city_id: &str, unit_type: palmietopia_core::UnitType) -> Result<(palmietopia_core::Unit, u64), String> {
-        tracing::info!("buy_unit called: game_id={}, player_id={}, city_id={}, unit_type={:?}", 
-            game_id, player_id, city_id, unit_type);
-        
-        let mut games = self.active_games.write().await;
-        let active_game = games.get_mut(game_id).ok_or_else(|| {
-            tracing::error!("Game not found: {}", game_id);
-            "Game not found".to_string()
-        })?;
-
-        // Verify it's this player's turn
-        let current_player = &active_game.game.players[active_game.game.current_turn];
-        if current_player.id != player_id {
-            return Err("Not your turn".to_string());
-        }
+fn visible_unit_ids(game: &GameSession, player_id: &str) -> HashSet<String> {
+    game.visible_units(player_id).into_iter().map(|u| u.id).collect()
+}
 
-        // Buy the unit
-        let unit = active_game.game.buy_unit(player_id, city_id, unit_type)?;
-        
-        // Get player's new gold amount
-        let player_idx = active_game.game.players.iter().position(|p| p.id == player_id).unwrap();
-        let player_gold = active_game.game.player_gold[player_idx];
-
-        // Broadcast the purchase to all players
-        let msg = ServerMessage::UnitPurchased {
-            unit: unit.clone(),
-            city_id: city_id.to_string(),
-            player_gold,
-        };
-        let _ = active_game.channel.send(serde_json::to_string(&msg).unwrap());
+/// Apply a command, record it in the replay and broadcast what it did
+fn apply_and_broadcast(active_game: &mut ActiveGame, command: GameCommand) -> Result<Vec<GameEvent>, GameError> {
+    broadcast_change(active_game, |active_game| {
+        let events = active_game.game.apply(command.clone())?;
+        active_game.replay.record(current_time_ms(), command);
+        Ok(events)
+    })
+}
 
-        Ok((unit, player_gold))
+/// Make a change to the game and send each player the events it produced that they are allowed
+/// to see, followed by the resulting change to their view
+fn broadcast_change(
+    active_game: &mut ActiveGame,
+    change: impl FnOnce(&mut ActiveGame) -> Result<Vec<GameEvent>, GameError>,
+) -> Result<Vec<GameEvent>, GameError> {
+    let seen_before: HashMap<String, HashSet<String>> = active_game.game.players
+        .iter()
+        .map(|p| (p.id.clone(), visible_unit_ids(&active_game.game, &p.id)))
+        .collect();
+
+    let events = change(active_game)?;
+    if events.iter().any(|e| matches!(e, GameEvent::TurnEnded { .. })) {
+        active_game.game.turn_started_at_ms = current_time_ms();
     }
 
-    pub async fn get_channel_async(&self, game_id: &str) -> Option<broadcast::Sender<String>> {
-        let games = self.active_games.read().await;
-        games.get(game_id).map(|g| g.channel.clone())
+    let game = &active_game.game;
+    let mut outgoing = Vec::new();
+    for player in &game.players {
+        let before = &seen_before[&player.id];
+        let after = visible_unit_ids(game, &player.id);
+        for event in &events {
+            if let Some(msg) = event_message_for(game, event, &player.id, before, &after) {
+                outgoing.push((player.id.clone(), msg));
+            }
+        }
     }
+    for (player_id, msg) in outgoing {
+        active_game.send_to(&player_id, msg);
+    }
+    active_game.send_deltas();
+    Ok(events)
 }
 
-impl Default for GameManager {
-    fn default() -> Self {
-        Self::new()
-    }
+/// The message a player receives for a game event, or None if it happened out of their sight.
+/// `before` and `after` are the units the player could see before and after the command. Events
+/// say what happened; the state it left behind reaches each player as a `StateDelta`.
+fn event_message_for(
+    game: &GameSession,
+    event: &GameEvent,
+    viewer: &str,
+    before: &HashSet<String>,
+    after: &HashSet<String>,
+) -> Option<ServerMessage> {
+    let owns = |unit_id: &str| game.unit(unit_id).is_some_and(|u| u.owner_id == viewer);
+    // Units that walk into view arrive whole in the StateDelta instead
+    let watched = |unit_id: &str| before.contains(unit_id) && after.contains(unit_id);
+
+    let msg = match event.clone() {
+        GameEvent::UnitMoved { unit_id, to_q, to_r, movement_remaining } => {
+            if !watched(&unit_id) {
+                return None;
+            }
+            ServerMessage::UnitMoved {
+                unit_id,
+                to_q,
+                to_r,
+                movement_remaining,
+            }
+        }
+        GameEvent::UnitMovedAlongPath { unit_id, path, movement_remaining } => {
+            if owns(&unit_id) {
+                ServerMessage::UnitMovedAlongPath {
+                    unit_id,
+                    path,
+                    movement_remaining,
+                }
+            } else if watched(&unit_id) {
+                // Only the end point: the route may have crossed tiles the viewer can't see
+                let &(to_q, to_r) = path.last()?;
+                ServerMessage::UnitMoved {
+                    unit_id,
+                    to_q,
+                    to_r,
+                    movement_remaining,
+                }
+            } else {
+                return None;
+            }
+        }
+        GameEvent::CombatResolved {
+            attacker_id,
+            defender_id,
+            attacker_hp,
+            defender_hp,
+            damage_to_attacker,
+            damage_to_defender,
+            attacker_died,
+            defender_died,
+            attacker_new_q,
+            attacker_new_r,
+        } => {
+            let attacker_seen = before.contains(&attacker_id);
+            if !attacker_seen && !before.contains(&defender_id) {
+                return None;
+            }
+            // A defender shot at from the fog learns what it suffered, not who did it. If the
+            // attacker advances into view it arrives in the StateDelta.
+            ServerMessage::CombatResult {
+                attacker_id: attacker_seen.then_some(attacker_id),
+                defender_id,
+                attacker_hp: attacker_seen.then_some(attacker_hp),
+                defender_hp,
+                damage_to_attacker: attacker_seen.then_some(damage_to_attacker),
+                damage_to_defender,
+                attacker_died: attacker_seen.then_some(attacker_died),
+                defender_died,
+                attacker_new_q: attacker_new_q.filter(|_| attacker_seen),
+                attacker_new_r: attacker_new_r.filter(|_| attacker_seen),
+            }
+        }
+        GameEvent::UnitFortified { unit_id, new_hp } => {
+            if !after.contains(&unit_id) {
+                return None;
+            }
+            ServerMessage::UnitFortified { unit_id, new_hp }
+        }
+        GameEvent::UnitPurchased { unit, city_id, player_gold } => {
+            // Rivals who can see the city learn about the unit from their StateDelta
+            if unit.owner_id != viewer {
+                return None;
+            }
+            ServerMessage::UnitPurchased { unit, city_id, player_gold }
+        }
+        GameEvent::PlayerEliminated { player_id, conquerer_id } => ServerMessage::PlayerEliminated { player_id, conquerer_id },
+        GameEvent::PlayerResigned { player_id } => ServerMessage::PlayerResigned { player_id },
+        GameEvent::DrawOffered { player_id } => ServerMessage::DrawOffered { player_id },
+        GameEvent::DrawAccepted { player_id } => ServerMessage::DrawAccepted { player_id },
+        GameEvent::GameDrawn => ServerMessage::GameDrawn,
+        GameEvent::GameAborted { player_id } => ServerMessage::GameAborted { player_id },
+        GameEvent::CitiesCaptured { city_ids } => {
+            // Only captures the viewer can see
+            let known = game.known_cities(viewer);
+            let city_ids: Vec<String> = city_ids
+                .into_iter()
+                .filter(|id| known.iter().any(|c| &c.id == id))
+                .collect();
+            if city_ids.is_empty() {
+                return None;
+            }
+            ServerMessage::CitiesCaptured { city_ids }
+        }
+        GameEvent::TurnEnded { current_turn } => ServerMessage::TurnChanged {
+            current_turn,
+            player_times_ms: game.player_times_ms.clone(),
+        },
+        GameEvent::GameWon { winner_id } => ServerMessage::GameOver { winner_id },
+    };
+    Some(msg)
 }
 
 pub fn current_time_ms() -> u64 {
@@ -317,7 +607,64 @@ pub fn current_time_ms() -> u64 {
         .as_millis() as u64
 }
 
-async fn run_game_timer(game_id: String, games: Arc<RwLock<HashMap<String, ActiveGame>>>) {
+/// Let a command return once what it changed is in the store, without holding any lock
+/// while the store is written
+async fn wait_for(saved: Option<oneshot::Receiver<()>>) {
+    if let Some(saved) = saved {
+        let _ = saved.await;
+    }
+}
+
+/// Apply the disconnect policy to players who have been gone for the whole grace period
+fn enforce_presence(active_game: &mut ActiveGame, config: &PresenceConfig) {
+    let now = current_time_ms();
+    let newly_expired: Vec<String> = active_game.presence
+        .iter_mut()
+        .filter_map(|(id, p)| p.expire(now, config.grace_period).then(|| id.clone()))
+        .collect();
+    for player_id in &newly_expired {
+        tracing::info!("Grace period ran out for player {}, applying {:?}", player_id, config.policy);
+        active_game.broadcast_presence(player_id, config.grace_period);
+        if config.policy == DisconnectPolicy::Resign && !active_game.game.eliminated_players.contains(player_id) {
+            let command = GameCommand::Resign { player_id: player_id.clone() };
+            if let Err(e) = apply_and_broadcast(active_game, command) {
+                tracing::error!("Resigning disconnected player failed: {}", e);
+            }
+        }
+    }
+
+    // Skipping: the turn passes on as soon as it reaches an expired player
+    let game = &active_game.game;
+    if config.policy != DisconnectPolicy::SkipTurns || game.status != palmietopia_core::GameStatus::InProgress {
+        return;
+    }
+    // With nobody left to come back to the game, skipping would go round forever: stop it
+    let expired = |id: &String| active_game.presence.get(id).is_some_and(|p| p.expired);
+    let mut remaining = game.players.iter().map(|p| &p.id).filter(|id| !game.eliminated_players.contains(id));
+    if remaining.all(expired) {
+        tracing::info!("Every player has left game {}, aborting it", game.id);
+        let _ = broadcast_change(active_game, |active_game| Ok(active_game.game.abandon()));
+        return;
+    }
+    let current_id = game.players[game.current_turn].id.clone();
+    if active_game.presence.get(&current_id).is_some_and(|p| p.expired) {
+        tracing::info!("Skipping turn of disconnected player {}", current_id);
+        let command = GameCommand::EndTurn {
+            player_id: current_id,
+            time_used_ms: now.saturating_sub(game.turn_started_at_ms),
+        };
+        if let Err(e) = apply_and_broadcast(active_game, command) {
+            tracing::error!("Skipping turn failed: {}", e);
+        }
+    }
+}
+
+async fn run_game_timer(
+    game_id: String,
+    games: Arc<RwLock<HashMap<String, ActiveGame>>>,
+    presence_config: PresenceConfig,
+    autosave_config: AutosaveConfig,
+) {
     let mut tick_interval = interval(Duration::from_secs(1));
 
     loop {
@@ -326,10 +673,20 @@ async fn run_game_timer(game_id: String, games: Arc<RwLock<HashMap<String, Activ
         {
             let mut games_lock = games.write().await;
             if let Some(active_game) = games_lock.get_mut(&game_id) {
-                // Stop timer if game is over
-                if let palmietopia_core::GameStatus::Victory { .. } = active_game.game.status {
-                    tracing::info!("Game {} ended (victory), stopping timer and cleaning up", game_id);
-                    games_lock.remove(&game_id);
+                // Stop timer if game is over: won, drawn or aborted
+                if active_game.game.status != palmietopia_core::GameStatus::InProgress {
+                    tracing::info!(
+                        "Game {} ended ({:?}), stopping timer and cleaning up",
+                        game_id,
+                        active_game.game.status
+                    );
+                    // Keep the finished game and its replay so it can be reviewed after it leaves
+                    // memory
+                    let finished = games_lock.remove(&game_id).unwrap();
+                    drop(games_lock);
+                    let archived = finished.save_snapshot(Vec::new());
+                    drop(finished);
+                    let _ = archived.await;
                     break;
                 }
 
@@ -343,26 +700,24 @@ async fn run_game_timer(game_id: String, games: Arc<RwLock<HashMap<String, Activ
                     player_index: active_game.game.current_turn,
                     remaining_ms: remaining,
                 };
-                let _ = active_game.channel.send(serde_json::to_string(&tick_msg).unwrap());
+                active_game.send_all_unsequenced(&tick_msg);
 
                 // Auto-end turn if time runs out
                 if remaining == 0 {
                     tracing::info!("Auto-ending turn for player {} (time ran out)", active_game.game.current_turn);
                     
                     // End turn with full time used (they ran out)
-                    active_game.game.end_current_turn(current_player_time);
-                    active_game.game.turn_started_at_ms = now;
-
-                    let turn_msg = ServerMessage::TurnChanged {
-                        current_turn: active_game.game.current_turn,
-                        player_times_ms: active_game.game.player_times_ms.clone(),
-                        player_gold: active_game.game.player_gold.clone(),
-                        units: active_game.game.units.clone(),
-                        cities: active_game.game.cities.clone(),
-                        explored_tiles: active_game.game.explored_tiles.clone(),
+                    let command = GameCommand::EndTurn {
+                        player_id: active_game.game.players[active_game.game.current_turn].id.clone(),
+                        time_used_ms: current_player_time,
                     };
-                    let _ = active_game.channel.send(serde_json::to_string(&turn_msg).unwrap());
+                    if let Err(e) = apply_and_broadcast(active_game, command) {
+                        tracing::error!("Auto end turn failed: {}", e);
+                    }
                 }
+
+                enforce_presence(active_game, &presence_config);
+                active_game.autosave(autosave_config);
             } else {
                 // Game no longer exists, stop the timer
                 break;
@@ -370,3 +725,75 @@ async fn run_game_timer(game_id: String, games: Arc<RwLock<HashMap<String, Activ
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::memory::InMemoryStore;
+    use crate::testing;
+    use palmietopia_core::{Terrain, Unit, UnitType};
+
+    /// p1's Bowman two tiles from p2's Conscript with a forest between them: the Bowman sees
+    /// over it, the Conscript doesn't
+    fn ambush() -> ActiveGame {
+        let mut game = testing::game("game", &["p1", "p2"]);
+        for tile in &mut game.map.tiles {
+            tile.terrain = if (tile.q, tile.r) == (1, 0) { Terrain::Forest } else { Terrain::Grassland };
+        }
+        game.cities.clear();
+        game.units = vec![
+            Unit::new("bowman".to_string(), "p1".to_string(), UnitType::Bowman, 2, 0),
+            Unit::new("conscript".to_string(), "p2".to_string(), UnitType::Conscript, 0, 0),
+        ];
+        game.reindex();
+        let replay = Replay::new(game.clone(), 0);
+        ActiveGame::new(game, replay, Arc::new(InMemoryStore::new()))
+    }
+
+    fn combat_result(feed: &mut broadcast::Receiver<String>) -> ServerMessage {
+        loop {
+            let sequenced: SequencedMessage = serde_json::from_str(&feed.try_recv().unwrap()).unwrap();
+            if matches!(sequenced.message, ServerMessage::CombatResult { .. }) {
+                return sequenced.message;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn attackers_in_the_fog_stay_hidden_from_their_target() {
+        let mut active_game = ambush();
+        assert!(!visible_unit_ids(&active_game.game, "p2").contains("bowman"));
+        let mut attacker_feed = active_game.feeds["p1"].tx.subscribe();
+        let mut defender_feed = active_game.feeds["p2"].tx.subscribe();
+
+        let command = GameCommand::AttackUnit {
+            player_id: "p1".to_string(),
+            attacker_id: "bowman".to_string(),
+            defender_id: "conscript".to_string(),
+        };
+        apply_and_broadcast(&mut active_game, command).unwrap();
+
+        let ServerMessage::CombatResult { attacker_id, attacker_hp, defender_id, defender_hp, .. } =
+            combat_result(&mut attacker_feed)
+        else {
+            unreachable!()
+        };
+        assert_eq!(attacker_id.as_deref(), Some("bowman"));
+        assert!(attacker_hp.is_some());
+
+        let ServerMessage::CombatResult {
+            attacker_id: hidden_id,
+            attacker_hp: hidden_hp,
+            damage_to_attacker,
+            attacker_died,
+            defender_id: seen_defender,
+            defender_hp: seen_hp,
+            ..
+        } = combat_result(&mut defender_feed)
+        else {
+            unreachable!()
+        };
+        assert_eq!((hidden_id, hidden_hp, damage_to_attacker, attacker_died), (None, None, None, None));
+        assert_eq!((seen_defender, seen_hp), (defender_id, defender_hp), "the target still learns what it took");
+    }
+}
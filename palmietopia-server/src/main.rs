@@ -1,39 +1,64 @@
+mod admin;
+mod autosave;
 mod game;
+mod presence;
 mod state;
 mod store;
+#[cfg(test)]
+mod testing;
 mod ws;
 
 use axum::{
-    extract::{State, WebSocketUpgrade},
+    extract::{Path, Query, State, WebSocketUpgrade},
+    http::StatusCode,
     response::IntoResponse,
-    routing::get,
+    routing::{delete, get, put},
     Json, Router,
 };
-use palmietopia_core::Lobby;
+use palmietopia_core::{GameSession, Lobby, Replay};
 use std::sync::Arc;
 use tower_http::cors::{Any, CorsLayer};
-use tracing_subscriber;
 
+use admin::AdminConfig;
+use autosave::AutosaveConfig;
+use presence::PresenceConfig;
 use state::AppState;
+use store::json_file::JsonFileStore;
 use store::memory::InMemoryStore;
+use store::sqlite::SqliteStore;
+use store::{GameFilter, GameState, GameStore, GameSummary, StoreError};
 
 #[tokio::main]
 async fn main() {
     tracing_subscriber::fmt::init();
 
-    let store = Arc::new(InMemoryStore::new());
-    let app_state = Arc::new(AppState::new(store));
+    let store = open_store();
+    let mut app_state = AppState::new(store, PresenceConfig::from_env(), AutosaveConfig::from_env());
+    app_state.admin = AdminConfig::from_env();
+    let app_state = Arc::new(app_state);
+    match app_state.game_manager.resume_games().await {
+        Ok(0) => {}
+        Ok(count) => tracing::info!("Resumed {} games in progress", count),
+        Err(e) => tracing::error!("Failed to load games in progress: {}", e),
+    }
 
     let cors = CorsLayer::new()
         .allow_origin(Any)
         .allow_methods(Any)
         .allow_headers(Any);
 
+    // Admin routes are added after the CORS layer, so web pages on other sites can't call them
     let app = Router::new()
         .route("/ws", get(ws_handler))
         .route("/api/lobbies", get(list_lobbies))
+        .route("/api/games", get(list_games))
+        .route("/api/replays", get(list_replays))
+        .route("/api/replays/{game_id}", get(get_replay))
+        .route("/api/replays/{game_id}/turns/{turn}", get(get_replay_turn))
         .route("/health", get(health_check))
         .layer(cors)
+        .route("/api/games/{game_id}", delete(admin::delete_game))
+        .route("/api/games/{game_id}/status", put(admin::update_game_status))
         .with_state(app_state);
 
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3001").await.unwrap();
@@ -41,6 +66,38 @@ async fn main() {
     axum::serve(listener, app).await.unwrap();
 }
 
+/// The store named by `STORE`: `memory` (the default, lost on restart), `sqlite`, kept in the
+/// file at `SQLITE_PATH` (default `palmietopia.db`), or `json`, a directory of JSON files at
+/// `JSON_STORE_DIR` (default `palmietopia-data`) that `JSON_STORE_FIXTURES` is copied into first
+fn open_store() -> Arc<dyn GameStore> {
+    match std::env::var("STORE").as_deref() {
+        Ok("sqlite") => {
+            let path = std::env::var("SQLITE_PATH").unwrap_or_else(|_| "palmietopia.db".to_string());
+            tracing::info!("Using SQLite store at {}", path);
+            match SqliteStore::open(&path) {
+                Ok(store) => Arc::new(store),
+                Err(e) => panic!("Failed to open SQLite store at {}: {}", path, e),
+            }
+        }
+        Ok("json") => {
+            let dir = std::env::var("JSON_STORE_DIR").unwrap_or_else(|_| "palmietopia-data".to_string());
+            if let Ok(fixtures) = std::env::var("JSON_STORE_FIXTURES") {
+                tracing::info!("Installing fixtures from {}", fixtures);
+                if let Err(e) = JsonFileStore::install_fixtures(fixtures.as_ref(), dir.as_ref()) {
+                    panic!("Failed to install fixtures from {}: {}", fixtures, e);
+                }
+            }
+            tracing::info!("Using JSON file store in {}", dir);
+            match JsonFileStore::open(&dir) {
+                Ok(store) => Arc::new(store),
+                Err(e) => panic!("Failed to open JSON file store in {}: {}", dir, e),
+            }
+        }
+        Ok("memory") | Err(_) => Arc::new(InMemoryStore::new()),
+        Ok(other) => panic!("Unknown STORE: {} (expected memory, sqlite or json)", other),
+    }
+}
+
 async fn ws_handler(
     ws: WebSocketUpgrade,
     State(state): State<Arc<AppState>>,
@@ -57,6 +114,69 @@ async fn list_lobbies(State(state): State<Arc<AppState>>) -> Json<Vec<Lobby>> {
     Json(visible)
 }
 
+fn store_error(e: StoreError) -> (StatusCode, String) {
+    match e {
+        StoreError::NotFound => (StatusCode::NOT_FOUND, "Game not found".to_string()),
+        e => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
+
+/// Saved games matching the query, e.g. `?status=victory&player_id=...&limit=20&offset=40`
+async fn list_games(
+    Query(filter): Query<GameFilter>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<GameSummary>>, (StatusCode, String)> {
+    state.store.list_games(&filter).await.map(Json).map_err(store_error)
+}
+
+/// Ids of games still being played. Their replays are saved as they go, but showing one would
+/// give away everything the fog of war hides.
+async fn running_game_ids(state: &AppState) -> Result<Vec<String>, StoreError> {
+    let running = state.store.list_games(&GameFilter::with_status(GameState::InProgress)).await?;
+    Ok(running.into_iter().map(|g| g.id).collect())
+}
+
+/// The replay of a game that is over
+async fn finished_replay(state: &AppState, game_id: &str) -> Result<Option<Replay>, StoreError> {
+    if running_game_ids(state).await?.iter().any(|id| id == game_id) {
+        return Ok(None);
+    }
+    state.store.get_replay(game_id).await
+}
+
+async fn list_replays(State(state): State<Arc<AppState>>) -> Json<Vec<String>> {
+    let running = running_game_ids(&state).await.unwrap_or_default();
+    let replays = state.store.list_replays().await.unwrap_or_default();
+    Json(replays.into_iter().filter(|id| !running.contains(id)).collect())
+}
+
+async fn get_replay(
+    Path(game_id): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Replay>, StatusCode> {
+    match finished_replay(&state, &game_id).await {
+        Ok(Some(replay)) => Ok(Json(replay)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+/// The game as it stood at the start of a turn, rebuilt from the replay
+async fn get_replay_turn(
+    Path((game_id, turn)): Path<(String, usize)>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<GameSession>, (StatusCode, String)> {
+    let replay = match finished_replay(&state, &game_id).await {
+        Ok(Some(replay)) => replay,
+        Ok(None) => return Err((StatusCode::NOT_FOUND, "Replay not found".to_string())),
+        Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    };
+    replay
+        .session_at_turn(turn)
+        .map(Json)
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, format!("Replay diverged: {}", e)))
+}
+
 async fn health_check() -> &'static str {
     "OK"
 }
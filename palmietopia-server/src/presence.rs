@@ -0,0 +1,142 @@
+use palmietopia_core::Presence;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// How long a disconnected player has to come back before the disconnect policy applies
+pub const DEFAULT_GRACE_PERIOD: Duration = Duration::from_secs(60);
+
+/// What happens to a player who stays disconnected past the grace period
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisconnectPolicy {
+    /// Their turns are ended as soon as they start. They can come back and pick up where they
+    /// left off.
+    SkipTurns,
+    /// They resign from the game
+    Resign,
+}
+
+impl FromStr for DisconnectPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" | "skip_turns" => Ok(DisconnectPolicy::SkipTurns),
+            "resign" => Ok(DisconnectPolicy::Resign),
+            _ => Err(format!("Unknown disconnect policy: {}", s)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PresenceConfig {
+    pub grace_period: Duration,
+    pub policy: DisconnectPolicy,
+}
+
+impl Default for PresenceConfig {
+    fn default() -> Self {
+        Self {
+            grace_period: DEFAULT_GRACE_PERIOD,
+            policy: DisconnectPolicy::SkipTurns,
+        }
+    }
+}
+
+impl PresenceConfig {
+    /// Read `DISCONNECT_GRACE_SECS` and `DISCONNECT_POLICY` (`skip` or `resign`), falling back
+    /// to the defaults for anything unset or invalid
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+        if let Ok(secs) = std::env::var("DISCONNECT_GRACE_SECS") {
+            match secs.parse() {
+                Ok(secs) => config.grace_period = Duration::from_secs(secs),
+                Err(_) => tracing::warn!("Ignoring invalid DISCONNECT_GRACE_SECS: {}", secs),
+            }
+        }
+        if let Ok(policy) = std::env::var("DISCONNECT_POLICY") {
+            match policy.parse() {
+                Ok(policy) => config.policy = policy,
+                Err(e) => tracing::warn!("{}", e),
+            }
+        }
+        config
+    }
+}
+
+/// Whether one player in a game is connected, and for how long they haven't been
+pub struct PlayerPresence {
+    pub presence: Presence,
+    /// Game sockets open for the player
+    sockets: usize,
+    /// When the player was last left without a socket
+    disconnected_at_ms: Option<u64>,
+    /// The grace period ran out; the disconnect policy applies until they come back
+    pub expired: bool,
+}
+
+impl PlayerPresence {
+    /// A player in a game that just started. They count as connected while they load the game,
+    /// but the grace period is already running in case they never do.
+    pub fn joining(now_ms: u64) -> Self {
+        Self {
+            presence: Presence::Connected,
+            sockets: 0,
+            disconnected_at_ms: Some(now_ms),
+            expired: false,
+        }
+    }
+
+    /// When the server stops waiting for the player, if it is waiting
+    pub fn grace_ends_at_ms(&self, grace_period: Duration) -> Option<u64> {
+        if self.presence != Presence::Disconnected || self.expired {
+            return None;
+        }
+        self.disconnected_at_ms.map(|at| at + grace_period.as_millis() as u64)
+    }
+
+    /// A socket joined as this player. Returns true if their presence changed.
+    pub fn connect(&mut self) -> bool {
+        self.sockets += 1;
+        self.disconnected_at_ms = None;
+        self.expired = false;
+        self.set(Presence::Connected)
+    }
+
+    /// A socket for this player closed. Returns true if that left them disconnected.
+    pub fn disconnect(&mut self, now_ms: u64) -> bool {
+        self.sockets = self.sockets.saturating_sub(1);
+        if self.sockets > 0 {
+            return false;
+        }
+        self.disconnected_at_ms = Some(now_ms);
+        self.set(Presence::Disconnected)
+    }
+
+    /// Returns true if their presence changed. Only connected players can be away.
+    pub fn set_away(&mut self, away: bool) -> bool {
+        if self.sockets == 0 {
+            return false;
+        }
+        self.set(if away { Presence::Away } else { Presence::Connected })
+    }
+
+    /// Mark the player expired if they have been without a socket for the whole grace period.
+    /// Returns true the first time that happens.
+    pub fn expire(&mut self, now_ms: u64, grace_period: Duration) -> bool {
+        let Some(since) = self.disconnected_at_ms else {
+            return false;
+        };
+        if self.expired || self.sockets > 0 || now_ms < since + grace_period.as_millis() as u64 {
+            return false;
+        }
+        self.expired = true;
+        self.presence = Presence::Disconnected;
+        true
+    }
+
+    fn set(&mut self, presence: Presence) -> bool {
+        let changed = self.presence != presence;
+        self.presence = presence;
+        changed
+    }
+}
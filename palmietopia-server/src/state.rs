@@ -1,8 +1,11 @@
 use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::{broadcast, RwLock};
+use tokio::sync::{broadcast, mpsc, RwLock};
 
+use crate::admin::AdminConfig;
+use crate::autosave::AutosaveConfig;
 use crate::game::GameManager;
+use crate::presence::PresenceConfig;
 use crate::store::GameStore;
 
 pub type Tx = broadcast::Sender<String>;
@@ -12,6 +15,7 @@ pub struct PlayerConnection {
     pub player_id: String,
     pub lobby_id: Option<String>,
     pub game_id: Option<String>,
+    pub direct: mpsc::UnboundedSender<String>,
 }
 
 pub struct AppState {
@@ -19,15 +23,18 @@ pub struct AppState {
     pub connections: RwLock<HashMap<String, PlayerConnection>>,
     pub lobby_channels: RwLock<HashMap<String, Tx>>,
     pub game_manager: Arc<GameManager>,
+    /// Off until set, e.g. from the environment
+    pub admin: AdminConfig,
 }
 
 impl AppState {
-    pub fn new(store: Arc<dyn GameStore>) -> Self {
+    pub fn new(store: Arc<dyn GameStore>, presence_config: PresenceConfig, autosave_config: AutosaveConfig) -> Self {
         Self {
+            game_manager: Arc::new(GameManager::new(Arc::clone(&store), presence_config, autosave_config)),
             store,
             connections: RwLock::new(HashMap::new()),
             lobby_channels: RwLock::new(HashMap::new()),
-            game_manager: Arc::new(GameManager::new()),
+            admin: AdminConfig::default(),
         }
     }
 
@@ -0,0 +1,368 @@
+use async_trait::async_trait;
+use palmietopia_core::{GameSession, GameStatus, Lobby, Replay, ReplayEntry};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use super::{GameFilter, GameStore, GameSummary, PlayerSession, StoreError, StoreResult};
+use crate::game::current_time_ms;
+
+const LOBBIES: &str = "lobbies";
+const GAMES: &str = "games";
+const SESSIONS: &str = "sessions";
+const REPLAYS: &str = "replays";
+
+/// A game's file: its latest snapshot. Only `game` is required, which keeps fixtures easy to
+/// write by hand.
+#[derive(Clone, Serialize, Deserialize)]
+struct StoredGame {
+    #[serde(default)]
+    created_at_ms: u64,
+    #[serde(default)]
+    updated_at_ms: u64,
+    game: GameSession,
+}
+
+/// A `GameStore` for local development that keeps every lobby, game, reconnect token and
+/// replay as a JSON file under one directory (`lobbies/<id>.json`, `games/<id>.json`, ...).
+/// Everything is read in when the store is opened and written through on each change. Files
+/// are replaced by renaming a freshly written temporary file over them, so a crash never leaves
+/// one half-written. A game's action log is the exception: each action is appended to
+/// `games/<id>.actions.jsonl` as a line of its own, and the file is removed once a snapshot
+/// has been written, so a crash in between can leave actions the snapshot already holds but
+/// never loses one. File access blocks, so each operation runs on tokio's blocking thread pool
+/// rather than on the async workers.
+pub struct JsonFileStore {
+    files: Arc<Files>,
+}
+
+/// The store's directory and everything read in from it
+struct Files {
+    dir: PathBuf,
+    lobbies: RwLock<HashMap<String, Lobby>>,
+    games: RwLock<HashMap<String, StoredGame>>,
+    sessions: RwLock<HashMap<String, PlayerSession>>,
+    replays: RwLock<HashMap<String, Replay>>,
+}
+
+impl JsonFileStore {
+    /// Open the store in `dir`, creating it if needed, and load everything in it
+    pub fn open(dir: impl Into<PathBuf>) -> StoreResult<Self> {
+        let dir = dir.into();
+        for kind in [LOBBIES, GAMES, SESSIONS, REPLAYS] {
+            fs::create_dir_all(dir.join(kind))?;
+        }
+        let mut games: HashMap<String, StoredGame> = load_all(&dir.join(GAMES))?;
+        // The unit index isn't serialized
+        for stored in games.values_mut() {
+            stored.game.reindex();
+        }
+        let files = Files {
+            lobbies: RwLock::new(load_all(&dir.join(LOBBIES))?),
+            games: RwLock::new(games),
+            sessions: RwLock::new(load_all(&dir.join(SESSIONS))?),
+            replays: RwLock::new(load_all(&dir.join(REPLAYS))?),
+            dir,
+        };
+        Ok(Self { files: Arc::new(files) })
+    }
+
+    /// Copy a fixture directory's files into `dir`, replacing files of the same name. The
+    /// server then starts with the fixture's lobbies and games, and playing them leaves the
+    /// fixture itself untouched.
+    pub fn install_fixtures(fixtures: &Path, dir: &Path) -> StoreResult<()> {
+        for kind in [LOBBIES, GAMES, SESSIONS, REPLAYS] {
+            let from = fixtures.join(kind);
+            if !from.is_dir() {
+                continue;
+            }
+            fs::create_dir_all(dir.join(kind))?;
+            for entry in fs::read_dir(&from)? {
+                let path = entry?.path();
+                let stored = is_json(&path) || path.extension().is_some_and(|ext| ext == "jsonl");
+                if let Some(name) = path.file_name().filter(|_| stored) {
+                    fs::copy(&path, dir.join(kind).join(name))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Run `op` with the files on a blocking thread
+    async fn run<T: Send + 'static>(
+        &self,
+        op: impl FnOnce(&Files) -> StoreResult<T> + Send + 'static,
+    ) -> StoreResult<T> {
+        let files = Arc::clone(&self.files);
+        tokio::task::spawn_blocking(move || op(&files))
+            .await
+            .map_err(|e| StoreError::Internal(e.to_string()))?
+    }
+}
+
+impl Files {
+    /// Where the file for `id` lives. Ids become file names, so anything that could step out
+    /// of the directory is refused.
+    fn path(&self, kind: &str, id: &str) -> StoreResult<PathBuf> {
+        if id.is_empty() || id.starts_with('.') || id.contains(['/', '\\']) {
+            return Err(StoreError::Internal(format!("Can't store {:?} as a file", id)));
+        }
+        Ok(self.dir.join(kind).join(format!("{}.json", id)))
+    }
+
+    fn actions_path(&self, game_id: &str) -> StoreResult<PathBuf> {
+        Ok(self.path(GAMES, game_id)?.with_extension("actions.jsonl"))
+    }
+
+    fn remove_actions(&self, game_id: &str) -> StoreResult<()> {
+        match fs::remove_file(self.actions_path(game_id)?) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn write<T: Serialize>(&self, kind: &str, id: &str, value: &T) -> StoreResult<()> {
+        let (tmp, path) = self.stage(kind, id, value)?;
+        fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+
+    /// Write the temporary file that will replace the one for `id`. Returns it and the path
+    /// to rename it to.
+    fn stage<T: Serialize>(&self, kind: &str, id: &str, value: &T) -> StoreResult<(PathBuf, PathBuf)> {
+        let path = self.path(kind, id)?;
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(value)?)?;
+        Ok((tmp, path))
+    }
+
+    fn remove(&self, kind: &str, id: &str) -> StoreResult<()> {
+        match fs::remove_file(self.path(kind, id)?) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "json")
+}
+
+/// Every `<id>.json` file in a directory, by id
+fn load_all<T: DeserializeOwned>(dir: &Path) -> StoreResult<HashMap<String, T>> {
+    let mut values = HashMap::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let Some(id) = path.file_stem().and_then(|s| s.to_str()).filter(|_| is_json(&path)) else {
+            continue;
+        };
+        let value = serde_json::from_slice(&fs::read(&path)?)
+            .map_err(|e| StoreError::Internal(format!("Bad data in {}: {}", path.display(), e)))?;
+        values.insert(id.to_string(), value);
+    }
+    Ok(values)
+}
+
+impl From<io::Error> for StoreError {
+    fn from(e: io::Error) -> Self {
+        StoreError::Internal(e.to_string())
+    }
+}
+
+#[async_trait]
+impl GameStore for JsonFileStore {
+    async fn create_lobby(&self, lobby: Lobby) -> StoreResult<String> {
+        self.run(move |files| {
+            let mut lobbies = files.lobbies.write().unwrap();
+            if lobbies.contains_key(&lobby.id) {
+                return Err(StoreError::AlreadyExists);
+            }
+            files.write(LOBBIES, &lobby.id, &lobby)?;
+            let id = lobby.id.clone();
+            lobbies.insert(id.clone(), lobby);
+            Ok(id)
+        })
+        .await
+    }
+
+    async fn get_lobby(&self, id: &str) -> StoreResult<Option<Lobby>> {
+        let id = id.to_string();
+        self.run(move |files| Ok(files.lobbies.read().unwrap().get(&id).cloned())).await
+    }
+
+    async fn list_lobbies(&self) -> StoreResult<Vec<Lobby>> {
+        self.run(|files| Ok(files.lobbies.read().unwrap().values().cloned().collect())).await
+    }
+
+    async fn update_lobby(&self, lobby: Lobby) -> StoreResult<()> {
+        self.run(move |files| {
+            let mut lobbies = files.lobbies.write().unwrap();
+            files.write(LOBBIES, &lobby.id, &lobby)?;
+            lobbies.insert(lobby.id.clone(), lobby);
+            Ok(())
+        })
+        .await
+    }
+
+    async fn delete_lobby(&self, id: &str) -> StoreResult<()> {
+        let id = id.to_string();
+        self.run(move |files| {
+            let mut lobbies = files.lobbies.write().unwrap();
+            if lobbies.remove(&id).is_some() {
+                files.remove(LOBBIES, &id)?;
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    async fn save_snapshot(&self, game: GameSession, replay: Replay) -> StoreResult<()> {
+        self.run(move |files| {
+            let mut games = files.games.write().unwrap();
+            let mut replays = files.replays.write().unwrap();
+            let now = current_time_ms();
+            let stored = StoredGame {
+                created_at_ms: games.get(&game.id).map_or(now, |g| g.created_at_ms),
+                updated_at_ms: now,
+                game,
+            };
+            // Both files are written out before either replaces anything, so running out of room
+            // changes neither
+            let (replay_tmp, replay_path) = files.stage(REPLAYS, &replay.game_id, &replay)?;
+            let (game_tmp, game_path) = files.stage(GAMES, &stored.game.id, &stored)?;
+            fs::rename(&replay_tmp, &replay_path)?;
+            fs::rename(&game_tmp, &game_path)?;
+            files.remove_actions(&stored.game.id)?;
+            replays.insert(replay.game_id.clone(), replay);
+            games.insert(stored.game.id.clone(), stored);
+            Ok(())
+        })
+        .await
+    }
+
+    async fn load_game(&self, id: &str) -> StoreResult<Option<GameSession>> {
+        let id = id.to_string();
+        self.run(move |files| Ok(files.games.read().unwrap().get(&id).map(|g| g.game.clone()))).await
+    }
+
+    async fn list_games(&self, filter: &GameFilter) -> StoreResult<Vec<GameSummary>> {
+        let filter = filter.clone();
+        self.run(move |files| {
+            let games = files.games.read().unwrap();
+            let summaries = games.values().map(|g| GameSummary::new(&g.game, g.created_at_ms, g.updated_at_ms));
+            Ok(filter.apply(summaries))
+        })
+        .await
+    }
+
+    async fn delete_game(&self, id: &str) -> StoreResult<()> {
+        let id = id.to_string();
+        self.run(move |files| {
+            let mut games = files.games.write().unwrap();
+            if games.remove(&id).is_none() {
+                return Err(StoreError::NotFound);
+            }
+            files.remove_actions(&id)?;
+            files.remove(GAMES, &id)
+        })
+        .await
+    }
+
+    async fn update_game_status(&self, id: &str, status: GameStatus) -> StoreResult<()> {
+        let id = id.to_string();
+        self.run(move |files| {
+            let mut games = files.games.write().unwrap();
+            let mut stored = games.get(&id).cloned().ok_or(StoreError::NotFound)?;
+            stored.game.status = status;
+            stored.updated_at_ms = current_time_ms();
+            files.write(GAMES, &id, &stored)?;
+            games.insert(id, stored);
+            Ok(())
+        })
+        .await
+    }
+
+    async fn append_action(&self, game_id: &str, entry: ReplayEntry) -> StoreResult<()> {
+        let game_id = game_id.to_string();
+        self.run(move |files| {
+            // Held so the log isn't appended to while a snapshot removes it
+            let games = files.games.read().unwrap();
+            if !games.contains_key(&game_id) {
+                return Err(StoreError::NotFound);
+            }
+            let mut line = serde_json::to_vec(&entry)?;
+            line.push(b'\n');
+            let mut log = OpenOptions::new().create(true).append(true).open(files.actions_path(&game_id)?)?;
+            log.write_all(&line)?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn load_actions(&self, game_id: &str) -> StoreResult<Vec<ReplayEntry>> {
+        let game_id = game_id.to_string();
+        self.run(move |files| {
+            let _games = files.games.read().unwrap();
+            let path = files.actions_path(&game_id)?;
+            let log = match fs::File::open(&path) {
+                Ok(log) => log,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+                Err(e) => return Err(e.into()),
+            };
+            let mut actions = Vec::new();
+            for line in BufReader::new(log).lines() {
+                let line = line?;
+                // A crash can cut the last line short; everything before it was written whole
+                match serde_json::from_str(&line) {
+                    Ok(entry) => actions.push(entry),
+                    Err(e) => {
+                        tracing::warn!("Ignoring the rest of {}: {}", path.display(), e);
+                        break;
+                    }
+                }
+            }
+            Ok(actions)
+        })
+        .await
+    }
+
+    async fn save_session(&self, session: PlayerSession) -> StoreResult<()> {
+        self.run(move |files| {
+            let mut sessions = files.sessions.write().unwrap();
+            files.write(SESSIONS, &session.token, &session)?;
+            sessions.insert(session.token.clone(), session);
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_session(&self, token: &str) -> StoreResult<Option<PlayerSession>> {
+        let token = token.to_string();
+        self.run(move |files| Ok(files.sessions.read().unwrap().get(&token).cloned())).await
+    }
+
+    async fn delete_session(&self, token: &str) -> StoreResult<()> {
+        let token = token.to_string();
+        self.run(move |files| {
+            let mut sessions = files.sessions.write().unwrap();
+            if sessions.remove(&token).is_some() {
+                files.remove(SESSIONS, &token)?;
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_replay(&self, game_id: &str) -> StoreResult<Option<Replay>> {
+        let game_id = game_id.to_string();
+        self.run(move |files| Ok(files.replays.read().unwrap().get(&game_id).cloned())).await
+    }
+
+    async fn list_replays(&self) -> StoreResult<Vec<String>> {
+        self.run(|files| Ok(files.replays.read().unwrap().keys().cloned().collect())).await
+    }
+}
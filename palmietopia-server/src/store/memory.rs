@@ -1,13 +1,23 @@
 use async_trait::async_trait;
-use palmietopia_core::{GameSession, Lobby};
+use palmietopia_core::{GameSession, GameStatus, Lobby, Replay, ReplayEntry};
 use std::collections::HashMap;
 use std::sync::RwLock;
 
-use super::{GameStore, StoreResult};
+use super::{GameFilter, GameStore, GameSummary, PlayerSession, StoreError, StoreResult};
+use crate::game::current_time_ms;
+
+struct StoredGame {
+    game: GameSession,
+    created_at_ms: u64,
+    updated_at_ms: u64,
+}
 
 pub struct InMemoryStore {
     lobbies: RwLock<HashMap<String, Lobby>>,
-    games: RwLock<HashMap<String, GameSession>>,
+    games: RwLock<HashMap<String, StoredGame>>,
+    actions: RwLock<HashMap<String, Vec<ReplayEntry>>>,
+    replays: RwLock<HashMap<String, Replay>>,
+    sessions: RwLock<HashMap<String, PlayerSession>>,
 }
 
 impl InMemoryStore {
@@ -15,6 +25,9 @@ impl InMemoryStore {
         Self {
             lobbies: RwLock::new(HashMap::new()),
             games: RwLock::new(HashMap::new()),
+            actions: RwLock::new(HashMap::new()),
+            replays: RwLock::new(HashMap::new()),
+            sessions: RwLock::new(HashMap::new()),
         }
     }
 }
@@ -30,6 +43,9 @@ impl GameStore for InMemoryStore {
     async fn create_lobby(&self, lobby: Lobby) -> StoreResult<String> {
         let id = lobby.id.clone();
         let mut lobbies = self.lobbies.write().unwrap();
+        if lobbies.contains_key(&id) {
+            return Err(StoreError::AlreadyExists);
+        }
         lobbies.insert(id.clone(), lobby);
         Ok(id)
     }
@@ -56,14 +72,80 @@ impl GameStore for InMemoryStore {
         Ok(())
     }
 
-    async fn save_game(&self, game: GameSession) -> StoreResult<()> {
+    async fn save_snapshot(&self, game: GameSession, replay: Replay) -> StoreResult<()> {
         let mut games = self.games.write().unwrap();
-        games.insert(game.id.clone(), game);
+        self.actions.write().unwrap().remove(&game.id);
+        let now = current_time_ms();
+        let created_at_ms = games.get(&game.id).map_or(now, |g| g.created_at_ms);
+        games.insert(game.id.clone(), StoredGame { game, created_at_ms, updated_at_ms: now });
+        self.replays.write().unwrap().insert(replay.game_id.clone(), replay);
         Ok(())
     }
 
     async fn load_game(&self, id: &str) -> StoreResult<Option<GameSession>> {
         let games = self.games.read().unwrap();
-        Ok(games.get(id).cloned())
+        Ok(games.get(id).map(|g| g.game.clone()))
+    }
+
+    async fn list_games(&self, filter: &GameFilter) -> StoreResult<Vec<GameSummary>> {
+        let games = self.games.read().unwrap();
+        let summaries = games.values().map(|g| GameSummary::new(&g.game, g.created_at_ms, g.updated_at_ms));
+        Ok(filter.apply(summaries))
+    }
+
+    async fn delete_game(&self, id: &str) -> StoreResult<()> {
+        let mut games = self.games.write().unwrap();
+        self.actions.write().unwrap().remove(id);
+        games.remove(id).map(|_| ()).ok_or(StoreError::NotFound)
+    }
+
+    async fn update_game_status(&self, id: &str, status: GameStatus) -> StoreResult<()> {
+        let mut games = self.games.write().unwrap();
+        let stored = games.get_mut(id).ok_or(StoreError::NotFound)?;
+        stored.game.status = status;
+        stored.updated_at_ms = current_time_ms();
+        Ok(())
+    }
+
+    async fn append_action(&self, game_id: &str, entry: ReplayEntry) -> StoreResult<()> {
+        let games = self.games.read().unwrap();
+        if !games.contains_key(game_id) {
+            return Err(StoreError::NotFound);
+        }
+        let mut actions = self.actions.write().unwrap();
+        actions.entry(game_id.to_string()).or_default().push(entry);
+        Ok(())
+    }
+
+    async fn load_actions(&self, game_id: &str) -> StoreResult<Vec<ReplayEntry>> {
+        let actions = self.actions.read().unwrap();
+        Ok(actions.get(game_id).cloned().unwrap_or_default())
+    }
+
+    async fn save_session(&self, session: PlayerSession) -> StoreResult<()> {
+        let mut sessions = self.sessions.write().unwrap();
+        sessions.insert(session.token.clone(), session);
+        Ok(())
+    }
+
+    async fn get_session(&self, token: &str) -> StoreResult<Option<PlayerSession>> {
+        let sessions = self.sessions.read().unwrap();
+        Ok(sessions.get(token).cloned())
+    }
+
+    async fn delete_session(&self, token: &str) -> StoreResult<()> {
+        let mut sessions = self.sessions.write().unwrap();
+        sessions.remove(token);
+        Ok(())
+    }
+
+    async fn get_replay(&self, game_id: &str) -> StoreResult<Option<Replay>> {
+        let replays = self.replays.read().unwrap();
+        Ok(replays.get(game_id).cloned())
+    }
+
+    async fn list_replays(&self) -> StoreResult<Vec<String>> {
+        let replays = self.replays.read().unwrap();
+        Ok(replays.keys().cloned().collect())
     }
 }
@@ -1,7 +1,10 @@
 use async_trait::async_trait;
-use palmietopia_core::{GameSession, Lobby};
+use palmietopia_core::{GameSession, GameStatus, Lobby, Player, Replay, ReplayEntry};
+use serde::{Deserialize, Serialize};
 
+pub mod json_file;
 pub mod memory;
+pub mod sqlite;
 
 pub type StoreResult<T> = Result<T, StoreError>;
 
@@ -25,6 +28,123 @@ impl std::fmt::Display for StoreError {
 
 impl std::error::Error for StoreError {}
 
+impl From<serde_json::Error> for StoreError {
+    fn from(e: serde_json::Error) -> Self {
+        StoreError::Internal(format!("Bad stored data: {}", e))
+    }
+}
+
+/// Who a reconnect token belongs to and what it lets them back into. Lobby tokens are issued
+/// when a player creates or joins a lobby, game tokens when the game starts.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PlayerSession {
+    pub token: String,
+    pub player: Player,
+    pub lobby_id: Option<String>,
+    pub game_id: Option<String>,
+}
+
+/// The broad state of a game, for filtering. Written `in_progress`, `victory`, `draw` or
+/// `finished`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GameState {
+    InProgress,
+    Victory,
+    Draw,
+    Finished,
+}
+
+impl GameState {
+    pub fn of(status: &GameStatus) -> Self {
+        match status {
+            GameStatus::InProgress => GameState::InProgress,
+            GameStatus::Victory { .. } => GameState::Victory,
+            GameStatus::Draw => GameState::Draw,
+            GameStatus::Finished => GameState::Finished,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            GameState::InProgress => "in_progress",
+            GameState::Victory => "victory",
+            GameState::Draw => "draw",
+            GameState::Finished => "finished",
+        }
+    }
+}
+
+/// Which games `list_games` returns. Every filter that is set has to match; time bounds are
+/// inclusive, in ms since the epoch. Results come newest first, `offset` and `limit` page
+/// through them.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct GameFilter {
+    pub status: Option<GameState>,
+    pub player_id: Option<String>,
+    pub created_after_ms: Option<u64>,
+    pub created_before_ms: Option<u64>,
+    pub updated_after_ms: Option<u64>,
+    pub updated_before_ms: Option<u64>,
+    #[serde(default)]
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+impl GameFilter {
+    pub fn with_status(status: GameState) -> Self {
+        Self {
+            status: Some(status),
+            ..Self::default()
+        }
+    }
+
+    /// Whether a game passes the filters, leaving paging aside
+    pub fn matches(&self, game: &GameSummary) -> bool {
+        let within = |at: u64, after: Option<u64>, before: Option<u64>| {
+            after.is_none_or(|after| at >= after) && before.is_none_or(|before| at <= before)
+        };
+        self.status.is_none_or(|status| GameState::of(&game.status) == status)
+            && self.player_id.as_ref().is_none_or(|id| game.players.iter().any(|p| &p.id == id))
+            && within(game.created_at_ms, self.created_after_ms, self.created_before_ms)
+            && within(game.updated_at_ms, self.updated_after_ms, self.updated_before_ms)
+    }
+
+    /// Filter, order and page summaries of every game, for stores that can't do it themselves
+    pub fn apply(&self, games: impl IntoIterator<Item = GameSummary>) -> Vec<GameSummary> {
+        let mut games: Vec<GameSummary> = games.into_iter().filter(|g| self.matches(g)).collect();
+        games.sort_by(|a, b| b.created_at_ms.cmp(&a.created_at_ms).then_with(|| a.id.cmp(&b.id)));
+        games.into_iter().skip(self.offset).take(self.limit.unwrap_or(usize::MAX)).collect()
+    }
+}
+
+/// A game as `list_games` describes it, without the map, units or anything else a player
+/// might not be allowed to see
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GameSummary {
+    pub id: String,
+    pub status: GameStatus,
+    pub players: Vec<Player>,
+    pub turn_number: u32,
+    /// When the game was first saved
+    pub created_at_ms: u64,
+    /// When the game's snapshot was last written
+    pub updated_at_ms: u64,
+}
+
+impl GameSummary {
+    pub fn new(game: &GameSession, created_at_ms: u64, updated_at_ms: u64) -> Self {
+        Self {
+            id: game.id.clone(),
+            status: game.status.clone(),
+            players: game.players.clone(),
+            turn_number: game.turn_number,
+            created_at_ms,
+            updated_at_ms,
+        }
+    }
+}
+
 #[async_trait]
 pub trait GameStore: Send + Sync {
     // Lobby operations
@@ -34,7 +154,233 @@ pub trait GameStore: Send + Sync {
     async fn update_lobby(&self, lobby: Lobby) -> StoreResult<()>;
     async fn delete_lobby(&self, id: &str) -> StoreResult<()>;
 
-    // Game operations
-    async fn save_game(&self, game: GameSession) -> StoreResult<()>;
+    // Game operations. A running game is kept as its latest snapshot plus a log of the actions
+    // accepted since.
+    /// Save a snapshot of the game together with its replay up to the same point, dropping the
+    /// actions logged before them, all in one write. If it fails, nothing has changed.
+    async fn save_snapshot(&self, game: GameSession, replay: Replay) -> StoreResult<()>;
     async fn load_game(&self, id: &str) -> StoreResult<Option<GameSession>>;
+    async fn list_games(&self, filter: &GameFilter) -> StoreResult<Vec<GameSummary>>;
+    /// Remove a game and its action log. Its replay, if any, is kept. `NotFound` if there is
+    /// no such game.
+    async fn delete_game(&self, id: &str) -> StoreResult<()>;
+    /// Change the status of a saved game. `NotFound` if there is no such game.
+    async fn update_game_status(&self, id: &str, status: GameStatus) -> StoreResult<()>;
+    /// Add an action to the game's log. `NotFound` if there is no such game.
+    async fn append_action(&self, game_id: &str, entry: ReplayEntry) -> StoreResult<()>;
+    /// The actions logged since the game's last snapshot, in order
+    async fn load_actions(&self, game_id: &str) -> StoreResult<Vec<ReplayEntry>>;
+
+    // Reconnect tokens
+    async fn save_session(&self, session: PlayerSession) -> StoreResult<()>;
+    async fn get_session(&self, token: &str) -> StoreResult<Option<PlayerSession>>;
+    async fn delete_session(&self, token: &str) -> StoreResult<()>;
+
+    // Replay operations. A running game's replay is saved with each snapshot; the HTTP API
+    // only serves replays of finished games.
+    async fn get_replay(&self, game_id: &str) -> StoreResult<Option<Replay>>;
+    async fn list_replays(&self) -> StoreResult<Vec<String>>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use json_file::JsonFileStore;
+    use memory::InMemoryStore;
+    use std::path::PathBuf;
+    use crate::testing;
+    use palmietopia_core::{GameCommand, GameStatus, LobbyStatus};
+    use sqlite::SqliteStore;
+
+    fn lobby(id: &str) -> Lobby {
+        testing::lobby(id, &["p1", "p2"])
+    }
+
+    /// The behaviour every store has to share
+    async fn check_store(store: &dyn GameStore) {
+        // Lobbies
+        assert_eq!(store.create_lobby(lobby("l1")).await.unwrap(), "l1");
+        assert!(matches!(store.create_lobby(lobby("l1")).await, Err(StoreError::AlreadyExists)));
+        store.create_lobby(lobby("l2")).await.unwrap();
+        let mut started = store.get_lobby("l1").await.unwrap().unwrap();
+        assert_eq!(started.players.len(), 2);
+        started.status = LobbyStatus::Starting;
+        store.update_lobby(started).await.unwrap();
+        assert_eq!(store.get_lobby("l1").await.unwrap().unwrap().status, LobbyStatus::Starting);
+        store.delete_lobby("l2").await.unwrap();
+        let ids: Vec<String> = store.list_lobbies().await.unwrap().into_iter().map(|l| l.id).collect();
+        assert_eq!(ids, vec!["l1"]);
+        assert!(store.get_lobby("l2").await.unwrap().is_none());
+
+        // Games
+        let game = GameSession::from_lobby(&lobby("g1"));
+        testing::save(store, game.clone()).await;
+        let loaded = store.load_game("g1").await.unwrap().unwrap();
+        assert_eq!(serde_json::to_value(&loaded.units).unwrap(), serde_json::to_value(&game.units).unwrap());
+        assert_eq!(serde_json::to_value(&loaded.map).unwrap(), serde_json::to_value(&game.map).unwrap());
+        assert_eq!(loaded.explored_tiles, game.explored_tiles);
+        let unit = &game.units[0];
+        assert_eq!(loaded.unit(&unit.id).map(|u| &u.id), Some(&unit.id), "loaded games are indexed");
+        assert!(store.load_game("missing").await.unwrap().is_none());
+        let mut finished = GameSession::from_lobby(&lobby("g2"));
+        finished.status = GameStatus::Finished;
+        testing::save(store, finished).await;
+        testing::save(store, testing::game("g3", &["p3", "p4"])).await;
+
+        // Listing
+        let list = async |filter: GameFilter| {
+            let mut ids: Vec<String> = store.list_games(&filter).await.unwrap().into_iter().map(|g| g.id).collect();
+            ids.sort();
+            ids
+        };
+        assert_eq!(list(GameFilter::with_status(GameState::InProgress)).await, vec!["g1", "g3"]);
+        assert_eq!(list(GameFilter::with_status(GameState::Finished)).await, vec!["g2"]);
+        let of_player = |id: &str| GameFilter { player_id: Some(id.to_string()), ..GameFilter::default() };
+        assert_eq!(list(of_player("p1")).await, vec!["g1", "g2"]);
+        assert_eq!(list(of_player("p4")).await, vec!["g3"]);
+        let created_later = GameFilter { created_after_ms: Some(u64::MAX / 2), ..GameFilter::default() };
+        assert!(list(created_later).await.is_empty());
+        let updated_by_now = GameFilter { updated_before_ms: Some(u64::MAX / 2), ..GameFilter::default() };
+        assert_eq!(list(updated_by_now).await.len(), 3);
+        let summary = store.list_games(&of_player("p4")).await.unwrap().remove(0);
+        assert_eq!((summary.players.len(), summary.turn_number), (2, 0));
+        assert!(summary.created_at_ms > 0 && summary.created_at_ms <= summary.updated_at_ms);
+
+        // Pages don't overlap and cover everything
+        let page = |offset| GameFilter { offset, limit: Some(2), ..GameFilter::default() };
+        let mut pages = list(page(0)).await;
+        assert_eq!(pages.len(), 2);
+        pages.extend(list(page(2)).await);
+        pages.sort();
+        assert_eq!(pages, vec!["g1", "g2", "g3"]);
+
+        // Status changes and deletion
+        store.update_game_status("g3", GameStatus::Draw).await.unwrap();
+        assert_eq!(store.load_game("g3").await.unwrap().unwrap().status, GameStatus::Draw);
+        assert_eq!(list(GameFilter::with_status(GameState::Draw)).await, vec!["g3"]);
+        assert!(matches!(store.update_game_status("missing", GameStatus::Draw).await, Err(StoreError::NotFound)));
+        store.delete_game("g3").await.unwrap();
+        assert!(store.load_game("g3").await.unwrap().is_none());
+        assert!(matches!(store.delete_game("g3").await, Err(StoreError::NotFound)));
+
+        // Action logs, cleared by the next snapshot
+        let end_turn = |at_ms| ReplayEntry {
+            at_ms,
+            command: GameCommand::EndTurn { player_id: "p1".to_string(), time_used_ms: 10 },
+        };
+        store.append_action("g1", end_turn(1)).await.unwrap();
+        store.append_action("g1", end_turn(2)).await.unwrap();
+        store.append_action("g2", end_turn(3)).await.unwrap();
+        let logged: Vec<u64> = store.load_actions("g1").await.unwrap().iter().map(|e| e.at_ms).collect();
+        assert_eq!(logged, vec![1, 2]);
+        assert!(matches!(store.append_action("g3", end_turn(4)).await, Err(StoreError::NotFound)), "deleted");
+        assert!(matches!(store.append_action("missing", end_turn(4)).await, Err(StoreError::NotFound)));
+        assert!(store.load_actions("missing").await.unwrap().is_empty());
+        testing::save(store, game.clone()).await;
+        assert!(store.load_actions("g1").await.unwrap().is_empty());
+        assert_eq!(store.load_actions("g2").await.unwrap().len(), 1);
+        store.append_action("g1", end_turn(4)).await.unwrap();
+
+        // Reconnect tokens
+        let session = PlayerSession {
+            token: "t1".to_string(),
+            player: lobby("l1").players.remove(0),
+            lobby_id: None,
+            game_id: Some("g1".to_string()),
+        };
+        store.save_session(session).await.unwrap();
+        let found = store.get_session("t1").await.unwrap().unwrap();
+        assert_eq!((found.player.id.as_str(), found.game_id.as_deref()), ("p1", Some("g1")));
+        store.delete_session("t1").await.unwrap();
+        assert!(store.get_session("t1").await.unwrap().is_none());
+
+        // Replays, saved with their game's snapshot and kept when the game is deleted
+        store.save_snapshot(game.clone(), Replay::new(game, 1_000)).await.unwrap();
+        assert_eq!(store.get_replay("g1").await.unwrap().unwrap().started_at_ms, 1_000);
+        assert!(store.load_actions("g1").await.unwrap().is_empty());
+        let mut replays = store.list_replays().await.unwrap();
+        replays.sort();
+        assert_eq!(replays, vec!["g1", "g2", "g3"]);
+    }
+
+    #[tokio::test]
+    async fn memory_store() {
+        check_store(&InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn sqlite_store() {
+        check_store(&SqliteStore::open_in_memory().unwrap()).await;
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("palmietopia-{}-{}", name, uuid::Uuid::new_v4()))
+    }
+
+    #[tokio::test]
+    async fn json_file_store() {
+        let dir = temp_path("json");
+        check_store(&JsonFileStore::open(&dir).unwrap()).await;
+
+        // Everything is read back from the files
+        let store = JsonFileStore::open(&dir).unwrap();
+        assert!(store.get_lobby("l1").await.unwrap().is_some());
+        assert!(store.load_game("g1").await.unwrap().unwrap().unit("unit-p1-0").is_some());
+        assert_eq!(store.load_actions("g2").await.unwrap().len(), 1);
+        assert_eq!(store.get_replay("g1").await.unwrap().unwrap().started_at_ms, 1_000);
+        assert!(store.delete_game("../g1").await.is_err());
+
+        // Actions are appended to a log of their own, which the next snapshot clears
+        let log = dir.join("games/g2.actions.jsonl");
+        assert_eq!(std::fs::read_to_string(&log).unwrap().lines().count(), 1);
+        let game_file = std::fs::read(dir.join("games/g2.json")).unwrap();
+        let entry = ReplayEntry { at_ms: 5, command: GameCommand::Resign { player_id: "p2".to_string() } };
+        store.append_action("g2", entry).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&log).unwrap().lines().count(), 2);
+        assert_eq!(std::fs::read(dir.join("games/g2.json")).unwrap(), game_file, "the snapshot isn't rewritten");
+        testing::save(&store, store.load_game("g2").await.unwrap().unwrap()).await;
+        assert!(!log.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn fixtures_load_and_resume() {
+        let fixtures = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures/two-player-midgame");
+        let dir = temp_path("fixtures");
+        JsonFileStore::install_fixtures(&fixtures, &dir).unwrap();
+        let store = JsonFileStore::open(&dir).unwrap();
+
+        let in_progress = store.list_games(&GameFilter::with_status(GameState::InProgress)).await.unwrap();
+        assert_eq!(in_progress.len(), 1);
+        let game_id = &in_progress[0].id;
+        let session = store.get_session("fixture-red").await.unwrap().unwrap();
+        assert_eq!(session.game_id.as_ref(), Some(game_id));
+        let snapshot = store.load_game(game_id).await.unwrap().unwrap();
+        let actions = store.load_actions(game_id).await.unwrap();
+        let mut replay = Replay::new(snapshot.clone(), 0);
+        let game = crate::autosave::rebuild(snapshot.clone(), actions.clone(), &mut replay);
+        let turns = actions.iter().filter(|a| matches!(a.command, GameCommand::EndTurn { .. })).count();
+        assert!(turns > 0 && actions.len() > turns);
+        assert_eq!(game.turn_number, snapshot.turn_number + turns as u32, "every logged action still applies");
+        assert_eq!(replay.entries.len(), actions.len());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn sqlite_store_survives_reopening() {
+        let path = temp_path("sqlite").with_extension("db");
+        {
+            let store = SqliteStore::open(&path).unwrap();
+            store.create_lobby(lobby("l1")).await.unwrap();
+            testing::save(&store, GameSession::from_lobby(&lobby("g1"))).await;
+        }
+        // Reopening runs no migrations twice and finds the data
+        let store = SqliteStore::open(&path).unwrap();
+        assert!(store.get_lobby("l1").await.unwrap().is_some());
+        assert!(store.load_game("g1").await.unwrap().is_some());
+        drop(store);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+    }
 }
@@ -0,0 +1,386 @@
+use async_trait::async_trait;
+use palmietopia_core::{GameSession, GameStatus, Lobby, Replay, ReplayEntry};
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+use serde::de::DeserializeOwned;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use super::{GameFilter, GameState, GameStore, GameSummary, PlayerSession, StoreError, StoreResult};
+use crate::game::current_time_ms;
+
+/// Schema changes, applied in order. `PRAGMA user_version` records how many have run, so only
+/// append to this list; never edit an entry that has shipped.
+const MIGRATIONS: &[&str] = &[
+    // 1: lobbies, games, replays and reconnect tokens. Rows hold the serialized value plus the
+    // columns we look things up by.
+    "CREATE TABLE lobbies (
+        id TEXT PRIMARY KEY,
+        status TEXT NOT NULL,
+        data TEXT NOT NULL
+    );
+    CREATE TABLE games (
+        id TEXT PRIMARY KEY,
+        data TEXT NOT NULL
+    );
+    CREATE TABLE replays (
+        game_id TEXT PRIMARY KEY,
+        data TEXT NOT NULL
+    );
+    CREATE TABLE sessions (
+        token TEXT PRIMARY KEY,
+        player_id TEXT NOT NULL,
+        data TEXT NOT NULL
+    );",
+    // 2: game status, to find the games to resume on startup
+    "ALTER TABLE games ADD COLUMN status TEXT NOT NULL DEFAULT '';
+    UPDATE games SET status = json_extract(data, '$.status');
+    CREATE INDEX games_status ON games (status);",
+    // 3: actions accepted since each game's last snapshot
+    "CREATE TABLE game_actions (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        game_id TEXT NOT NULL,
+        data TEXT NOT NULL
+    );
+    CREATE INDEX game_actions_game ON game_actions (game_id, id);",
+    // 4: game timestamps, and statuses as `GameState` names for filtering
+    "ALTER TABLE games ADD COLUMN created_at_ms INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE games ADD COLUMN updated_at_ms INTEGER NOT NULL DEFAULT 0;
+    UPDATE games SET status = CASE status
+        WHEN 'InProgress' THEN 'in_progress'
+        WHEN 'Draw' THEN 'draw'
+        WHEN 'Finished' THEN 'finished'
+        ELSE 'victory'
+    END;
+    CREATE INDEX games_created ON games (created_at_ms);",
+];
+
+/// A `GameStore` kept in a SQLite database file, so lobbies, games and replays survive a
+/// restart. Values are stored as JSON. Queries block, so each one runs on tokio's blocking
+/// thread pool rather than on the async workers.
+pub struct SqliteStore {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStore {
+    /// Open (or create) the database at `path` and bring its schema up to date
+    pub fn open(path: impl AsRef<Path>) -> StoreResult<Self> {
+        Self::with_connection(Connection::open(path)?)
+    }
+
+    /// A private database that goes away when the store is dropped
+    #[allow(dead_code)]
+    pub fn open_in_memory() -> StoreResult<Self> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(mut conn: Connection) -> StoreResult<Self> {
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        migrate(&mut conn)?;
+        Ok(Self { conn: Arc::new(Mutex::new(conn)) })
+    }
+
+    /// Run `query` with the connection on a blocking thread
+    async fn run<T: Send + 'static>(
+        &self,
+        query: impl FnOnce(&mut Connection) -> StoreResult<T> + Send + 'static,
+    ) -> StoreResult<T> {
+        let conn = Arc::clone(&self.conn);
+        tokio::task::spawn_blocking(move || query(&mut conn.lock().unwrap()))
+            .await
+            .map_err(|e| StoreError::Internal(e.to_string()))?
+    }
+
+    async fn get<T: DeserializeOwned + Send + 'static>(&self, sql: &'static str, key: &str) -> StoreResult<Option<T>> {
+        let key = key.to_string();
+        self.run(move |conn| {
+            let data: Option<String> = conn.query_row(sql, [key], |row| row.get(0)).optional()?;
+            data.map(|data| serde_json::from_str(&data).map_err(StoreError::from)).transpose()
+        })
+        .await
+    }
+}
+
+/// Run the migrations the database hasn't seen yet, each in its own transaction
+fn migrate(conn: &mut Connection) -> StoreResult<()> {
+    let applied: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    if applied > MIGRATIONS.len() {
+        return Err(StoreError::Internal(format!(
+            "Database schema version {} is newer than this server ({})",
+            applied,
+            MIGRATIONS.len()
+        )));
+    }
+    for (version, sql) in MIGRATIONS.iter().enumerate().skip(applied) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(sql)?;
+        tx.pragma_update(None, "user_version", version + 1)?;
+        tx.commit()?;
+        tracing::info!("Applied database migration {}", version + 1);
+    }
+    Ok(())
+}
+
+impl From<rusqlite::Error> for StoreError {
+    fn from(e: rusqlite::Error) -> Self {
+        StoreError::Internal(e.to_string())
+    }
+}
+
+fn lobby_status(lobby: &Lobby) -> String {
+    format!("{:?}", lobby.status)
+}
+
+fn game_state(status: &GameStatus) -> &'static str {
+    GameState::of(status).as_str()
+}
+
+/// The WHERE clause and its parameters for a `GameFilter`
+fn game_conditions(filter: &GameFilter) -> (String, Vec<Value>) {
+    let mut conditions = vec!["1 = 1".to_string()];
+    let mut values = Vec::new();
+    if let Some(status) = filter.status {
+        conditions.push("status = ?".to_string());
+        values.push(Value::Text(status.as_str().to_string()));
+    }
+    if let Some(player_id) = &filter.player_id {
+        conditions.push(
+            "EXISTS (SELECT 1 FROM json_each(data, '$.players') WHERE value ->> '$.id' = ?)".to_string(),
+        );
+        values.push(Value::Text(player_id.clone()));
+    }
+    let bounds = [
+        ("created_at_ms >= ?", filter.created_after_ms),
+        ("created_at_ms <= ?", filter.created_before_ms),
+        ("updated_at_ms >= ?", filter.updated_after_ms),
+        ("updated_at_ms <= ?", filter.updated_before_ms),
+    ];
+    for (condition, bound) in bounds {
+        if let Some(bound) = bound {
+            conditions.push(condition.to_string());
+            values.push(Value::Integer(bound as i64));
+        }
+    }
+    (conditions.join(" AND "), values)
+}
+
+/// The unit index isn't serialized
+fn indexed(mut game: GameSession) -> GameSession {
+    game.reindex();
+    game
+}
+
+#[async_trait]
+impl GameStore for SqliteStore {
+    async fn create_lobby(&self, lobby: Lobby) -> StoreResult<String> {
+        let data = serde_json::to_string(&lobby)?;
+        self.run(move |conn| {
+            let inserted = conn.execute(
+                "INSERT OR IGNORE INTO lobbies (id, status, data) VALUES (?1, ?2, ?3)",
+                params![lobby.id, lobby_status(&lobby), data],
+            )?;
+            if inserted == 0 {
+                return Err(StoreError::AlreadyExists);
+            }
+            Ok(lobby.id)
+        })
+        .await
+    }
+
+    async fn get_lobby(&self, id: &str) -> StoreResult<Option<Lobby>> {
+        self.get("SELECT data FROM lobbies WHERE id = ?1", id).await
+    }
+
+    async fn list_lobbies(&self) -> StoreResult<Vec<Lobby>> {
+        self.run(|conn| {
+            let mut stmt = conn.prepare("SELECT data FROM lobbies")?;
+            let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+            let mut lobbies = Vec::new();
+            for data in rows {
+                lobbies.push(serde_json::from_str(&data?)?);
+            }
+            Ok(lobbies)
+        })
+        .await
+    }
+
+    async fn update_lobby(&self, lobby: Lobby) -> StoreResult<()> {
+        let data = serde_json::to_string(&lobby)?;
+        self.run(move |conn| {
+            conn.execute(
+                "INSERT INTO lobbies (id, status, data) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (id) DO UPDATE SET status = excluded.status, data = excluded.data",
+                params![lobby.id, lobby_status(&lobby), data],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn delete_lobby(&self, id: &str) -> StoreResult<()> {
+        let id = id.to_string();
+        self.run(move |conn| {
+            conn.execute("DELETE FROM lobbies WHERE id = ?1", [id])?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn save_snapshot(&self, game: GameSession, replay: Replay) -> StoreResult<()> {
+        let data = serde_json::to_string(&game)?;
+        let replay_data = serde_json::to_string(&replay)?;
+        self.run(move |conn| {
+            let tx = conn.transaction()?;
+            tx.execute(
+                "INSERT INTO games (id, status, data, created_at_ms, updated_at_ms) VALUES (?1, ?2, ?3, ?4, ?4)
+                 ON CONFLICT (id) DO UPDATE
+                 SET status = excluded.status, data = excluded.data, updated_at_ms = excluded.updated_at_ms",
+                params![game.id, game_state(&game.status), data, current_time_ms() as i64],
+            )?;
+            tx.execute("DELETE FROM game_actions WHERE game_id = ?1", [&game.id])?;
+            tx.execute(
+                "INSERT INTO replays (game_id, data) VALUES (?1, ?2)
+                 ON CONFLICT (game_id) DO UPDATE SET data = excluded.data",
+                params![replay.game_id, replay_data],
+            )?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn load_game(&self, id: &str) -> StoreResult<Option<GameSession>> {
+        let game: Option<GameSession> = self.get("SELECT data FROM games WHERE id = ?1", id).await?;
+        Ok(game.map(indexed))
+    }
+
+    async fn list_games(&self, filter: &GameFilter) -> StoreResult<Vec<GameSummary>> {
+        let (conditions, mut values) = game_conditions(filter);
+        values.push(Value::Integer(filter.limit.map_or(-1, |limit| limit as i64)));
+        values.push(Value::Integer(filter.offset as i64));
+        let sql = format!(
+            "SELECT id, data -> '$.status', data -> '$.players', data ->> '$.turn_number', created_at_ms, updated_at_ms
+             FROM games WHERE {} ORDER BY created_at_ms DESC, id LIMIT ? OFFSET ?",
+            conditions
+        );
+        self.run(move |conn| {
+            let mut stmt = conn.prepare(&sql)?;
+            let mut rows = stmt.query(params_from_iter(values))?;
+            let mut games = Vec::new();
+            while let Some(row) = rows.next()? {
+                games.push(GameSummary {
+                    id: row.get(0)?,
+                    status: serde_json::from_str(&row.get::<_, String>(1)?)?,
+                    players: serde_json::from_str(&row.get::<_, String>(2)?)?,
+                    turn_number: row.get(3)?,
+                    created_at_ms: row.get::<_, i64>(4)? as u64,
+                    updated_at_ms: row.get::<_, i64>(5)? as u64,
+                });
+            }
+            Ok(games)
+        })
+        .await
+    }
+
+    async fn delete_game(&self, id: &str) -> StoreResult<()> {
+        let id = id.to_string();
+        self.run(move |conn| {
+            let tx = conn.transaction()?;
+            let deleted = tx.execute("DELETE FROM games WHERE id = ?1", [&id])?;
+            tx.execute("DELETE FROM game_actions WHERE game_id = ?1", [&id])?;
+            tx.commit()?;
+            if deleted == 0 {
+                return Err(StoreError::NotFound);
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    async fn update_game_status(&self, id: &str, status: GameStatus) -> StoreResult<()> {
+        let id = id.to_string();
+        let data = serde_json::to_string(&status)?;
+        self.run(move |conn| {
+            let updated = conn.execute(
+                "UPDATE games SET status = ?2, data = json_set(data, '$.status', json(?3)), updated_at_ms = ?4
+                 WHERE id = ?1",
+                params![id, game_state(&status), data, current_time_ms() as i64],
+            )?;
+            if updated == 0 {
+                return Err(StoreError::NotFound);
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    async fn append_action(&self, game_id: &str, entry: ReplayEntry) -> StoreResult<()> {
+        let game_id = game_id.to_string();
+        let data = serde_json::to_string(&entry)?;
+        self.run(move |conn| {
+            let inserted = conn.execute(
+                "INSERT INTO game_actions (game_id, data)
+                 SELECT ?1, ?2 WHERE EXISTS (SELECT 1 FROM games WHERE id = ?1)",
+                params![game_id, data],
+            )?;
+            if inserted == 0 {
+                return Err(StoreError::NotFound);
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    async fn load_actions(&self, game_id: &str) -> StoreResult<Vec<ReplayEntry>> {
+        let game_id = game_id.to_string();
+        self.run(move |conn| {
+            let mut stmt = conn.prepare("SELECT data FROM game_actions WHERE game_id = ?1 ORDER BY id")?;
+            let rows = stmt.query_map([game_id], |row| row.get::<_, String>(0))?;
+            let mut actions = Vec::new();
+            for data in rows {
+                actions.push(serde_json::from_str(&data?)?);
+            }
+            Ok(actions)
+        })
+        .await
+    }
+
+    async fn save_session(&self, session: PlayerSession) -> StoreResult<()> {
+        let data = serde_json::to_string(&session)?;
+        self.run(move |conn| {
+            conn.execute(
+                "INSERT INTO sessions (token, player_id, data) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (token) DO UPDATE SET player_id = excluded.player_id, data = excluded.data",
+                params![session.token, session.player.id, data],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_session(&self, token: &str) -> StoreResult<Option<PlayerSession>> {
+        self.get("SELECT data FROM sessions WHERE token = ?1", token).await
+    }
+
+    async fn delete_session(&self, token: &str) -> StoreResult<()> {
+        let token = token.to_string();
+        self.run(move |conn| {
+            conn.execute("DELETE FROM sessions WHERE token = ?1", [token])?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_replay(&self, game_id: &str) -> StoreResult<Option<Replay>> {
+        self.get("SELECT data FROM replays WHERE game_id = ?1", game_id).await
+    }
+
+    async fn list_replays(&self) -> StoreResult<Vec<String>> {
+        self.run(|conn| {
+            let mut stmt = conn.prepare("SELECT game_id FROM replays")?;
+            let ids = stmt.query_map([], |row| row.get(0))?.collect::<Result<_, _>>()?;
+            Ok(ids)
+        })
+        .await
+    }
+}
@@ -0,0 +1,28 @@
+//! Lobbies and games for the server's tests to start from
+
+use palmietopia_core::{GameSession, Lobby, MapSize, Player, PlayerColor, Replay};
+
+use crate::store::GameStore;
+
+/// A small-map lobby with `players` in seat order, the first hosting, each named after their id
+pub fn lobby(id: &str, players: &[&str]) -> Lobby {
+    let player = |i: usize| Player {
+        id: players[i].to_string(),
+        name: players[i].to_string(),
+        color: PlayerColor::from_index(i),
+    };
+    let mut lobby = Lobby::with_seed(id.to_string(), player(0), MapSize::Small, 7);
+    lobby.players.extend((1..players.len()).map(player));
+    lobby
+}
+
+/// A game just started from `lobby`
+pub fn game(id: &str, players: &[&str]) -> GameSession {
+    GameSession::from_lobby(&lobby(id, players))
+}
+
+/// Save a snapshot of `game` with a replay that starts from it
+pub async fn save(store: &dyn GameStore, game: GameSession) {
+    let replay = Replay::new(game.clone(), 0);
+    store.save_snapshot(game, replay).await.unwrap();
+}
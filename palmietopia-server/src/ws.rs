@@ -1,454 +1,652 @@
 use axum::extract::ws::{Message, WebSocket};
 use futures::{SinkExt, StreamExt};
 use palmietopia_core::{
-    ClientMessage, GameSession, Lobby, LobbyStatus, Player, PlayerColor, ServerMessage,
+    ClientMessage, GameCommand, GameError, GameSession, Lobby, LobbyStatus, Player, PlayerColor, ServerMessage,
 };
 use std::sync::Arc;
-use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, mpsc};
 use uuid::Uuid;
 
+use crate::game::Resync;
 use crate::state::AppState;
+use crate::store::{PlayerSession, StoreResult};
 
-pub async fn handle_socket(socket: WebSocket, state: Arc<AppState>) {
-    let (mut sender, mut receiver) = socket.split();
+/// Per-socket state. The socket is bound to one player identity chosen by the server: the id
+/// minted when it connects, or the player whose reconnect token it presented. Game commands
+/// always act as that player; clients never say who they are in a command.
+pub struct Session {
+    /// Key of this socket in `AppState::connections`
+    connection_id: String,
+    player_id: String,
+    lobby_id: Option<String>,
+    /// Reconnect token for the lobby this socket is in
+    lobby_token: Option<String>,
+    game_id: Option<String>,
+    /// The game this socket counts towards its player's presence in
+    present_in: Option<String>,
+    /// The lobby or game channel this socket is subscribed to
+    rx: Option<broadcast::Receiver<String>>,
+}
 
-    let player_id = Uuid::new_v4().to_string();
-    let mut current_lobby_id: Option<String> = None;
-    let mut lobby_rx: Option<broadcast::Receiver<String>> = None;
+impl Session {
+    /// Register a new socket. The receiver gets messages meant for this socket alone, e.g. the
+    /// player's own view of a game that just started.
+    pub async fn connect(state: &Arc<AppState>) -> (Self, mpsc::UnboundedReceiver<String>) {
+        let connection_id = Uuid::new_v4().to_string();
+        let (direct_tx, direct_rx) = mpsc::unbounded_channel::<String>();
+        {
+            let mut connections = state.connections.write().await;
+            connections.insert(
+                connection_id.clone(),
+                crate::state::PlayerConnection {
+                    player_id: connection_id.clone(),
+                    lobby_id: None,
+                    game_id: None,
+                    direct: direct_tx,
+                },
+            );
+        }
+        let session = Self {
+            player_id: connection_id.clone(),
+            connection_id,
+            lobby_id: None,
+            lobby_token: None,
+            game_id: None,
+            present_in: None,
+            rx: None,
+        };
+        (session, direct_rx)
+    }
 
-    let mut current_game_id: Option<String> = None;
+    pub async fn disconnect(&self, state: &Arc<AppState>) {
+        // Remove from connections
+        {
+            let mut connections = state.connections.write().await;
+            connections.remove(&self.connection_id);
+        }
 
-    // Register connection
-    {
-        let mut connections = state.connections.write().await;
-        connections.insert(
-            player_id.clone(),
-            crate::state::PlayerConnection {
-                player_id: player_id.clone(),
-                lobby_id: None,
-                game_id: None,
-            },
-        );
-    }
+        // Leave lobby if in one
+        if let Some(lobby_id) = &self.lobby_id {
+            leave_lobby(&self.player_id, lobby_id, state).await;
+        }
 
-    loop {
-        tokio::select! {
-            // Handle incoming messages from client
-            msg = receiver.next() => {
-                match msg {
-                    Some(Ok(Message::Text(text))) => {
-                        match serde_json::from_str::<ClientMessage>(&text) {
-                            Ok(client_msg) => {
-                                let response = handle_client_message(
-                                    client_msg,
-                                    &player_id,
-                                    &mut current_lobby_id,
-                                    &mut current_game_id,
-                                    &mut lobby_rx,
-                                    &state,
-                                ).await;
-
-                                if let Some(msg) = response {
-                                    let json = serde_json::to_string(&msg).unwrap();
-                                    if sender.send(Message::Text(json.into())).await.is_err() {
-                                        break;
-                                    }
-                                }
-                            }
-                            Err(e) => {
-                                let error = ServerMessage::Error {
-                                    message: format!("Invalid message format: {}", e),
-                                };
-                                let json = serde_json::to_string(&error).unwrap();
-                                let _ = sender.send(Message::Text(json.into())).await;
-                            }
-                        }
-                    }
-                    Some(Ok(Message::Close(_))) | None => break,
-                    _ => {}
-                }
-            }
+        // Let the other players know, and start the grace period if this was the player's
+        // last socket
+        if let Some(game_id) = &self.present_in {
+            state.game_manager.player_disconnected(game_id, &self.player_id).await;
+        }
+    }
 
-            // Handle broadcast messages from lobby
-            broadcast_msg = async {
-                if let Some(ref mut rx) = lobby_rx {
-                    rx.recv().await.ok()
-                } else {
-                    std::future::pending::<Option<String>>().await
-                }
-            } => {
-                if let Some(msg) = broadcast_msg {
-                    if sender.send(Message::Text(msg.into())).await.is_err() {
-                        break;
-                    }
-                }
-            }
+    /// Point this socket's connection entry at the player, lobby and game it is now in
+    async fn update_connection(&self, state: &Arc<AppState>) {
+        let mut connections = state.connections.write().await;
+        if let Some(conn) = connections.get_mut(&self.connection_id) {
+            conn.player_id = self.player_id.clone();
+            conn.lobby_id = self.lobby_id.clone();
+            conn.game_id = self.game_id.clone();
         }
     }
 
-    // Cleanup on disconnect
-    handle_disconnect(&player_id, &current_lobby_id, &state).await;
-}
+    /// Subscribe to a lobby's channel as a member of it, with the token to get back in
+    async fn enter_lobby(&mut self, state: &Arc<AppState>, lobby_id: &str, token: String) -> broadcast::Sender<String> {
+        let tx = state.get_or_create_lobby_channel(lobby_id).await;
+        self.rx = Some(tx.subscribe());
+        self.lobby_id = Some(lobby_id.to_string());
+        self.lobby_token = Some(token);
+        self.update_connection(state).await;
+        tx
+    }
 
-async fn handle_client_message(
-    msg: ClientMessage,
-    player_id: &str,
-    current_lobby_id: &mut Option<String>,
-    current_game_id: &mut Option<String>,
-    lobby_rx: &mut Option<broadcast::Receiver<String>>,
-    state: &Arc<AppState>,
-) -> Option<ServerMessage> {
-    match msg {
-        ClientMessage::ListLobbies => {
-            let lobbies = state.store.list_lobbies().await.unwrap_or_default();
-            let visible_lobbies: Vec<Lobby> = lobbies
-                .into_iter()
-                .filter(|l| l.status == LobbyStatus::Waiting)
-                .collect();
-            Some(ServerMessage::LobbyList {
-                lobbies: visible_lobbies,
-            })
+    /// The player this socket acts as in the given game, if it is in that game
+    fn game_player(&self, game_id: &str) -> Option<&str> {
+        match &self.game_id {
+            Some(id) if id == game_id => Some(&self.player_id),
+            _ => None,
         }
+    }
 
-        ClientMessage::CreateLobby {
-            player_name,
-            map_size,
-        } => {
-            // Prevent creating if already in a lobby
-            if current_lobby_id.is_some() {
-                return Some(ServerMessage::Error {
-                    message: "Already in a lobby. Leave first before creating a new one.".to_string(),
-                });
+    /// Run a game command as this socket's player. Results reach every player (including the
+    /// sender) through the game channel, so only errors are answered directly.
+    async fn apply_command(
+        &self,
+        state: &Arc<AppState>,
+        game_id: &str,
+        command: impl FnOnce(String) -> GameCommand,
+    ) -> Option<ServerMessage> {
+        let Some(player_id) = self.game_player(game_id) else {
+            return Some(not_in_game());
+        };
+        match state.game_manager.apply_command(game_id, command(player_id.to_string())).await {
+            Ok(events) => {
+                tracing::info!("Command succeeded: {} events", events.len());
+                None
             }
+            Err(e) => {
+                tracing::error!("Command failed: {}", e);
+                Some(e.into())
+            }
+        }
+    }
 
-            let lobby_id = Uuid::new_v4().to_string();
-            let player = Player {
-                id: player_id.to_string(),
-                name: player_name,
-                color: PlayerColor::Red,
-            };
-
-            let lobby = Lobby::new(lobby_id.clone(), player, map_size);
-            if let Err(e) = state.store.create_lobby(lobby.clone()).await {
-                return Some(ServerMessage::Error {
-                    message: format!("Failed to create lobby: {}", e),
-                });
+    pub async fn handle(&mut self, msg: ClientMessage, state: &Arc<AppState>) -> Option<ServerMessage> {
+        match msg {
+            ClientMessage::ListLobbies => {
+                let lobbies = state.store.list_lobbies().await.unwrap_or_default();
+                let visible_lobbies: Vec<Lobby> = lobbies
+                    .into_iter()
+                    .filter(|l| l.status == LobbyStatus::Waiting)
+                    .collect();
+                Some(ServerMessage::LobbyList {
+                    lobbies: visible_lobbies,
+                })
             }
 
-            // Subscribe to lobby channel
-            let tx = state.get_or_create_lobby_channel(&lobby_id).await;
-            *lobby_rx = Some(tx.subscribe());
-            *current_lobby_id = Some(lobby_id.clone());
+            ClientMessage::CreateLobby {
+                player_name,
+                map_size,
+                seed,
+                map_config,
+                start_config,
+            } => {
+                // Prevent creating if already in a lobby
+                if self.lobby_id.is_some() {
+                    return Some(ServerMessage::error("already_in_lobby", "Already in a lobby. Leave first before creating a new one."));
+                }
+                let map_config = map_config.unwrap_or_default();
+                let start_config = start_config.unwrap_or_default();
+                if !map_config.is_valid() || !start_config.is_valid() {
+                    return Some(ServerMessage::error("invalid_config", "Can't generate a playable map with these settings."));
+                }
+
+                let lobby_id = Uuid::new_v4().to_string();
+                let player = Player {
+                    id: self.player_id.clone(),
+                    name: player_name,
+                    color: PlayerColor::Red,
+                };
 
-            // Update connection state
-            {
-                let mut connections = state.connections.write().await;
-                if let Some(conn) = connections.get_mut(player_id) {
-                    conn.lobby_id = Some(lobby_id.clone());
+                let mut lobby = match seed {
+                    Some(seed) => Lobby::with_seed(lobby_id.clone(), player, map_size, seed),
+                    None => Lobby::new(lobby_id.clone(), player, map_size),
+                };
+                lobby.map_config = map_config;
+                lobby.start_config = start_config;
+                if let Err(e) = state.store.create_lobby(lobby.clone()).await {
+                    return Some(ServerMessage::error("store_error", format!("Failed to create lobby: {}", e)));
                 }
-            }
+                let token = match issue_token(state, lobby.players[0].clone(), Some(&lobby_id), None).await {
+                    Ok(token) => token,
+                    Err(e) => {
+                        return Some(ServerMessage::error("store_error", format!("Failed to create lobby: {}", e)));
+                    }
+                };
 
-            // Broadcast lobby state to the creator (so they see the lobby room)
-            let lobby_update = ServerMessage::LobbyUpdated { lobby: lobby.clone() };
-            let _ = tx.send(serde_json::to_string(&lobby_update).unwrap());
+                // Subscribe to lobby channel
+                let tx = self.enter_lobby(state, &lobby_id, token.clone()).await;
 
-            Some(ServerMessage::LobbyCreated {
-                lobby_id,
-                player_id: player_id.to_string(),
-            })
-        }
+                // Broadcast lobby state to the creator (so they see the lobby room)
+                let lobby_update = ServerMessage::LobbyUpdated { lobby: lobby.clone() };
+                let _ = tx.send(serde_json::to_string(&lobby_update).unwrap());
 
-        ClientMessage::JoinLobby {
-            lobby_id,
-            player_name,
-        } => {
-            // Prevent joining if already in a lobby
-            if current_lobby_id.is_some() {
-                return Some(ServerMessage::Error {
-                    message: "Already in a lobby. Leave first before joining another.".to_string(),
-                });
+                Some(ServerMessage::LobbyCreated {
+                    lobby_id,
+                    player_id: self.player_id.clone(),
+                    token,
+                })
             }
 
-            let lobby = match state.store.get_lobby(&lobby_id).await {
-                Ok(Some(l)) => l,
-                Ok(None) => {
-                    return Some(ServerMessage::Error {
-                        message: "Lobby not found".to_string(),
-                    });
+            ClientMessage::JoinLobby {
+                lobby_id,
+                player_name,
+            } => {
+                // Prevent joining if already in a lobby
+                if self.lobby_id.is_some() {
+                    return Some(ServerMessage::error("already_in_lobby", "Already in a lobby. Leave first before joining another."));
+                }
+
+                let lobby = match state.store.get_lobby(&lobby_id).await {
+                    Ok(Some(l)) => l,
+                    Ok(None) => {
+                        return Some(ServerMessage::error("lobby_not_found", "Lobby not found"));
+                    }
+                    Err(e) => {
+                        return Some(ServerMessage::error("store_error", format!("Failed to get lobby: {}", e)));
+                    }
+                };
+
+                if !lobby.can_join() {
+                    return Some(ServerMessage::error("cannot_join_lobby", "Cannot join this lobby"));
                 }
-                Err(e) => {
-                    return Some(ServerMessage::Error {
-                        message: format!("Failed to get lobby: {}", e),
-                    });
+
+                // Prevent joining a lobby you're already in
+                if lobby.players.iter().any(|p| p.id == self.player_id) {
+                    return Some(ServerMessage::error("already_in_lobby", "You are already in this lobby"));
                 }
-            };
 
-            if !lobby.can_join() {
-                return Some(ServerMessage::Error {
-                    message: "Cannot join this lobby".to_string(),
-                });
-            }
+                let player = Player {
+                    id: self.player_id.clone(),
+                    name: player_name,
+                    color: PlayerColor::from_index(lobby.players.len()),
+                };
 
-            // Prevent joining a lobby you're already in
-            if lobby.players.iter().any(|p| p.id == player_id) {
-                return Some(ServerMessage::Error {
-                    message: "You are already in this lobby".to_string(),
-                });
-            }
+                let mut updated_lobby = lobby;
+                updated_lobby.players.push(player.clone());
 
-            let player = Player {
-                id: player_id.to_string(),
-                name: player_name,
-                color: PlayerColor::from_index(lobby.players.len()),
-            };
+                if let Err(e) = state.store.update_lobby(updated_lobby.clone()).await {
+                    return Some(ServerMessage::error("store_error", format!("Failed to join lobby: {}", e)));
+                }
+                let token = match issue_token(state, player, Some(&lobby_id), None).await {
+                    Ok(token) => token,
+                    Err(e) => {
+                        return Some(ServerMessage::error("store_error", format!("Failed to join lobby: {}", e)));
+                    }
+                };
+
+                // Subscribe to lobby channel
+                let tx = self.enter_lobby(state, &lobby_id, token.clone()).await;
 
-            let mut updated_lobby = lobby;
-            updated_lobby.players.push(player);
+                // Broadcast updated lobby to all players
+                let update_msg = ServerMessage::LobbyUpdated {
+                    lobby: updated_lobby.clone(),
+                };
+                let _ = tx.send(serde_json::to_string(&update_msg).unwrap());
 
-            if let Err(e) = state.store.update_lobby(updated_lobby.clone()).await {
-                return Some(ServerMessage::Error {
-                    message: format!("Failed to join lobby: {}", e),
-                });
+                Some(ServerMessage::JoinedLobby {
+                    lobby: updated_lobby,
+                    player_id: self.player_id.clone(),
+                    token,
+                })
             }
 
-            // Subscribe to lobby channel
-            let tx = state.get_or_create_lobby_channel(&lobby_id).await;
-            *lobby_rx = Some(tx.subscribe());
-            *current_lobby_id = Some(lobby_id.clone());
+            ClientMessage::RejoinLobby { token } => {
+                if self.lobby_id.is_some() {
+                    return Some(ServerMessage::error("already_in_lobby", "Already in a lobby. Leave first before joining another."));
+                }
 
-            // Update connection state
-            {
-                let mut connections = state.connections.write().await;
-                if let Some(conn) = connections.get_mut(player_id) {
-                    conn.lobby_id = Some(lobby_id);
+                let (player, lobby_id) = match state.store.get_session(&token).await {
+                    Ok(Some(PlayerSession { player, lobby_id: Some(lobby_id), .. })) => (player, lobby_id),
+                    Ok(_) => return Some(invalid_token()),
+                    Err(e) => {
+                        return Some(ServerMessage::error("store_error", format!("Failed to look up token: {}", e)));
+                    }
+                };
+
+                let lobby = match state.store.get_lobby(&lobby_id).await {
+                    Ok(Some(l)) if l.status == LobbyStatus::Waiting => l,
+                    _ => {
+                        // The lobby is gone or its game has started; the token is no use any more
+                        let _ = state.store.delete_session(&token).await;
+                        return Some(ServerMessage::error("lobby_not_found", "Lobby not found"));
+                    }
+                };
+
+                // Dropping the old connection took the player out of the lobby; put them back
+                // if there is still room
+                let mut updated_lobby = lobby;
+                if !updated_lobby.players.iter().any(|p| p.id == player.id) {
+                    if !updated_lobby.can_join() {
+                        return Some(ServerMessage::error("cannot_join_lobby", "Cannot join this lobby"));
+                    }
+                    let color = PlayerColor::from_index(updated_lobby.players.len());
+                    updated_lobby.players.push(Player { color, ..player.clone() });
+                    if let Err(e) = state.store.update_lobby(updated_lobby.clone()).await {
+                        return Some(ServerMessage::error("store_error", format!("Failed to join lobby: {}", e)));
+                    }
                 }
-            }
 
-            // Broadcast updated lobby to all players
-            let update_msg = ServerMessage::LobbyUpdated {
-                lobby: updated_lobby.clone(),
-            };
-            let _ = tx.send(serde_json::to_string(&update_msg).unwrap());
+                tracing::info!("Player {} rejoined lobby {}", player.id, lobby_id);
+                self.player_id = player.id;
+                let tx = self.enter_lobby(state, &lobby_id, token.clone()).await;
 
-            Some(ServerMessage::JoinedLobby {
-                lobby: updated_lobby,
-                player_id: player_id.to_string(),
-            })
-        }
+                let update_msg = ServerMessage::LobbyUpdated {
+                    lobby: updated_lobby.clone(),
+                };
+                let _ = tx.send(serde_json::to_string(&update_msg).unwrap());
 
-        ClientMessage::LeaveLobby => {
-            if let Some(lobby_id) = current_lobby_id.take() {
-                leave_lobby(player_id, &lobby_id, state).await;
-                *lobby_rx = None;
+                Some(ServerMessage::JoinedLobby {
+                    lobby: updated_lobby,
+                    player_id: self.player_id.clone(),
+                    token,
+                })
+            }
 
-                // Update connection state
-                {
-                    let mut connections = state.connections.write().await;
-                    if let Some(conn) = connections.get_mut(player_id) {
-                        conn.lobby_id = None;
+            ClientMessage::LeaveLobby => {
+                if let Some(lobby_id) = self.lobby_id.take() {
+                    leave_lobby(&self.player_id, &lobby_id, state).await;
+                    self.rx = None;
+                    // Leaving on purpose gives up the way back in
+                    if let Some(token) = self.lobby_token.take() {
+                        let _ = state.store.delete_session(&token).await;
                     }
+                    self.update_connection(state).await;
                 }
+                None
             }
-            None
-        }
 
-        ClientMessage::StartGame => {
-            let lobby_id = match current_lobby_id {
-                Some(id) => id.clone(),
-                None => {
-                    return Some(ServerMessage::Error {
-                        message: "Not in a lobby".to_string(),
-                    });
+            ClientMessage::StartGame => {
+                let lobby_id = match &self.lobby_id {
+                    Some(id) => id.clone(),
+                    None => {
+                        return Some(ServerMessage::error("not_in_lobby", "Not in a lobby"));
+                    }
+                };
+
+                let lobby = match state.store.get_lobby(&lobby_id).await {
+                    Ok(Some(l)) => l,
+                    _ => {
+                        return Some(ServerMessage::error("lobby_not_found", "Lobby not found"));
+                    }
+                };
+
+                if lobby.host_id != self.player_id {
+                    return Some(ServerMessage::error("not_host", "Only the host can start the game"));
                 }
-            };
-
-            let lobby = match state.store.get_lobby(&lobby_id).await {
-                Ok(Some(l)) => l,
-                _ => {
-                    return Some(ServerMessage::Error {
-                        message: "Lobby not found".to_string(),
-                    });
+
+                if !lobby.can_start() {
+                    return Some(ServerMessage::error("not_enough_players", "Need at least 2 players to start"));
                 }
-            };
 
-            if lobby.host_id != player_id {
-                return Some(ServerMessage::Error {
-                    message: "Only the host can start the game".to_string(),
-                });
-            }
+                // Create game session with timestamp
+                let mut game = GameSession::from_lobby(&lobby);
+                game.turn_started_at_ms = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap()
+                    .as_millis() as u64;
 
-            if !lobby.can_start() {
-                return Some(ServerMessage::Error {
-                    message: "Need at least 2 players to start".to_string(),
-                });
-            }
+                // Update lobby status
+                let mut updated_lobby = lobby;
+                updated_lobby.status = LobbyStatus::InGame;
+                let _ = state.store.update_lobby(updated_lobby).await;
 
-            // Create game session with timestamp
-            let mut game = GameSession::from_lobby(&lobby);
-            game.turn_started_at_ms = std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_millis() as u64;
+                // Start the game with timer; the game manager saves it
+                state.game_manager.start_game(game.clone()).await;
 
-            // Update lobby status
-            let mut updated_lobby = lobby;
-            updated_lobby.status = LobbyStatus::InGame;
-            let _ = state.store.update_lobby(updated_lobby).await;
+                // Set current game ID
+                self.game_id = Some(game.id.clone());
 
-            // Save game
-            let _ = state.store.save_game(game.clone()).await;
+                // Each player in the lobby room gets the game as they can see it, and a token to
+                // rejoin it with
+                let connections = state.connections.read().await;
+                for player in &game.players {
+                    let Some(conn) = connections
+                        .values()
+                        .find(|c| c.player_id == player.id && c.lobby_id.as_deref() == Some(lobby_id.as_str()))
+                    else {
+                        continue;
+                    };
+                    let Ok(token) = issue_token(state, player.clone(), None, Some(&game.id)).await else {
+                        tracing::error!("Failed to issue a game token to {}", player.id);
+                        continue;
+                    };
+                    if let Some((seq, view)) = state.game_manager.snapshot(&game.id, &player.id).await {
+                        let start_msg = ServerMessage::GameStarted { game: view, last_seq: seq, token };
+                        let _ = conn.direct.send(serde_json::to_string(&start_msg).unwrap());
+                    }
+                }
 
-            // Get channel and start the game with timer
-            let tx = state.get_or_create_lobby_channel(&lobby_id).await;
-            state.game_manager.start_game(game.clone(), tx.clone()).await;
+                None
+            }
 
-            // Set current game ID
-            *current_game_id = Some(game.id.clone());
+            ClientMessage::EndTurn { game_id } => {
+                let Some(player_id) = self.game_player(&game_id) else {
+                    return Some(not_in_game());
+                };
+                tracing::info!("EndTurn received: game_id={}, player_id={}", game_id, player_id);
+                match state.game_manager.end_turn(&game_id, player_id).await {
+                    Ok(_) => {
+                        tracing::info!("EndTurn succeeded");
+                        None // TurnChanged already broadcast to subscribed clients
+                    }
+                    Err(e) => {
+                        tracing::error!("EndTurn failed: {}", e);
+                        Some(e.into())
+                    }
+                }
+            }
 
-            // Broadcast game start to all players
-            let start_msg = ServerMessage::GameStarted { game: game.clone() };
-            let _ = tx.send(serde_json::to_string(&start_msg).unwrap());
+            ClientMessage::RejoinGame { game_id, token } => {
+                tracing::info!("RejoinGame received: game_id={}", game_id);
 
-            Some(ServerMessage::GameStarted { game })
-        }
+                // The token says who is rejoining; it has to be one issued for this game
+                let msg_player_id = match state.store.get_session(&token).await {
+                    Ok(Some(session)) if session.game_id.as_deref() == Some(game_id.as_str()) => session.player.id,
+                    Ok(_) => return Some(invalid_token()),
+                    Err(e) => {
+                        return Some(ServerMessage::error("store_error", format!("Failed to look up token: {}", e)));
+                    }
+                };
 
-        ClientMessage::EndTurn { game_id, player_id: msg_player_id } => {
-            tracing::info!("EndTurn received: game_id={}, player_id={}", game_id, msg_player_id);
-            match state.game_manager.end_turn(&game_id, &msg_player_id).await {
-                Ok(game) => {
-                    tracing::info!("EndTurn succeeded");
-                    // Return TurnChanged directly (broadcast also sent to subscribed clients)
-                    Some(ServerMessage::TurnChanged {
-                        current_turn: game.current_turn,
-                        player_times_ms: game.player_times_ms.clone(),
-                        player_gold: game.player_gold.clone(),
-                        units: game.units.clone(),
-                        cities: game.cities.clone(),
-                        explored_tiles: game.explored_tiles.clone(),
-                    })
+                // Get the game
+                let game = match state.game_manager.get_game(&game_id).await {
+                    Some(g) => g,
+                    None => {
+                        return Some(GameError::GameNotFound.into());
+                    }
+                };
+
+                // Verify player is in this game
+                if !game.players.iter().any(|p| p.id == msg_player_id) {
+                    return Some(not_in_game());
                 }
-                Err(e) => {
-                    tracing::error!("EndTurn failed: {}", e);
-                    Some(ServerMessage::Error { message: e })
+
+                // Subscribe to this player's game channel before taking the snapshot, so no delta
+                // falls in between (the client skips deltas the snapshot already includes). From
+                // here on this socket acts as that player.
+                let Some(tx) = state.game_manager.player_channel(&game_id, &msg_player_id).await else {
+                    return Some(GameError::GameNotFound.into());
+                };
+                if let Some(previous) = self.present_in.take() {
+                    state.game_manager.player_disconnected(&previous, &self.player_id).await;
                 }
-            }
-        }
+                self.rx = Some(tx.subscribe());
+                self.game_id = Some(game_id.clone());
+                self.player_id = msg_player_id.clone();
+                self.update_connection(state).await;
+                tracing::info!("Player {} rejoined game {}", msg_player_id, game_id);
+
+                let Some((seq, view)) = state.game_manager.snapshot(&game_id, &msg_player_id).await else {
+                    return Some(GameError::GameNotFound.into());
+                };
 
-        ClientMessage::RejoinGame { game_id, player_id: msg_player_id } => {
-            tracing::info!("RejoinGame received: game_id={}, player_id={}", game_id, msg_player_id);
-            
-            // Get the game
-            let game = match state.game_manager.get_game(&game_id).await {
-                Some(g) => g,
-                None => {
-                    return Some(ServerMessage::Error {
-                        message: "Game not found".to_string(),
-                    });
+                // Announced after the snapshot so this client hears it too, along with who else
+                // is away or gone
+                state.game_manager.player_connected(&game_id, &msg_player_id).await;
+                self.present_in = Some(game_id.clone());
+                let report = state.game_manager.presence_report(&game_id).await;
+                let connections = state.connections.read().await;
+                if let Some(conn) = connections.get(&self.connection_id) {
+                    for msg in report {
+                        let _ = conn.direct.send(serde_json::to_string(&msg).unwrap());
+                    }
                 }
-            };
 
-            // Verify player is in this game
-            if !game.players.iter().any(|p| p.id == msg_player_id) {
-                return Some(ServerMessage::Error {
-                    message: "You are not in this game".to_string(),
-                });
+                Some(ServerMessage::GameRejoined { game: view, last_seq: seq })
             }
 
-            // Subscribe to game's broadcast channel
-            if let Some(tx) = state.game_manager.get_channel_async(&game_id).await {
-                *lobby_rx = Some(tx.subscribe());
-                *current_game_id = Some(game_id.clone());
-                tracing::info!("Player {} rejoined game {}", msg_player_id, game_id);
+            ClientMessage::Resign { game_id } => {
+                self.apply_command(state, &game_id, |player_id| GameCommand::Resign { player_id }).await
             }
 
-            Some(ServerMessage::GameRejoined { game })
-        }
+            ClientMessage::OfferDraw { game_id } => {
+                self.apply_command(state, &game_id, |player_id| GameCommand::OfferDraw { player_id }).await
+            }
 
-        ClientMessage::MoveUnit { game_id, player_id: msg_player_id, unit_id, to_q, to_r } => {
-            tracing::info!("MoveUnit received: game_id={}, player_id={}, unit_id={}, to=({},{})", 
-                game_id, msg_player_id, unit_id, to_q, to_r);
-            
-            match state.game_manager.move_unit(&game_id, &msg_player_id, &unit_id, to_q, to_r).await {
-                Ok(outcome) => {
-                    tracing::info!("MoveUnit succeeded, movement_remaining={}", outcome.movement_remaining);
-                    // Server already broadcasts via channel with explored_tiles, return None to avoid duplicate
-                    None
-                }
-                Err(e) => {
-                    tracing::error!("MoveUnit failed: {}", e);
-                    Some(ServerMessage::Error { message: e })
-                }
+            ClientMessage::AcceptDraw { game_id } => {
+                self.apply_command(state, &game_id, |player_id| GameCommand::AcceptDraw { player_id }).await
             }
-        }
 
-        ClientMessage::AttackUnit { game_id, player_id: msg_player_id, attacker_id, defender_id } => {
-            tracing::info!("AttackUnit received: game_id={}, attacker={}, defender={}", 
-                game_id, attacker_id, defender_id);
-            
-            match state.game_manager.attack_unit(&game_id, &msg_player_id, &attacker_id, &defender_id).await {
-                Ok(outcome) => {
-                    tracing::info!("AttackUnit succeeded: {:?}", outcome);
-                    Some(ServerMessage::CombatResult {
-                        attacker_id,
-                        defender_id,
-                        attacker_hp: outcome.attacker_hp,
-                        defender_hp: outcome.defender_hp,
-                        damage_to_attacker: outcome.damage_to_attacker,
-                        damage_to_defender: outcome.damage_to_defender,
-                        attacker_died: outcome.attacker_died,
-                        defender_died: outcome.defender_died,
-                        attacker_new_q: outcome.attacker_new_q,
-                        attacker_new_r: outcome.attacker_new_r,
-                    })
-                }
-                Err(e) => {
-                    tracing::error!("AttackUnit failed: {}", e);
-                    Some(ServerMessage::Error { message: e })
+            ClientMessage::AbortGame { game_id } => {
+                self.apply_command(state, &game_id, |player_id| GameCommand::Abort { player_id }).await
+            }
+
+            ClientMessage::SetAway { game_id, away } => {
+                let Some(player_id) = self.game_player(&game_id) else {
+                    return Some(not_in_game());
+                };
+                state.game_manager.set_away(&game_id, player_id, away).await;
+                None
+            }
+
+            ClientMessage::RequestSync { game_id, since_seq } => {
+                let Some(player_id) = self.game_player(&game_id) else {
+                    return Some(not_in_game());
+                };
+                match state.game_manager.resync(&game_id, player_id, since_seq).await {
+                    Some(Resync::Missed(missed)) => {
+                        tracing::info!("Resending {} game messages to {}", missed.len(), player_id);
+                        let connections = state.connections.read().await;
+                        if let Some(conn) = connections.get(&self.connection_id) {
+                            for json in missed {
+                                let _ = conn.direct.send(json);
+                            }
+                        }
+                        None
+                    }
+                    Some(Resync::Snapshot(seq, view)) => Some(ServerMessage::Snapshot { game: *view, last_seq: seq }),
+                    None => Some(GameError::GameNotFound.into()),
                 }
             }
+
+            ClientMessage::MoveUnit { game_id, unit_id, to_q, to_r } => {
+                self.apply_command(state, &game_id, |player_id| GameCommand::MoveUnit { player_id, unit_id, to_q, to_r })
+                    .await
+            }
+
+            ClientMessage::MoveUnitPath { game_id, unit_id, path } => {
+                self.apply_command(state, &game_id, |player_id| GameCommand::MoveUnitPath { player_id, unit_id, path })
+                    .await
+            }
+
+            ClientMessage::AttackUnit { game_id, attacker_id, defender_id } => {
+                self.apply_command(state, &game_id, |player_id| GameCommand::AttackUnit {
+                    player_id,
+                    attacker_id,
+                    defender_id,
+                })
+                .await
+            }
+
+            ClientMessage::FortifyUnit { game_id, unit_id } => {
+                self.apply_command(state, &game_id, |player_id| GameCommand::FortifyUnit { player_id, unit_id })
+                    .await
+            }
+
+            ClientMessage::BuyUnit { game_id, city_id, unit_type } => {
+                // Parse unit type
+                let unit_type = match unit_type.as_str() {
+                    "Conscript" => palmietopia_core::UnitType::Conscript,
+                    "Knight" => palmietopia_core::UnitType::Knight,
+                    "Bowman" => palmietopia_core::UnitType::Bowman,
+                    "Explorer" => palmietopia_core::UnitType::Explorer,
+                    _ => return Some(GameError::InvalidUnitType { unit_type }.into()),
+                };
+                self.apply_command(state, &game_id, |player_id| GameCommand::BuyUnit { player_id, city_id, unit_type })
+                    .await
+            }
         }
+    }
+}
 
-        ClientMessage::FortifyUnit { game_id, player_id: msg_player_id, unit_id } => {
-            tracing::info!("FortifyUnit received: game_id={}, player_id={}, unit_id={}", 
-                game_id, msg_player_id, unit_id);
-            
-            match state.game_manager.fortify_unit(&game_id, &msg_player_id, &unit_id).await {
-                Ok(new_hp) => {
-                    tracing::info!("FortifyUnit succeeded, new_hp={}", new_hp);
-                    Some(ServerMessage::UnitFortified { unit_id, new_hp })
+pub async fn handle_socket(socket: WebSocket, state: Arc<AppState>) {
+    let (mut sender, mut receiver) = socket.split();
+    let (mut session, mut direct_rx) = Session::connect(&state).await;
+
+    loop {
+        tokio::select! {
+            // Handle incoming messages from client
+            msg = receiver.next() => {
+                match msg {
+                    Some(Ok(Message::Text(text))) => {
+                        match serde_json::from_str::<ClientMessage>(&text) {
+                            Ok(client_msg) => {
+                                if let Some(msg) = session.handle(client_msg, &state).await {
+                                    let json = serde_json::to_string(&msg).unwrap();
+                                    if sender.send(Message::Text(json.into())).await.is_err() {
+                                        break;
+                                    }
+                                }
+                            }
+                            Err(e) => {
+                                let error = ServerMessage::error("invalid_message", format!("Invalid message format: {}", e));
+                                let json = serde_json::to_string(&error).unwrap();
+                                let _ = sender.send(Message::Text(json.into())).await;
+                            }
+                        }
+                    }
+                    Some(Ok(Message::Close(_))) | None => break,
+                    _ => {}
                 }
-                Err(e) => {
-                    tracing::error!("FortifyUnit failed: {}", e);
-                    Some(ServerMessage::Error { message: e })
+            }
+
+            Some(msg) = direct_rx.recv() => {
+                if sender.send(Message::Text(msg.into())).await.is_err() {
+                    break;
                 }
             }
-        }
 
-        ClientMessage::BuyUnit { game_id, player_id: msg_player_id, city_id, unit_type } => {
-            tracing::info!("BuyUnit received: game_id={}, player_id={}, city_id={}, unit_type={}", 
-                game_id, msg_player_id, city_id, unit_type);
-            
-            // Parse unit type
-            let parsed_unit_type = match unit_type.as_str() {
-                "Conscript" => palmietopia_core::UnitType::Conscript,
-                "Knight" => palmietopia_core::UnitType::Knight,
-                "Bowman" => palmietopia_core::UnitType::Bowman,
-                "Explorer" => palmietopia_core::UnitType::Explorer,
-                _ => return Some(ServerMessage::Error { message: "Invalid unit type".to_string() }),
-            };
-            
-            match state.game_manager.buy_unit(&game_id, &msg_player_id, &city_id, parsed_unit_type).await {
-                Ok((unit, player_gold)) => {
-                    tracing::info!("BuyUnit succeeded, unit_id={}, gold={}", unit.id, player_gold);
-                    None // Broadcast already sent by game_manager
+            // Handle broadcast messages from the lobby or game channel
+            broadcast_msg = async {
+                if let Some(ref mut rx) = session.rx {
+                    rx.recv().await
+                } else {
+                    std::future::pending::<Result<String, RecvError>>().await
                 }
-                Err(e) => {
-                    tracing::error!("BuyUnit failed: {}", e);
-                    Some(ServerMessage::Error { message: e })
+            } => {
+                match broadcast_msg {
+                    Ok(msg) => {
+                        if sender.send(Message::Text(msg.into())).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(RecvError::Lagged(missed)) => {
+                        // The channel dropped messages this socket hadn't read yet: send a fresh
+                        // snapshot so the client doesn't carry on from stale state
+                        tracing::warn!("Socket {} lagged behind by {} messages", session.connection_id, missed);
+                        if let Some(game_id) = &session.game_id
+                            && let Some((seq, view)) = state.game_manager.snapshot(game_id, &session.player_id).await
+                        {
+                            let json = serde_json::to_string(&ServerMessage::Snapshot { game: view, last_seq: seq }).unwrap();
+                            if sender.send(Message::Text(json.into())).await.is_err() {
+                                break;
+                            }
+                        }
+                    }
+                    Err(RecvError::Closed) => session.rx = None,
                 }
             }
         }
     }
+
+    // Cleanup on disconnect
+    session.disconnect(&state).await;
+}
+
+fn not_in_game() -> ServerMessage {
+    ServerMessage::error("not_in_game", "You are not in this game")
+}
+
+fn invalid_token() -> ServerMessage {
+    ServerMessage::error("invalid_token", "Unknown or expired session token")
+}
+
+/// Mint a reconnect token for the player and remember what it is for. Tokens are random v4
+/// UUIDs, known only to the server and the client they are sent to.
+async fn issue_token(
+    state: &Arc<AppState>,
+    player: Player,
+    lobby_id: Option<&str>,
+    game_id: Option<&str>,
+) -> StoreResult<String> {
+    let token = Uuid::new_v4().to_string();
+    let session = PlayerSession {
+        token: token.clone(),
+        player,
+        lobby_id: lobby_id.map(str::to_string),
+        game_id: game_id.map(str::to_string),
+    };
+    state.store.save_session(session).await?;
+    Ok(token)
 }
 
 async fn leave_lobby(player_id: &str, lobby_id: &str, state: &Arc<AppState>) {
@@ -485,15 +683,386 @@ async fn leave_lobby(player_id: &str, lobby_id: &str, state: &Arc<AppState>) {
     }
 }
 
-async fn handle_disconnect(player_id: &str, current_lobby_id: &Option<String>, state: &Arc<AppState>) {
-    // Remove from connections
-    {
-        let mut connections = state.connections.write().await;
-        connections.remove(player_id);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::autosave::AutosaveConfig;
+    use crate::presence::{DisconnectPolicy, PresenceConfig};
+    use crate::store::memory::InMemoryStore;
+    use palmietopia_core::{GameStatus, MapGenConfig, MapSize, Presence, Replay, SequencedMessage, StartConfig};
+    use std::time::Duration;
+    use tokio::sync::mpsc::UnboundedReceiver;
+
+    fn error_code(response: Option<ServerMessage>) -> Option<String> {
+        match response {
+            Some(ServerMessage::Error { code, .. }) => Some(code),
+            _ => None,
+        }
+    }
+
+    /// A host in a new lobby, and the lobby's id
+    async fn hosted_lobby() -> (Arc<AppState>, Session, String) {
+        let (state, host, _, lobby_id) = hosted_lobby_with(PresenceConfig::default()).await;
+        (state, host, lobby_id)
     }
 
-    // Leave lobby if in one
-    if let Some(lobby_id) = current_lobby_id {
-        leave_lobby(player_id, lobby_id, state).await;
+    async fn hosted_lobby_with(config: PresenceConfig) -> (Arc<AppState>, Session, UnboundedReceiver<String>, String) {
+        let state = Arc::new(AppState::new(Arc::new(InMemoryStore::new()), config, AutosaveConfig::default()));
+        let (mut host, host_rx) = Session::connect(&state).await;
+        let create = ClientMessage::CreateLobby {
+            player_name: "Host".to_string(),
+            map_size: MapSize::Small,
+            seed: Some(1),
+            map_config: None,
+            start_config: None,
+        };
+        let Some(ServerMessage::LobbyCreated { lobby_id, .. }) = host.handle(create, &state).await else {
+            panic!("lobby not created");
+        };
+        (state, host, host_rx, lobby_id)
+    }
+
+    /// Pick up a started game on a new socket, with the token the lobby socket was sent
+    async fn rejoin(state: &Arc<AppState>, lobby_rx: &mut UnboundedReceiver<String>) -> Session {
+        let started = serde_json::from_str(&lobby_rx.try_recv().unwrap()).unwrap();
+        let ServerMessage::GameStarted { game, token, .. } = started else {
+            panic!("player was not sent the game");
+        };
+        let (mut session, _) = Session::connect(state).await;
+        let rejoin = ClientMessage::RejoinGame { game_id: game.id, token };
+        assert!(matches!(session.handle(rejoin, state).await, Some(ServerMessage::GameRejoined { .. })));
+        session
+    }
+
+    /// A started two-player game, with both players on their game sockets
+    async fn started_game() -> (Arc<AppState>, Session, Session, String) {
+        started_game_with(PresenceConfig::default()).await
+    }
+
+    async fn started_game_with(config: PresenceConfig) -> (Arc<AppState>, Session, Session, String) {
+        let (state, host, mut guests, game_id) = started_game_for(config, 1).await;
+        (state, host, guests.pop().unwrap(), game_id)
+    }
+
+    /// A started game with the host and `guests` other players on their game sockets
+    async fn started_game_for(config: PresenceConfig, guests: usize) -> (Arc<AppState>, Session, Vec<Session>, String) {
+        let (state, mut host_lobby, mut host_rx, lobby_id) = hosted_lobby_with(config).await;
+        let mut guest_lobbies = Vec::new();
+        for i in 0..guests {
+            let (mut guest_lobby, guest_rx) = Session::connect(&state).await;
+            let join = ClientMessage::JoinLobby { lobby_id: lobby_id.clone(), player_name: format!("Guest {}", i + 1) };
+            guest_lobby.handle(join, &state).await;
+            guest_lobbies.push((guest_lobby, guest_rx));
+        }
+        assert!(host_lobby.handle(ClientMessage::StartGame, &state).await.is_none());
+
+        let game_id = host_lobby.game_id.clone().unwrap();
+        let host = rejoin(&state, &mut host_rx).await;
+        assert_eq!(host.player_id, host_lobby.player_id);
+        let mut guests = Vec::new();
+        for (guest_lobby, mut guest_rx) in guest_lobbies {
+            let guest = rejoin(&state, &mut guest_rx).await;
+            assert_eq!(guest.player_id, guest_lobby.player_id);
+            guests.push(guest);
+        }
+        (state, host, guests, game_id)
+    }
+
+    async fn current_turn(state: &AppState, game_id: &str) -> usize {
+        state.game_manager.get_game(game_id).await.unwrap().current_turn
+    }
+
+    #[tokio::test]
+    async fn commands_act_as_the_socket_player() {
+        let (state, mut host, mut guest, game_id) = started_game().await;
+        let game = state.game_manager.get_game(&game_id).await.unwrap();
+        let host_unit = game.units.iter().find(|u| u.owner_id == host.player_id).unwrap().id.clone();
+        let guest_unit = game.units.iter().find(|u| u.owner_id == guest.player_id).unwrap().id.clone();
+        assert_eq!(game.players[0].id, host.player_id);
+
+        // The guest can't end or play the host's turn
+        let end_turn = || ClientMessage::EndTurn { game_id: game_id.clone() };
+        assert_eq!(error_code(guest.handle(end_turn(), &state).await).as_deref(), Some("not_your_turn"));
+        let fortify = ClientMessage::FortifyUnit { game_id: game_id.clone(), unit_id: host_unit.clone() };
+        assert_eq!(error_code(guest.handle(fortify, &state).await).as_deref(), Some("not_your_turn"));
+        assert_eq!(current_turn(&state, &game_id).await, 0);
+
+        // On its own turn the guest still can't order the host's units around
+        assert!(host.handle(end_turn(), &state).await.is_none());
+        assert_eq!(current_turn(&state, &game_id).await, 1);
+        let fortify = ClientMessage::FortifyUnit { game_id: game_id.clone(), unit_id: host_unit };
+        assert_eq!(error_code(guest.handle(fortify, &state).await).as_deref(), Some("not_your_unit"));
+        let fortify = ClientMessage::FortifyUnit { game_id: game_id.clone(), unit_id: guest_unit };
+        assert!(guest.handle(fortify, &state).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn client_supplied_player_id_is_ignored() {
+        let (state, host, mut guest, game_id) = started_game().await;
+
+        // An old-style message naming the host is read as coming from the guest
+        let json = serde_json::json!({ "type": "EndTurn", "game_id": game_id, "player_id": host.player_id });
+        let msg: ClientMessage = serde_json::from_value(json).unwrap();
+        assert_eq!(error_code(guest.handle(msg, &state).await).as_deref(), Some("not_your_turn"));
+        assert_eq!(current_turn(&state, &game_id).await, 0);
+    }
+
+    #[tokio::test]
+    async fn sockets_outside_the_game_cannot_act() {
+        let (state, _host, _guest, game_id) = started_game().await;
+        let (mut stranger, _) = Session::connect(&state).await;
+
+        let end_turn = ClientMessage::EndTurn { game_id: game_id.clone() };
+        assert_eq!(error_code(stranger.handle(end_turn, &state).await).as_deref(), Some("not_in_game"));
+        let sync = ClientMessage::RequestSync { game_id: game_id.clone(), since_seq: 0 };
+        assert_eq!(error_code(stranger.handle(sync, &state).await).as_deref(), Some("not_in_game"));
+        assert_eq!(current_turn(&state, &game_id).await, 0);
+    }
+
+    #[tokio::test]
+    async fn rejoining_a_game_needs_its_token() {
+        let (state, _host, _guest, game_id) = started_game().await;
+        let (mut impostor, _) = Session::connect(&state).await;
+
+        let rejoin = |token: &str| ClientMessage::RejoinGame { game_id: game_id.clone(), token: token.to_string() };
+        assert_eq!(error_code(impostor.handle(rejoin("guessed"), &state).await).as_deref(), Some("invalid_token"));
+
+        // A lobby token doesn't get anyone into a game
+        let (state, mut host, lobby_id) = hosted_lobby().await;
+        let rejoin = ClientMessage::RejoinGame { game_id: lobby_id, token: host.lobby_token.clone().unwrap() };
+        assert_eq!(error_code(host.handle(rejoin, &state).await).as_deref(), Some("invalid_token"));
+    }
+
+    #[tokio::test]
+    async fn lobby_membership_survives_a_reconnect() {
+        let (state, _host, lobby_id) = hosted_lobby().await;
+        let (mut guest, _) = Session::connect(&state).await;
+        let join = ClientMessage::JoinLobby { lobby_id: lobby_id.clone(), player_name: "Guest".to_string() };
+        let Some(ServerMessage::JoinedLobby { token, player_id, .. }) = guest.handle(join, &state).await else {
+            panic!("guest did not join");
+        };
+
+        // Losing the connection drops the guest from the lobby; the token brings them back
+        guest.disconnect(&state).await;
+        assert_eq!(state.store.get_lobby(&lobby_id).await.unwrap().unwrap().players.len(), 1);
+        let (mut guest, _) = Session::connect(&state).await;
+        let rejoin = ClientMessage::RejoinLobby { token: token.clone() };
+        let Some(ServerMessage::JoinedLobby { lobby, player_id: rejoined_as, .. }) = guest.handle(rejoin, &state).await
+        else {
+            panic!("guest did not rejoin");
+        };
+        assert_eq!(rejoined_as, player_id);
+        assert!(lobby.players.iter().any(|p| p.id == player_id && p.name == "Guest"));
+
+        // Leaving on purpose spends the token
+        guest.handle(ClientMessage::LeaveLobby, &state).await;
+        let (mut guest, _) = Session::connect(&state).await;
+        let rejoin = ClientMessage::RejoinLobby { token };
+        assert_eq!(error_code(guest.handle(rejoin, &state).await).as_deref(), Some("invalid_token"));
+    }
+
+    #[tokio::test]
+    async fn lobbies_keep_the_hosts_map_settings() {
+        let state = Arc::new(AppState::new(Arc::new(InMemoryStore::new()), PresenceConfig::default(), AutosaveConfig::default()));
+        let (mut host, _) = Session::connect(&state).await;
+        let create = |map_config| ClientMessage::CreateLobby {
+            player_name: "Host".to_string(),
+            map_size: MapSize::Small,
+            seed: None,
+            map_config: Some(map_config),
+            start_config: None,
+        };
+
+        let flooded = MapGenConfig { water_ratio: 1.0, ..MapGenConfig::default() };
+        assert_eq!(error_code(host.handle(create(flooded), &state).await).as_deref(), Some("invalid_config"));
+
+        let dry = MapGenConfig { water_ratio: 0.1, ..MapGenConfig::default() };
+        let Some(ServerMessage::LobbyCreated { lobby_id, .. }) = host.handle(create(dry.clone()), &state).await else {
+            panic!("lobby not created");
+        };
+        let lobby = state.store.get_lobby(&lobby_id).await.unwrap().unwrap();
+        assert_eq!(lobby.map_config, dry);
+        assert_eq!(lobby.start_config, StartConfig::default());
+    }
+
+    #[tokio::test]
+    async fn games_resume_after_a_restart() {
+        let (state, mut host, guest, game_id) = started_game().await;
+        assert!(host.handle(ClientMessage::EndTurn { game_id: game_id.clone() }, &state).await.is_none());
+        let before = state.game_manager.get_game(&game_id).await.unwrap();
+        let guest_player = before.players[1].clone();
+        let token = issue_token(&state, guest_player.clone(), None, Some(&game_id)).await.unwrap();
+        // The turn change is in the log, not yet in a snapshot
+        assert_eq!(state.store.load_actions(&game_id).await.unwrap().len(), 1);
+
+        // A new server on the same store picks the game up where it was
+        let config = AutosaveConfig::default();
+        let restarted = Arc::new(AppState::new(Arc::clone(&state.store), PresenceConfig::default(), config));
+        assert_eq!(restarted.game_manager.resume_games().await.unwrap(), 1);
+        assert!(state.store.load_actions(&game_id).await.unwrap().is_empty());
+        let (mut guest_again, _) = Session::connect(&restarted).await;
+        let rejoin = ClientMessage::RejoinGame { game_id: game_id.clone(), token };
+        let Some(ServerMessage::GameRejoined { game, .. }) = guest_again.handle(rejoin, &restarted).await else {
+            panic!("guest could not rejoin the resumed game");
+        };
+        assert_eq!(guest_again.player_id, guest.player_id);
+        assert_eq!((game.current_turn, game.turn_number), (before.current_turn, before.turn_number));
+        assert_eq!(game.units.len(), before.visible_units(&guest_player.id).len());
+
+        // Only the time actually played comes off the clock
+        let used = before.player_times_ms[1] - game.player_times_ms[1];
+        assert!(used < 1_000, "charged {}ms", used);
+        assert!(guest_again.handle(ClientMessage::EndTurn { game_id: game_id.clone() }, &restarted).await.is_none());
+        assert_eq!(current_turn(&restarted, &game_id).await, 0);
+    }
+
+    #[tokio::test]
+    async fn resumed_games_keep_their_whole_replay() {
+        let (state, mut host, mut guest, game_id) = started_game().await;
+        let end_turn = || ClientMessage::EndTurn { game_id: game_id.clone() };
+        assert!(host.handle(end_turn(), &state).await.is_none());
+        assert!(guest.handle(end_turn(), &state).await.is_none());
+        let replay = async |state: &AppState| {
+            let games = state.game_manager.active_games.read().await;
+            serde_json::to_value(&games[&game_id].replay).unwrap()
+        };
+        let before = replay(&state).await;
+        assert_eq!(before["entries"].as_array().unwrap().len(), 2);
+
+        // Restart twice: first with the turns only in the log, then with them in the snapshot
+        // and one more in the log
+        let restart = async || {
+            let restarted = Arc::new(AppState::new(Arc::clone(&state.store), PresenceConfig::default(), AutosaveConfig::default()));
+            assert_eq!(restarted.game_manager.resume_games().await.unwrap(), 1);
+            restarted
+        };
+        let restarted = restart().await;
+        assert_eq!(replay(&restarted).await, before);
+        restarted.game_manager.end_turn(&game_id, &host.player_id).await.unwrap();
+        let before = replay(&restarted).await;
+        assert_eq!(before["entries"].as_array().unwrap().len(), 3);
+
+        let restarted = restart().await;
+        assert_eq!(replay(&restarted).await, before);
+        let game = restarted.game_manager.get_game(&game_id).await.unwrap();
+        let replay: Replay = serde_json::from_value(before).unwrap();
+        assert_eq!(replay.initial.turn_number, 0, "the replay still starts at the first turn");
+        assert_eq!(replay.session_at_turn(3).unwrap().turn_number, game.turn_number);
+    }
+
+    /// Wait for the game timer to get round to something
+    async fn eventually(mut check: impl AsyncFnMut() -> bool) {
+        for _ in 0..30 {
+            if check().await {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("timed out waiting for the game timer");
+    }
+
+    /// The next sequenced message on a game feed, passing over clock ticks
+    async fn next_update(feed: &mut broadcast::Receiver<String>) -> ServerMessage {
+        loop {
+            let json = feed.recv().await.unwrap();
+            if let Ok(msg) = serde_json::from_str::<SequencedMessage>(&json) {
+                return msg.message;
+            }
+            assert!(matches!(serde_json::from_str(&json).unwrap(), ServerMessage::TimeTick { .. }));
+        }
+    }
+
+    #[tokio::test]
+    async fn clock_ticks_are_not_kept_in_the_feed() {
+        let (state, host, _guest, game_id) = started_game().await;
+        let mut host_feed = state.game_manager.player_channel(&game_id, &host.player_id).await.unwrap().subscribe();
+        let (seq, _) = state.game_manager.snapshot(&game_id, &host.player_id).await.unwrap();
+
+        let tick = serde_json::from_str(&host_feed.recv().await.unwrap()).unwrap();
+        assert!(matches!(tick, ServerMessage::TimeTick { player_index: 0, .. }));
+        assert_eq!(state.game_manager.snapshot(&game_id, &host.player_id).await.unwrap().0, seq);
+        let Some(Resync::Missed(missed)) = state.game_manager.resync(&game_id, &host.player_id, 0).await else {
+            panic!("the feed's history was lost");
+        };
+        assert_eq!(missed.len() as u64, seq, "nothing but sequenced messages is kept");
+    }
+
+    #[tokio::test]
+    async fn dropped_players_are_announced_then_skipped() {
+        let config = PresenceConfig { grace_period: Duration::ZERO, policy: DisconnectPolicy::SkipTurns };
+        let (state, mut host, guest, game_id) = started_game_with(config).await;
+        let mut host_feed = state.game_manager.player_channel(&game_id, &host.player_id).await.unwrap().subscribe();
+
+        guest.disconnect(&state).await;
+        let announced = loop {
+            if let ServerMessage::PresenceChanged { player_id, presence, .. } = next_update(&mut host_feed).await {
+                break (player_id, presence);
+            }
+        };
+        assert_eq!(announced, (guest.player_id.clone(), Presence::Disconnected));
+
+        // Once the grace period is up the guest's turn passes straight back
+        assert!(host.handle(ClientMessage::EndTurn { game_id: game_id.clone() }, &state).await.is_none());
+        eventually(async || current_turn(&state, &game_id).await == 0).await;
+        assert_eq!(state.game_manager.get_game(&game_id).await.unwrap().turn_number, 2);
+    }
+
+    #[tokio::test]
+    async fn games_everyone_dropped_out_of_are_aborted() {
+        let config = PresenceConfig { grace_period: Duration::ZERO, policy: DisconnectPolicy::SkipTurns };
+        let (state, host, guest, game_id) = started_game_with(config).await;
+
+        guest.disconnect(&state).await;
+        host.disconnect(&state).await;
+        eventually(async || {
+            let game = state.store.load_game(&game_id).await.unwrap();
+            game.is_some_and(|g| g.status == GameStatus::Finished)
+        })
+        .await;
+        eventually(async || state.game_manager.get_game(&game_id).await.is_none()).await;
+    }
+
+    #[tokio::test]
+    async fn games_are_aborted_when_everyone_left_after_the_host() {
+        let config = PresenceConfig { grace_period: Duration::ZERO, policy: DisconnectPolicy::SkipTurns };
+        let (state, mut host, guests, game_id) = started_game_for(config, 2).await;
+        let mut host_feed = state.game_manager.player_channel(&game_id, &host.player_id).await.unwrap().subscribe();
+
+        assert!(host.handle(ClientMessage::Resign { game_id: game_id.clone() }, &state).await.is_none());
+        for guest in guests {
+            guest.disconnect(&state).await;
+        }
+        while !matches!(next_update(&mut host_feed).await, ServerMessage::GameAborted { player_id: None }) {}
+        eventually(async || {
+            let game = state.store.load_game(&game_id).await.unwrap();
+            game.is_some_and(|g| g.status == GameStatus::Finished)
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn dropped_players_can_be_resigned() {
+        let config = PresenceConfig { grace_period: Duration::ZERO, policy: DisconnectPolicy::Resign };
+        let (state, host, guest, game_id) = started_game_with(config).await;
+
+        guest.disconnect(&state).await;
+        eventually(async || {
+            let game = state.store.load_game(&game_id).await.unwrap();
+            game.is_some_and(|g| g.status == GameStatus::Victory { winner_id: host.player_id.clone() })
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn games_end_in_agreed_draws() {
+        let (state, mut host, mut guest, game_id) = started_game().await;
+
+        // Draw offers can be made and accepted out of turn, but only the host can abort
+        assert!(guest.handle(ClientMessage::OfferDraw { game_id: game_id.clone() }, &state).await.is_none());
+        let abort = ClientMessage::AbortGame { game_id: game_id.clone() };
+        assert_eq!(error_code(guest.handle(abort, &state).await).as_deref(), Some("not_host"));
+        let mut guest_feed = state.game_manager.player_channel(&game_id, &guest.player_id).await.unwrap().subscribe();
+        assert!(host.handle(ClientMessage::AcceptDraw { game_id: game_id.clone() }, &state).await.is_none());
+        while !matches!(next_update(&mut guest_feed).await, ServerMessage::GameDrawn) {}
     }
 }
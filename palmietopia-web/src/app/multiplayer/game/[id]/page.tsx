@@ -3,7 +3,8 @@ import { useEffect, useState, useCallback } from "react";
 import { useParams, useRouter } from "next/navigation";
 import { HexGrid } from "@/components/HexGrid";
 import { GameOverDialog } from "@/components/GameOverDialog";
-import { useWebSocket, GameSession, Unit } from "@/hooks/useWebSocket";
+import { useWebSocket, PlayerView, Unit } from "@/hooks/useWebSocket";
+import { useRules } from "@/hooks/useRules";
 import { PLAYER_COLORS, UNIT_STATS, UnitType } from "@/types/game";
 
 function formatTime(ms: number): string {
@@ -13,40 +14,6 @@ function formatTime(ms: number): string {
   return `${minutes}:${seconds.toString().padStart(2, "0")}`;
 }
 
-function hexDistance(q1: number, r1: number, q2: number, r2: number): number {
-  return (Math.abs(q1 - q2) + Math.abs(r1 - r2) + Math.abs(q1 + r1 - q2 - r2)) / 2;
-}
-
-function getAdjacentTiles(q: number, r: number): { q: number; r: number }[] {
-  return [
-    { q: q + 1, r },
-    { q: q - 1, r },
-    { q, r: r + 1 },
-    { q, r: r - 1 },
-    { q: q + 1, r: r - 1 },
-    { q: q - 1, r: r + 1 },
-  ];
-}
-
-// Get tiles within a given range
-function getTilesInRange(q: number, r: number, range: number): Array<[number, number]> {
-  const tiles: Array<[number, number]> = [];
-  for (let dq = -range; dq <= range; dq++) {
-    for (let dr = Math.max(-range, -dq - range); dr <= Math.min(range, -dq + range); dr++) {
-      tiles.push([q + dq, r + dr]);
-    }
-  }
-  return tiles;
-}
-
-const CITY_VISION_RANGE = 2;
-const VISION_RANGES: Record<string, number> = {
-  Conscript: 2,
-  Knight: 2,
-  Bowman: 3,
-  Explorer: 4,
-};
-
 export default function GamePage() {
   const params = useParams();
   const router = useRouter();
@@ -56,20 +23,28 @@ export default function GamePage() {
     isConnected,
     game,
     turnTimeRemaining,
+    presence,
     endTurn,
     rejoinGame,
-    moveUnit,
+    moveUnitPath,
     attackUnit,
     fortifyUnit,
     buyUnit,
+    resign,
+    offerDraw,
+    acceptDraw,
+    abortGame,
+    setAway,
   } = useWebSocket();
+  const rules = useRules();
 
-  const [initialGame, setInitialGame] = useState<GameSession | null>(null);
+  const [initialGame, setInitialGame] = useState<PlayerView | null>(null);
   const [localTimeRemaining, setLocalTimeRemaining] = useState<number>(0);
   const [myPlayerId, setMyPlayerId] = useState<string | null>(null);
   const [selectedUnitId, setSelectedUnitId] = useState<string | null>(null);
   const [selectedCityId, setSelectedCityId] = useState<string | null>(null);
   const [highlightedTiles, setHighlightedTiles] = useState<{ q: number; r: number }[]>([]);
+  const [targetedTiles, setTargetedTiles] = useState<{ q: number; r: number }[]>([]);
 
   useEffect(() => {
     const storedGame = sessionStorage.getItem(`game-${gameId}`);
@@ -89,12 +64,21 @@ export default function GamePage() {
   }, [gameId]);
 
   useEffect(() => {
-    if (isConnected && myPlayerId && gameId) {
+    const token = sessionStorage.getItem(`token-${gameId}`);
+    if (isConnected && token && gameId) {
       console.log("Rejoining game:", { gameId, myPlayerId });
-      rejoinGame(gameId, myPlayerId);
+      rejoinGame(gameId, token);
     }
   }, [isConnected, myPlayerId, gameId, rejoinGame]);
 
+  // Let the other players know when we switch to another tab
+  useEffect(() => {
+    if (!isConnected || !gameId) return;
+    const onVisibilityChange = () => setAway(gameId, document.hidden);
+    document.addEventListener("visibilitychange", onVisibilityChange);
+    return () => document.removeEventListener("visibilitychange", onVisibilityChange);
+  }, [isConnected, gameId, setAway]);
+
   const currentGame = game || initialGame;
 
   useEffect(() => {
@@ -123,34 +107,28 @@ export default function GamePage() {
     return () => clearInterval(interval);
   }, [currentGame?.turn_started_at_ms, currentGame?.current_turn, currentGame]);
 
-  // Calculate valid movement tiles for selected unit
-  const calculateMovementTiles = useCallback((unit: Unit) => {
-    if (!currentGame) return [];
-    
-    const validTiles: { q: number; r: number }[] = [];
-    const adjacent = getAdjacentTiles(unit.q, unit.r);
-    
-    for (const pos of adjacent) {
-      const tile = currentGame.map.tiles.find(t => t.q === pos.q && t.r === pos.r);
-      if (!tile) continue;
-      
-      // Check terrain passability and cost
-      let cost = 0;
-      if (tile.terrain === "Water") continue;
-      if (tile.terrain === "Mountain") cost = 2;
-      else cost = 1;
-      
-      if (unit.movement_remaining >= cost) {
-        // Check if tile is occupied by another unit
-        const occupied = (currentGame.units || []).some(u => u.q === pos.q && u.r === pos.r);
-        if (!occupied) {
-          validTiles.push(pos);
-        }
-      }
+  // Show where a unit can move this turn and which enemies it can attack, using the same
+  // rules as the server (terrain costs, blocked tiles, range and line of sight)
+  const highlightUnit = useCallback((unit: Unit | null) => {
+    if (!unit || !currentGame || !rules) {
+      setHighlightedTiles([]);
+      setTargetedTiles([]);
+      return;
     }
-    
-    return validTiles;
-  }, [currentGame]);
+    setHighlightedTiles(rules.reachableTiles(currentGame, unit.id).map(({ q, r }) => ({ q, r })));
+    const targets = rules.attackableUnits(currentGame, unit.id);
+    setTargetedTiles((currentGame.units || []).filter(u => targets.includes(u.id)).map(({ q, r }) => ({ q, r })));
+  }, [currentGame, rules]);
+
+  // Walk to a tile along the cheapest route. Routes longer than the unit's movement are
+  // walked as far as it gets this turn. Returns false when there is no route at all.
+  const sendMove = useCallback((unitId: string, q: number, r: number) => {
+    if (!currentGame || !rules) return false;
+    const path = rules.findPath(currentGame, unitId, q, r);
+    if (!path || path.length === 0) return false;
+    moveUnitPath(gameId, unitId, path);
+    return true;
+  }, [currentGame, rules, gameId, moveUnitPath]);
 
   const handleUnitClick = useCallback((unitId: string) => {
     if (!currentGame || !myPlayerId) return;
@@ -166,37 +144,33 @@ export default function GamePage() {
     
     // If we have a unit selected and click an enemy unit, try to attack
     if (selectedUnitId && clickedUnit.owner_id !== myPlayerId && isMyTurn) {
-      const myUnit = (currentGame.units || []).find(u => u.id === selectedUnitId);
-      if (myUnit && myUnit.movement_remaining > 0) {
-        // Check if within attack range
-        const distance = hexDistance(myUnit.q, myUnit.r, clickedUnit.q, clickedUnit.r);
-        const unitRange = UNIT_STATS[myUnit.unit_type as UnitType]?.range ?? 1;
-        if (distance <= unitRange) {
-          attackUnit(gameId, myPlayerId, selectedUnitId, unitId);
-          setSelectedUnitId(null);
-          setHighlightedTiles([]);
-          return;
-        }
+      // Without the rules loaded yet, let the server decide
+      const targets = rules?.attackableUnits(currentGame, selectedUnitId);
+      if (!targets || targets.includes(unitId)) {
+        attackUnit(gameId, selectedUnitId, unitId);
+        setSelectedUnitId(null);
+        highlightUnit(null);
+        return;
       }
     }
     
     // Can only select own units on your turn
     if (!isMyTurn || clickedUnit.owner_id !== myPlayerId) {
       setSelectedUnitId(null);
-      setHighlightedTiles([]);
+      highlightUnit(null);
       return;
     }
     
     if (selectedUnitId === unitId) {
       // Deselect
       setSelectedUnitId(null);
-      setHighlightedTiles([]);
+      highlightUnit(null);
     } else {
       // Select and show movement options
       setSelectedUnitId(unitId);
-      setHighlightedTiles(calculateMovementTiles(clickedUnit));
+      highlightUnit(clickedUnit);
     }
-  }, [currentGame, myPlayerId, selectedUnitId, calculateMovementTiles, gameId, attackUnit]);
+  }, [currentGame, myPlayerId, selectedUnitId, rules, highlightUnit, gameId, attackUnit]);
 
   const handleCityClick = useCallback((cityId: string) => {
     if (!currentGame || !myPlayerId) return;
@@ -209,18 +183,16 @@ export default function GamePage() {
     
     // If we have a unit selected and click an enemy city, try to move there to capture
     if (selectedUnitId && isMyTurn && clickedCity.owner_id !== myPlayerId) {
-      const isValidMove = highlightedTiles.some(t => t.q === clickedCity.q && t.r === clickedCity.r);
-      if (isValidMove) {
-        moveUnit(gameId, myPlayerId, selectedUnitId, clickedCity.q, clickedCity.r);
+      if (sendMove(selectedUnitId, clickedCity.q, clickedCity.r)) {
         setSelectedUnitId(null);
-        setHighlightedTiles([]);
+        highlightUnit(null);
         return;
       }
     }
     
     // Clear unit selection when clicking cities
     setSelectedUnitId(null);
-    setHighlightedTiles([]);
+    highlightUnit(null);
     
     // Can only select own cities on your turn
     if (!isMyTurn || clickedCity.owner_id !== myPlayerId) {
@@ -235,66 +207,59 @@ export default function GamePage() {
       // Select city
       setSelectedCityId(cityId);
     }
-  }, [currentGame, myPlayerId, selectedCityId, selectedUnitId, highlightedTiles, gameId, moveUnit]);
+  }, [currentGame, myPlayerId, selectedCityId, selectedUnitId, sendMove, highlightUnit]);
 
   const handleTileClick = useCallback((q: number, r: number) => {
     if (!selectedUnitId || !myPlayerId || !currentGame) return;
     
-    // Check if this tile is in highlighted (valid move) tiles
-    const isValidMove = highlightedTiles.some(t => t.q === q && t.r === r);
-    if (!isValidMove) {
-      // Deselect if clicking invalid tile
-      setSelectedUnitId(null);
-      setHighlightedTiles([]);
-      return;
-    }
-    
-    // Move the unit
-    moveUnit(gameId, myPlayerId, selectedUnitId, q, r);
+    // Highlighted tiles can be reached this turn, but any tile with a route can be picked and
+    // the unit heads there. Clicking somewhere unreachable just deselects.
+    sendMove(selectedUnitId, q, r);
     setSelectedUnitId(null);
-    setHighlightedTiles([]);
-  }, [selectedUnitId, myPlayerId, currentGame, highlightedTiles, gameId, moveUnit]);
+    highlightUnit(null);
+  }, [selectedUnitId, myPlayerId, currentGame, sendMove, highlightUnit]);
 
   // Update highlighted tiles when game state changes
   useEffect(() => {
     if (selectedUnitId && currentGame) {
       const unit = (currentGame.units || []).find(u => u.id === selectedUnitId);
       if (unit) {
-        setHighlightedTiles(calculateMovementTiles(unit));
+        highlightUnit(unit);
       } else {
         setSelectedUnitId(null);
-        setHighlightedTiles([]);
+        highlightUnit(null);
       }
     }
-  }, [currentGame?.units, selectedUnitId, calculateMovementTiles]);
+  }, [currentGame?.units, selectedUnitId, highlightUnit]);
 
   const handleLeaveGame = () => {
     sessionStorage.removeItem(`game-${gameId}`);
+    sessionStorage.removeItem(`token-${gameId}`);
     router.push("/multiplayer");
   };
 
   const handleEndTurn = () => {
     if (myPlayerId) {
       console.log("Ending turn:", { gameId, myPlayerId });
-      endTurn(gameId, myPlayerId);
+      endTurn(gameId);
       setSelectedUnitId(null);
-      setHighlightedTiles([]);
+      highlightUnit(null);
     }
   };
 
   const handleFortify = () => {
     if (myPlayerId && selectedUnitId) {
       console.log("Fortifying unit:", { gameId, myPlayerId, selectedUnitId });
-      fortifyUnit(gameId, myPlayerId, selectedUnitId);
+      fortifyUnit(gameId, selectedUnitId);
       setSelectedUnitId(null);
-      setHighlightedTiles([]);
+      highlightUnit(null);
     }
   };
 
   const handleBuyUnit = (unitType: string) => {
     if (myPlayerId && selectedCityId) {
       console.log("Buying unit:", { gameId, myPlayerId, selectedCityId, unitType });
-      buyUnit(gameId, myPlayerId, selectedCityId, unitType);
+      buyUnit(gameId, selectedCityId, unitType);
       setSelectedCityId(null);
     }
   };
@@ -332,10 +297,13 @@ export default function GamePage() {
   const isVictory = typeof currentGame.status === "object" && "Victory" in currentGame.status;
   const winnerId = isVictory ? (currentGame.status as { Victory: { winner_id: string } }).Victory.winner_id : null;
   const winnerName = winnerId ? currentGame.players.find(p => p.id === winnerId)?.name || null : null;
+  const isOver = currentGame.status !== "InProgress";
+  const drawOfferedBy = currentGame.draw_offered_by || [];
+  const isHost = currentGame.host_id === myPlayerId;
   
   // Get my gold amount
   const myPlayerIdx = currentGame.players.findIndex(p => p.id === myPlayerId);
-  const myGold = myPlayerIdx >= 0 ? (currentGame.player_gold?.[myPlayerIdx] ?? 0) : 0;
+  const myGold = currentGame.gold ?? 0;
 
   // Calculate visibility for current player
   const visibleTiles = new Set<string>();
@@ -343,30 +311,13 @@ export default function GamePage() {
   
   if (myPlayerId && myPlayerIdx >= 0) {
     // Add explored tiles from server state
-    const playerExplored = currentGame.explored_tiles?.[myPlayerIdx];
-    if (playerExplored) {
-      for (const [q, r] of playerExplored) {
-        exploredTiles.add(`${q},${r}`);
-      }
-    }
-    
-    // Calculate currently visible tiles from cities
-    for (const city of currentGame.cities || []) {
-      if (city.owner_id === myPlayerId) {
-        for (const [q, r] of getTilesInRange(city.q, city.r, CITY_VISION_RANGE)) {
-          visibleTiles.add(`${q},${r}`);
-        }
-      }
+    for (const [q, r] of currentGame.explored_tiles || []) {
+      exploredTiles.add(`${q},${r}`);
     }
     
-    // Calculate currently visible tiles from units
-    for (const unit of currentGame.units || []) {
-      if (unit.owner_id === myPlayerId) {
-        const range = VISION_RANGES[unit.unit_type] ?? 2;
-        for (const [q, r] of getTilesInRange(unit.q, unit.r, range)) {
-          visibleTiles.add(`${q},${r}`);
-        }
-      }
+    // Currently visible tiles come from the server, which applies line of sight
+    for (const [q, r] of currentGame.visible_tiles || []) {
+      visibleTiles.add(`${q},${r}`);
     }
   }
 
@@ -400,6 +351,47 @@ export default function GamePage() {
             End Turn
           </button>
 
+          {!isOver && !isEliminated && (
+            drawOfferedBy.length > 0 && !drawOfferedBy.includes(myPlayerId || "") ? (
+              <button
+                onClick={() => acceptDraw(gameId)}
+                className="px-4 py-2 bg-sky-600 hover:bg-sky-500 rounded text-white transition-colors"
+              >
+                Accept Draw
+              </button>
+            ) : (
+              <button
+                onClick={() => offerDraw(gameId)}
+                disabled={drawOfferedBy.length > 0}
+                className={`px-4 py-2 rounded transition-colors ${
+                  drawOfferedBy.length > 0
+                    ? "bg-zinc-700 text-zinc-500 cursor-not-allowed"
+                    : "bg-zinc-600 hover:bg-zinc-500 text-white"
+                }`}
+              >
+                {drawOfferedBy.length > 0 ? "Draw Offered" : "Offer Draw"}
+              </button>
+            )
+          )}
+
+          {!isOver && !isEliminated && (
+            <button
+              onClick={() => window.confirm("Resign from this game?") && resign(gameId)}
+              className="px-4 py-2 bg-zinc-600 hover:bg-zinc-500 rounded text-white transition-colors"
+            >
+              Resign
+            </button>
+          )}
+
+          {!isOver && isHost && (
+            <button
+              onClick={() => window.confirm("Abort the game for everyone?") && abortGame(gameId)}
+              className="px-4 py-2 bg-zinc-600 hover:bg-zinc-500 rounded text-white transition-colors"
+            >
+              Abort
+            </button>
+          )}
+
           <button
             onClick={handleLeaveGame}
             className="px-4 py-2 bg-red-600 hover:bg-red-500 rounded text-white transition-colors"
@@ -414,6 +406,7 @@ export default function GamePage() {
           const isCurrentTurn = index === currentGame.current_turn;
           const isMe = player.id === myPlayerId;
           const isPlayerEliminated = (currentGame.eliminated_players || []).includes(player.id);
+          const playerPresence = presence[player.id]?.presence ?? "Connected";
           
           return (
             <div
@@ -432,6 +425,8 @@ export default function GamePage() {
                 {player.name}
                 {isMe && " (You)"}
                 {isPlayerEliminated && " [Eliminated]"}
+                {!isPlayerEliminated && playerPresence === "Away" && " [Away]"}
+                {!isPlayerEliminated && playerPresence === "Disconnected" && " [Disconnected]"}
               </span>
               {isCurrentTurn && !isPlayerEliminated && (
                 <span className="text-xs text-emerald-400 ml-1">●</span>
@@ -553,12 +548,13 @@ export default function GamePage() {
         <HexGrid 
           map={currentGame.map} 
           hexSize={40}
-          cities={currentGame.cities || []}
+          cities={[...(currentGame.cities || []), ...(currentGame.last_seen_cities || []).map((s) => s.city)]}
           units={currentGame.units || []}
           players={currentGame.players}
           selectedUnitId={selectedUnitId}
           selectedCityId={selectedCityId}
           highlightedTiles={highlightedTiles}
+          targetedTiles={targetedTiles}
           visibleTiles={visibleTiles}
           exploredTiles={exploredTiles}
           onTileClick={handleTileClick}
@@ -567,9 +563,13 @@ export default function GamePage() {
         />
       </main>
 
-      {isVictory && (
+      {isOver && (
         <GameOverDialog
-          isWinner={winnerId === myPlayerId}
+          outcome={
+            isVictory
+              ? winnerId === myPlayerId ? "victory" : "defeat"
+              : currentGame.status === "Draw" ? "draw" : "aborted"
+          }
           winnerName={winnerName}
           onClose={() => router.push("/multiplayer")}
         />
@@ -1,18 +1,33 @@
 interface GameOverDialogProps {
-  isWinner: boolean;
+  outcome: "victory" | "defeat" | "draw" | "aborted";
   winnerName: string | null;
   onClose: () => void;
 }
 
-export function GameOverDialog({ isWinner, winnerName, onClose }: GameOverDialogProps) {
+const TITLES = {
+  victory: "Victory!",
+  defeat: "Defeat",
+  draw: "Draw",
+  aborted: "Game Aborted",
+};
+
+export function GameOverDialog({ outcome, winnerName, onClose }: GameOverDialogProps) {
+  const messages = {
+    victory: "You have conquered all opponents!",
+    defeat: `${winnerName} has won the game!`,
+    draw: "All players agreed to a draw.",
+    aborted: "The host ended the game without a result.",
+  };
+  const titleColor = outcome === "victory" ? "text-emerald-400" : outcome === "defeat" ? "text-red-400" : "text-zinc-200";
+
   return (
     <div className="fixed inset-0 bg-black/70 flex items-center justify-center z-50">
       <div className="bg-zinc-800 rounded-lg p-8 text-center shadow-2xl border border-zinc-700 min-w-80">
-        <h1 className={`text-4xl font-bold mb-4 ${isWinner ? "text-emerald-400" : "text-red-400"}`}>
-          {isWinner ? "Victory!" : "Defeat"}
+        <h1 className={`text-4xl font-bold mb-4 ${titleColor}`}>
+          {TITLES[outcome]}
         </h1>
         <p className="text-xl text-zinc-300 mb-8">
-          {isWinner ? "You have conquered all opponents!" : `${winnerName} has won the game!`}
+          {messages[outcome]}
         </p>
         <button
           onClick={onClose}
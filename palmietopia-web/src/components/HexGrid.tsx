@@ -25,6 +25,7 @@ interface HexGridProps {
   selectedUnitId?: string | null;
   selectedCityId?: string | null;
   highlightedTiles?: { q: number; r: number }[];
+  targetedTiles?: { q: number; r: number }[];  // Enemies the selected unit can attack
   visibleTiles?: Set<string>;  // "q,r" strings for visible tiles
   exploredTiles?: Set<string>; // "q,r" strings for explored (but not visible) tiles
   onTileClick?: (q: number, r: number) => void;
@@ -49,6 +50,7 @@ export function HexGrid({
   selectedUnitId,
   selectedCityId,
   highlightedTiles = [],
+  targetedTiles = [],
   visibleTiles,
   exploredTiles,
   onTileClick,
@@ -78,6 +80,10 @@ export function HexGrid({
     return highlightedTiles.some(t => t.q === q && t.r === r);
   };
 
+  const isTargeted = (q: number, r: number): boolean => {
+    return targetedTiles.some(t => t.q === q && t.r === r);
+  };
+
   // Determine visibility state: "visible" | "explored" | "unexplored"
   const getVisibilityState = (q: number, r: number): "visible" | "explored" | "unexplored" => {
     const key = `${q},${r}`;
@@ -171,6 +177,7 @@ export function HexGrid({
               size={hexSize}
               onClick={onTileClick ? () => onTileClick(tile.q, tile.r) : undefined}
               isHighlighted={isHighlighted(tile.q, tile.r)}
+              isTargeted={isTargeted(tile.q, tile.r)}
               isSelected={false}
               visibilityState={getVisibilityState(tile.q, tile.r)}
             />
@@ -5,6 +5,7 @@ interface HexTileProps {
   size: number;
   onClick?: () => void;
   isHighlighted?: boolean;
+  isTargeted?: boolean;
   isSelected?: boolean;
   visibilityState?: "visible" | "explored" | "unexplored";
 }
@@ -25,7 +26,7 @@ const TERRAIN_DARK: Record<string, string> = {
   Desert: "#E65100",
 };
 
-export function HexTile({ q, r, terrain, size, onClick, isHighlighted, isSelected, visibilityState = "visible" }: HexTileProps) {
+export function HexTile({ q, r, terrain, size, onClick, isHighlighted, isTargeted, isSelected, visibilityState = "visible" }: HexTileProps) {
   const x = size * (Math.sqrt(3) * q + (Math.sqrt(3) / 2) * r);
   const y = size * ((3 / 2) * r);
 
@@ -199,8 +200,8 @@ export function HexTile({ q, r, terrain, size, onClick, isHighlighted, isSelecte
       <polygon
         points={points.join(" ")}
         fill={`url(#${gradientId})`}
-        stroke={isSelected ? "#FFD700" : isHighlighted ? "#00FF00" : "#1a1a1a"}
-        strokeWidth={isSelected ? "3" : isHighlighted ? "2.5" : "1.5"}
+        stroke={isSelected ? "#FFD700" : isTargeted ? "#FF1744" : isHighlighted ? "#00FF00" : "#1a1a1a"}
+        strokeWidth={isSelected ? "3" : isTargeted || isHighlighted ? "2.5" : "1.5"}
         className="transition-all hover:brightness-110"
       />
       
@@ -0,0 +1,51 @@
+"use client";
+import { useEffect, useState } from "react";
+import init, {
+  find_path_for_view,
+  get_attackable_units_for_view,
+  get_reachable_tiles_for_view,
+} from "../../pkg/palmietopia_core";
+import type { PlayerView } from "./useWebSocket";
+
+// Game rules from the core crate, compiled to WASM, so the client plans moves exactly the way
+// the server checks them. They run on the player's own view of the game.
+export interface Rules {
+  reachableTiles(view: PlayerView, unitId: string): Array<{ q: number; r: number; cost: number }>;
+  attackableUnits(view: PlayerView, unitId: string): string[];
+  findPath(view: PlayerView, unitId: string, toQ: number, toR: number): Array<[number, number]> | null;
+}
+
+const rules: Rules = {
+  reachableTiles(view, unitId) {
+    return JSON.parse(get_reachable_tiles_for_view(JSON.stringify(view), unitId));
+  },
+  attackableUnits(view, unitId) {
+    return JSON.parse(get_attackable_units_for_view(JSON.stringify(view), unitId));
+  },
+  findPath(view, unitId, toQ, toR) {
+    const path: { steps: Array<[number, number]>; cost: number } | null = JSON.parse(
+      find_path_for_view(JSON.stringify(view), unitId, toQ, toR),
+    );
+    return path?.steps ?? null;
+  },
+};
+
+let loading: Promise<void> | null = null;
+
+// The rules once the WASM module has loaded, or null until then
+export function useRules(): Rules | null {
+  const [ready, setReady] = useState(false);
+
+  useEffect(() => {
+    loading ??= init("/wasm/palmietopia_core_bg.wasm");
+    let cancelled = false;
+    loading
+      .then(() => !cancelled && setReady(true))
+      .catch((e) => console.error("Failed to load game rules:", e));
+    return () => {
+      cancelled = true;
+    };
+  }, []);
+
+  return ready ? rules : null;
+}
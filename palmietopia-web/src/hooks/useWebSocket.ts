@@ -9,6 +9,19 @@ export interface Player {
   color: string;
 }
 
+export interface MapGenConfig {
+  water_ratio: number;
+  mountain_ratio: number;
+  forest_ratio: number;
+  desert_ratio: number;
+  rivers_per_4_radius: number;
+}
+
+export interface StartConfig {
+  tolerance: number;
+  area_radius: number;
+}
+
 export interface Lobby {
   id: string;
   host_id: string;
@@ -16,6 +29,9 @@ export interface Lobby {
   map_size: MapSize;
   max_players: number;
   status: string;
+  seed: number;
+  map_config: MapGenConfig;
+  start_config: StartConfig;
 }
 
 export interface Tile {
@@ -50,267 +66,393 @@ export interface Unit {
   max_hp: number;
 }
 
-export interface GameSession {
+// Enemy cities and units out of sight, as they were when last seen
+export interface SeenCity {
+  city: City;
+  seen_turn: number;
+}
+
+export interface SeenUnit {
+  unit: Unit;
+  seen_turn: number;
+}
+
+// What this player is allowed to know about the game (built by the server per player)
+export interface PlayerView {
   id: string;
+  seed: number;
+  player_id: string;
   map: GameMap;
   players: Player[];
   cities: City[];
   units: Unit[];
+  last_seen_cities: SeenCity[];
+  last_seen_units: SeenUnit[];
   current_turn: number;
-  status: string | { Victory: { winner_id: string } };
+  turn_number: number;
+  status: "InProgress" | "Draw" | "Finished" | { Victory: { winner_id: string } };
   eliminated_players: string[];
+  // Players who have offered or accepted a draw that is still open
+  draw_offered_by: string[];
+  host_id: string;
   player_times_ms: number[];
-  player_gold: number[];
-  explored_tiles: Array<Array<[number, number]>>;  // Per-player explored tiles
+  gold: number;
+  explored_tiles: Array<[number, number]>;
+  visible_tiles: Array<[number, number]>;
   turn_started_at_ms: number;
   base_time_ms: number;
   increment_ms: number;
 }
 
+// What changed in our view since the last delta (see ViewDelta in palmietopia-core)
+export interface ViewDelta {
+  units_changed?: Unit[];
+  units_removed?: string[];
+  cities_changed?: City[];
+  cities_removed?: string[];
+  last_seen_cities_changed?: SeenCity[];
+  last_seen_cities_removed?: string[];
+  last_seen_units_changed?: SeenUnit[];
+  last_seen_units_removed?: string[];
+  explored_added?: Array<[number, number]>;
+  visible_added?: Array<[number, number]>;
+  visible_removed?: Array<[number, number]>;
+  gold?: number;
+  current_turn?: number;
+  turn_number?: number;
+  status?: PlayerView["status"];
+  eliminated_players?: string[];
+  draw_offered_by?: string[];
+  player_times_ms?: number[];
+  turn_started_at_ms?: number;
+}
+
+// Replace entries by id, append new ones and drop removed ones
+function patchById<T>(list: T[], changed: T[] = [], removed: string[] = [], id: (item: T) => string): T[] {
+  const changedIds = new Set(changed.map(id));
+  const kept = list.filter((item) => !removed.includes(id(item)) && !changedIds.has(id(item)));
+  return [...kept, ...changed];
+}
+
+function patchTiles(
+  tiles: Array<[number, number]>,
+  added: Array<[number, number]> = [],
+  removed: Array<[number, number]> = []
+): Array<[number, number]> {
+  const key = ([q, r]: [number, number]) => `${q},${r}`;
+  const gone = new Set(removed.map(key));
+  const present = new Set(tiles.map(key));
+  return [
+    ...tiles.filter((t) => !gone.has(key(t))),
+    ...added.filter((t) => !present.has(key(t))),
+  ];
+}
+
+export function applyDelta(view: PlayerView, delta: ViewDelta): PlayerView {
+  return {
+    ...view,
+    units: patchById(view.units, delta.units_changed, delta.units_removed, (u) => u.id),
+    cities: patchById(view.cities, delta.cities_changed, delta.cities_removed, (c) => c.id),
+    last_seen_cities: patchById(
+      view.last_seen_cities || [],
+      delta.last_seen_cities_changed,
+      delta.last_seen_cities_removed,
+      (s) => s.city.id
+    ),
+    last_seen_units: patchById(
+      view.last_seen_units || [],
+      delta.last_seen_units_changed,
+      delta.last_seen_units_removed,
+      (s) => s.unit.id
+    ),
+    explored_tiles: patchTiles(view.explored_tiles || [], delta.explored_added),
+    visible_tiles: patchTiles(view.visible_tiles || [], delta.visible_added, delta.visible_removed),
+    gold: delta.gold ?? view.gold,
+    current_turn: delta.current_turn ?? view.current_turn,
+    turn_number: delta.turn_number ?? view.turn_number,
+    status: delta.status ?? view.status,
+    eliminated_players: delta.eliminated_players ?? view.eliminated_players,
+    draw_offered_by: delta.draw_offered_by ?? view.draw_offered_by,
+    player_times_ms: delta.player_times_ms ?? view.player_times_ms,
+    turn_started_at_ms: delta.turn_started_at_ms ?? view.turn_started_at_ms,
+  };
+}
+
+export type Presence = "Connected" | "Away" | "Disconnected";
+
+export interface PlayerPresence {
+  presence: Presence;
+  grace_ends_at_ms: number | null;
+}
+
 export type ServerMessage =
-  | { type: "LobbyCreated"; lobby_id: string; player_id: string }
-  | { type: "JoinedLobby"; lobby: Lobby; player_id: string }
+  | { type: "LobbyCreated"; lobby_id: string; player_id: string; token: string }
+  | { type: "JoinedLobby"; lobby: Lobby; player_id: string; token: string }
   | { type: "LobbyUpdated"; lobby: Lobby }
   | { type: "LobbyList"; lobbies: Lobby[] }
-  | { type: "GameStarted"; game: GameSession }
-  | { type: "GameRejoined"; game: GameSession }
+  | { type: "GameStarted"; game: PlayerView; last_seq: number; token: string }
+  | { type: "GameRejoined"; game: PlayerView; last_seq: number }
+  | { type: "Snapshot"; game: PlayerView; last_seq: number }
   | { type: "PlayerLeft"; player_id: string }
-  | { type: "Error"; message: string }
-  | { type: "TurnChanged"; current_turn: number; player_times_ms: number[]; player_gold: number[]; units: Unit[]; cities: City[]; explored_tiles: Array<Array<[number, number]>> }
+  | { type: "Error"; code: string; message: string }
+  | { type: "TurnChanged"; current_turn: number; player_times_ms: number[] }
   | { type: "TimeTick"; player_index: number; remaining_ms: number }
-  | { type: "UnitMoved"; unit_id: string; to_q: number; to_r: number; movement_remaining: number; explored_tiles: Array<Array<[number, number]>> }
-  | { type: "CombatResult"; attacker_id: string; defender_id: string; attacker_hp: number; defender_hp: number; damage_to_attacker: number; damage_to_defender: number; attacker_died: boolean; defender_died: boolean; attacker_new_q: number | null; attacker_new_r: number | null }
+  | { type: "UnitMoved"; unit_id: string; to_q: number; to_r: number; movement_remaining: number }
+  | { type: "UnitMovedAlongPath"; unit_id: string; path: Array<[number, number]>; movement_remaining: number }
+  // The attacker's fields are null when the attacker was out of sight
+  | { type: "CombatResult"; attacker_id: string | null; defender_id: string; attacker_hp: number | null; defender_hp: number; damage_to_attacker: number | null; damage_to_defender: number; attacker_died: boolean | null; defender_died: boolean; attacker_new_q: number | null; attacker_new_r: number | null }
   | { type: "PlayerEliminated"; player_id: string; conquerer_id: string }
-  | { type: "CitiesCaptured"; cities: City[] }
+  | { type: "PlayerResigned"; player_id: string }
+  | { type: "DrawOffered"; player_id: string }
+  | { type: "DrawAccepted"; player_id: string }
+  | { type: "GameDrawn" }
+  // player_id is null when the game was stopped because everyone had left
+  | { type: "GameAborted"; player_id: string | null }
+  | { type: "PresenceChanged"; player_id: string; presence: Presence; grace_ends_at_ms: number | null }
+  | { type: "CitiesCaptured"; city_ids: string[] }
   | { type: "GameOver"; winner_id: string }
   | { type: "UnitFortified"; unit_id: string; new_hp: number }
-  | { type: "UnitPurchased"; unit: Unit; city_id: string; player_gold: number };
+  | { type: "UnitPurchased"; unit: Unit; city_id: string; player_gold: number }
+  | { type: "StateDelta"; delta: ViewDelta };
+
+// Messages on a game channel are numbered per player so gaps can be noticed
+export type SequencedMessage = ServerMessage & { seq?: number };
 
 export type ClientMessage =
-  | { type: "CreateLobby"; player_name: string; map_size: MapSize }
+  | {
+      type: "CreateLobby";
+      player_name: string;
+      map_size: MapSize;
+      seed?: number;
+      map_config?: MapGenConfig;
+      start_config?: StartConfig;
+    }
   | { type: "JoinLobby"; lobby_id: string; player_name: string }
+  | { type: "RejoinLobby"; token: string }
   | { type: "LeaveLobby" }
   | { type: "StartGame" }
   | { type: "ListLobbies" }
-  | { type: "EndTurn"; game_id: string; player_id: string }
-  | { type: "RejoinGame"; game_id: string; player_id: string }
-  | { type: "MoveUnit"; game_id: string; player_id: string; unit_id: string; to_q: number; to_r: number }
-  | { type: "AttackUnit"; game_id: string; player_id: string; attacker_id: string; defender_id: string }
-  | { type: "FortifyUnit"; game_id: string; player_id: string; unit_id: string }
-  | { type: "BuyUnit"; game_id: string; player_id: string; city_id: string; unit_type: string };
+  | { type: "EndTurn"; game_id: string }
+  | { type: "RejoinGame"; game_id: string; token: string }
+  | { type: "RequestSync"; game_id: string; since_seq: number }
+  | { type: "MoveUnit"; game_id: string; unit_id: string; to_q: number; to_r: number }
+  | { type: "MoveUnitPath"; game_id: string; unit_id: string; path: Array<[number, number]> }
+  | { type: "AttackUnit"; game_id: string; attacker_id: string; defender_id: string }
+  | { type: "FortifyUnit"; game_id: string; unit_id: string }
+  | { type: "BuyUnit"; game_id: string; city_id: string; unit_type: string }
+  | { type: "SetAway"; game_id: string; away: boolean }
+  | { type: "Resign"; game_id: string }
+  | { type: "OfferDraw"; game_id: string }
+  | { type: "AcceptDraw"; game_id: string }
+  | { type: "AbortGame"; game_id: string };
 
 const WS_URL = process.env.NEXT_PUBLIC_WS_URL || "ws://localhost:3001/ws";
 
+// Reconnect token for the lobby this tab is in. Game tokens are stored per game as `token-<id>`.
+const LOBBY_TOKEN_KEY = "lobby-token";
+// How long to wait before reconnecting after the connection drops, e.g. while the server restarts
+const RECONNECT_DELAY_MS = 2000;
+
 export function useWebSocket() {
   const [isConnected, setIsConnected] = useState(false);
   const [playerId, setPlayerId] = useState<string | null>(null);
   const [currentLobby, setCurrentLobby] = useState<Lobby | null>(null);
   const [lobbies, setLobbies] = useState<Lobby[]>([]);
-  const [game, setGame] = useState<GameSession | null>(null);
+  const [game, setGame] = useState<PlayerView | null>(null);
   const [turnTimeRemaining, setTurnTimeRemaining] = useState<number>(0);
+  // Players who are away or disconnected; everyone else is connected
+  const [presence, setPresence] = useState<Record<string, PlayerPresence>>({});
   const [error, setError] = useState<string | null>(null);
 
   const wsRef = useRef<WebSocket | null>(null);
   const playerIdRef = useRef<string | null>(null); // Ref to avoid stale closure
+  // Sequence number of the last game message handled, and which game it is for
+  const seqRef = useRef<number>(0);
+  const syncGameIdRef = useRef<string | null>(null);
+  // Bumped to open a fresh connection after the last one dropped
+  const [connectAttempt, setConnectAttempt] = useState(0);
 
-  // Auto-connect on mount, cleanup on unmount
+  // Auto-connect on mount, reconnect when dropped, cleanup on unmount
   useEffect(() => {
     const ws = new WebSocket(WS_URL);
     wsRef.current = ws;
+    let closing = false;
+    let reconnectTimer: ReturnType<typeof setTimeout> | undefined;
 
     ws.onopen = () => {
       setIsConnected(true);
       setError(null);
       ws.send(JSON.stringify({ type: "ListLobbies" }));
+      // Back in the lobby we were in before the page reloaded or the connection dropped
+      const lobbyToken = sessionStorage.getItem(LOBBY_TOKEN_KEY);
+      if (lobbyToken) {
+        ws.send(JSON.stringify({ type: "RejoinLobby", token: lobbyToken }));
+      }
     };
 
+    // The game page rejoins its game once we are connected again
     ws.onclose = () => {
       setIsConnected(false);
+      if (!closing) {
+        reconnectTimer = setTimeout(() => setConnectAttempt((n) => n + 1), RECONNECT_DELAY_MS);
+      }
     };
 
     ws.onerror = () => {
       setError("Connection error");
     };
 
+    const handleMessage = (msg: ServerMessage) => {
+      switch (msg.type) {
+        case "LobbyCreated":
+          setPlayerId(msg.player_id);
+          playerIdRef.current = msg.player_id; // Update ref synchronously
+          sessionStorage.setItem(LOBBY_TOKEN_KEY, msg.token);
+          break;
+        case "JoinedLobby":
+          setPlayerId(msg.player_id);
+          playerIdRef.current = msg.player_id; // Update ref synchronously
+          sessionStorage.setItem(LOBBY_TOKEN_KEY, msg.token);
+          setCurrentLobby(msg.lobby);
+          break;
+        case "LobbyUpdated":
+          setCurrentLobby(msg.lobby);
+          break;
+        case "LobbyList":
+          setLobbies(msg.lobbies);
+          break;
+        case "GameStarted":
+          console.log("GameStarted received:", msg.game);
+          seqRef.current = msg.last_seq;
+          syncGameIdRef.current = msg.game.id;
+          setGame(msg.game);
+          // Set initial time for current player
+          setTurnTimeRemaining(msg.game.player_times_ms[msg.game.current_turn]);
+          if (typeof window !== "undefined") {
+            sessionStorage.setItem(`game-${msg.game.id}`, JSON.stringify(msg.game));
+            // The game page rejoins with this token; the lobby one is spent
+            sessionStorage.setItem(`token-${msg.game.id}`, msg.token);
+            sessionStorage.removeItem(LOBBY_TOKEN_KEY);
+            // Store player ID so game page knows which player we are
+            const currentPlayerId = playerIdRef.current;
+            if (currentPlayerId) {
+              sessionStorage.setItem(`player-${msg.game.id}`, currentPlayerId);
+            }
+          }
+          break;
+        case "GameRejoined":
+        case "Snapshot":
+          console.log(`${msg.type} received:`, msg.game);
+          seqRef.current = msg.last_seq;
+          syncGameIdRef.current = msg.game.id;
+          setGame(msg.game);
+          setTurnTimeRemaining(msg.game.player_times_ms[msg.game.current_turn]);
+          break;
+        case "StateDelta":
+          setGame((prev) => (prev ? applyDelta(prev, msg.delta) : null));
+          break;
+        case "TurnChanged":
+          console.log("TurnChanged received:", msg);
+          setTurnTimeRemaining(msg.player_times_ms[msg.current_turn]);
+          break;
+        case "TimeTick":
+          // Only update if this is for the current player
+          setGame((prev) => {
+            if (prev && prev.current_turn === msg.player_index) {
+              setTurnTimeRemaining(msg.remaining_ms);
+            }
+            return prev;
+          });
+          break;
+        // Events say what happened; the state they leave behind arrives in the StateDelta
+        // that follows them
+        case "UnitMoved":
+        case "UnitMovedAlongPath":
+        case "PresenceChanged":
+          setPresence((prev) => ({
+            ...prev,
+            [msg.player_id]: { presence: msg.presence, grace_ends_at_ms: msg.grace_ends_at_ms },
+          }));
+          break;
+        case "PlayerEliminated":
+        case "PlayerResigned":
+        case "DrawOffered":
+        case "DrawAccepted":
+        case "GameDrawn":
+        case "GameAborted":
+        case "CombatResult":
+        case "CitiesCaptured":
+        case "UnitFortified":
+        case "UnitPurchased":
+          console.log(`${msg.type} received:`, msg);
+          break;
+        case "GameOver":
+          console.log("GameOver! Winner:", msg.winner_id);
+          break;
+        case "PlayerLeft":
+          break;
+        case "Error":
+          if (msg.code === "invalid_token" || msg.code === "lobby_not_found") {
+            sessionStorage.removeItem(LOBBY_TOKEN_KEY);
+          }
+          setError(msg.message);
+          break;
+      }
+    };
+
+    // Game messages are handled strictly in order. Ones that arrive early wait in `held` while
+    // we ask the server for the ones in between (or for a snapshot, if it no longer has them).
+    const held = new Map<number, ServerMessage>();
+    let syncRequested = false;
+    const drainHeld = () => {
+      while (held.has(seqRef.current + 1)) {
+        const next = held.get(seqRef.current + 1)!;
+        held.delete(seqRef.current + 1);
+        seqRef.current += 1;
+        handleMessage(next);
+      }
+      for (const seq of held.keys()) {
+        if (seq <= seqRef.current) held.delete(seq);
+      }
+      if (held.size === 0) {
+        syncRequested = false;
+      } else if (!syncRequested && syncGameIdRef.current) {
+        console.warn(`Missed game messages after ${seqRef.current}, requesting sync`);
+        syncRequested = true;
+        ws.send(JSON.stringify({
+          type: "RequestSync",
+          game_id: syncGameIdRef.current,
+          since_seq: seqRef.current,
+        }));
+      }
+    };
+
     ws.onmessage = (event) => {
       try {
-        const msg: ServerMessage = JSON.parse(event.data);
-        
-        switch (msg.type) {
-          case "LobbyCreated":
-            setPlayerId(msg.player_id);
-            playerIdRef.current = msg.player_id; // Update ref synchronously
-            break;
-          case "JoinedLobby":
-            setPlayerId(msg.player_id);
-            playerIdRef.current = msg.player_id; // Update ref synchronously
-            setCurrentLobby(msg.lobby);
-            break;
-          case "LobbyUpdated":
-            setCurrentLobby(msg.lobby);
-            break;
-          case "LobbyList":
-            setLobbies(msg.lobbies);
-            break;
-          case "GameStarted":
-            console.log("GameStarted received:", msg.game);
-            setGame(msg.game);
-            // Set initial time for current player
-            setTurnTimeRemaining(msg.game.player_times_ms[msg.game.current_turn]);
-            if (typeof window !== "undefined") {
-              sessionStorage.setItem(`game-${msg.game.id}`, JSON.stringify(msg.game));
-              // Store player ID so game page knows which player we are
-              const currentPlayerId = playerIdRef.current;
-              if (currentPlayerId) {
-                sessionStorage.setItem(`player-${msg.game.id}`, currentPlayerId);
-              }
-            }
-            break;
-          case "GameRejoined":
-            console.log("GameRejoined received:", msg.game);
-            setGame(msg.game);
-            setTurnTimeRemaining(msg.game.player_times_ms[msg.game.current_turn]);
-            break;
-          case "TurnChanged":
-            console.log("TurnChanged received:", msg);
-            setGame((prev) =>
-              prev ? { 
-                ...prev, 
-                current_turn: msg.current_turn,
-                player_times_ms: msg.player_times_ms,
-                player_gold: msg.player_gold,
-                units: msg.units,
-                cities: msg.cities,
-                explored_tiles: msg.explored_tiles,
-                turn_started_at_ms: Date.now(),
-              } : null
-            );
-            setTurnTimeRemaining(msg.player_times_ms[msg.current_turn]);
-            break;
-          case "TimeTick":
-            // Only update if this is for the current player
-            setGame((prev) => {
-              if (prev && prev.current_turn === msg.player_index) {
-                setTurnTimeRemaining(msg.remaining_ms);
-              }
-              return prev;
-            });
-            break;
-          case "UnitMoved":
-            console.log("UnitMoved received:", msg);
-            setGame((prev) => {
-              if (!prev) return null;
-              return {
-                ...prev,
-                units: prev.units.map((u) =>
-                  u.id === msg.unit_id
-                    ? { ...u, q: msg.to_q, r: msg.to_r, movement_remaining: msg.movement_remaining }
-                    : u
-                ),
-                explored_tiles: msg.explored_tiles,
-              };
-            });
-            break;
-          case "CombatResult":
-            console.log("CombatResult received:", msg);
-            setGame((prev) => {
-              if (!prev) return null;
-              let newUnits = prev.units.map((u) => {
-                if (u.id === msg.attacker_id) {
-                  return { 
-                    ...u, 
-                    hp: msg.attacker_hp, 
-                    movement_remaining: 0,
-                    // Move to defender's position if we killed them
-                    ...(msg.attacker_new_q !== null && { q: msg.attacker_new_q }),
-                    ...(msg.attacker_new_r !== null && { r: msg.attacker_new_r }),
-                  };
-                }
-                if (u.id === msg.defender_id) {
-                  return { ...u, hp: msg.defender_hp };
-                }
-                return u;
-              });
-              // Remove dead units
-              if (msg.attacker_died) {
-                newUnits = newUnits.filter(u => u.id !== msg.attacker_id);
-              }
-              if (msg.defender_died) {
-                newUnits = newUnits.filter(u => u.id !== msg.defender_id);
-              }
-              return { ...prev, units: newUnits };
-            });
-            break;
-          case "PlayerEliminated":
-            console.log("PlayerEliminated:", msg);
-            setGame((prev) => {
-              if (!prev) return null;
-              return {
-                ...prev,
-                eliminated_players: [...(prev.eliminated_players || []), msg.player_id],
-                units: prev.units.filter(u => u.owner_id !== msg.player_id),
-              };
-            });
-            break;
-          case "CitiesCaptured":
-            console.log("CitiesCaptured:", msg);
-            setGame((prev) => prev ? { ...prev, cities: msg.cities } : null);
-            break;
-          case "GameOver":
-            console.log("GameOver! Winner:", msg.winner_id);
-            setGame((prev) => prev ? { ...prev, status: { Victory: { winner_id: msg.winner_id } } } : null);
-            break;
-          case "UnitFortified":
-            console.log("UnitFortified:", msg);
-            setGame((prev) => {
-              if (!prev) return null;
-              return {
-                ...prev,
-                units: prev.units.map((u) =>
-                  u.id === msg.unit_id
-                    ? { ...u, hp: msg.new_hp, movement_remaining: 0 }
-                    : u
-                ),
-              };
-            });
-            break;
-          case "UnitPurchased":
-            console.log("UnitPurchased:", msg);
-            setGame((prev) => {
-              if (!prev) return null;
-              // Find player index to update gold
-              const playerIdx = prev.players.findIndex(p => p.id === msg.unit.owner_id);
-              const newGold = [...prev.player_gold];
-              if (playerIdx >= 0) {
-                newGold[playerIdx] = msg.player_gold;
-              }
-              // Mark city as produced
-              const newCities = prev.cities.map(c => 
-                c.id === msg.city_id ? { ...c, produced_this_turn: true } : c
-              );
-              return {
-                ...prev,
-                units: [...prev.units, msg.unit],
-                cities: newCities,
-                player_gold: newGold,
-              };
-            });
-            break;
-          case "PlayerLeft":
-            break;
-          case "Error":
-            setError(msg.message);
-            break;
+        const msg: SequencedMessage = JSON.parse(event.data);
+        if (msg.seq === undefined) {
+          handleMessage(msg);
+          // A full view may have caught us up past messages we were holding
+          if (msg.type === "GameRejoined" || msg.type === "Snapshot") {
+            syncRequested = false;
+            drainHeld();
+          }
+          return;
         }
+        if (msg.seq <= seqRef.current) return; // Already reflected in our state
+        held.set(msg.seq, msg);
+        drainHeld();
       } catch (e) {
         console.error("Failed to parse message:", e);
       }
     };
 
     return () => {
+      closing = true;
+      clearTimeout(reconnectTimer);
       ws.close();
     };
-  }, []);
+  }, [connectAttempt]);
 
   const send = useCallback((msg: ClientMessage) => {
     if (wsRef.current?.readyState === WebSocket.OPEN) {
@@ -334,6 +476,7 @@ export function useWebSocket() {
 
   const leaveLobby = useCallback(() => {
     send({ type: "LeaveLobby" });
+    sessionStorage.removeItem(LOBBY_TOKEN_KEY);
     setCurrentLobby(null);
   }, [send]);
 
@@ -345,34 +488,62 @@ export function useWebSocket() {
     send({ type: "ListLobbies" });
   }, [send]);
 
-  const endTurn = useCallback((gameId: string, playerId: string) => {
-    console.log("Sending EndTurn:", { gameId, playerId });
-    send({ type: "EndTurn", game_id: gameId, player_id: playerId });
+  // Game actions act as the player this socket rejoined as; the server doesn't take our word
+  // for who we are
+  const endTurn = useCallback((gameId: string) => {
+    console.log("Sending EndTurn:", { gameId });
+    send({ type: "EndTurn", game_id: gameId });
+  }, [send]);
+
+  // The token from GameStarted is what proves which player we are
+  const rejoinGame = useCallback((gameId: string, token: string) => {
+    console.log("Sending RejoinGame:", { gameId });
+    send({ type: "RejoinGame", game_id: gameId, token });
+  }, [send]);
+
+  const moveUnit = useCallback((gameId: string, unitId: string, toQ: number, toR: number) => {
+    console.log("Sending MoveUnit:", { gameId, unitId, toQ, toR });
+    send({ type: "MoveUnit", game_id: gameId, unit_id: unitId, to_q: toQ, to_r: toR });
+  }, [send]);
+
+  const moveUnitPath = useCallback((gameId: string, unitId: string, path: Array<[number, number]>) => {
+    console.log("Sending MoveUnitPath:", { gameId, unitId, path });
+    send({ type: "MoveUnitPath", game_id: gameId, unit_id: unitId, path });
+  }, [send]);
+
+  const attackUnit = useCallback((gameId: string, attackerId: string, defenderId: string) => {
+    console.log("Sending AttackUnit:", { gameId, attackerId, defenderId });
+    send({ type: "AttackUnit", game_id: gameId, attacker_id: attackerId, defender_id: defenderId });
+  }, [send]);
+
+  const fortifyUnit = useCallback((gameId: string, unitId: string) => {
+    console.log("Sending FortifyUnit:", { gameId, unitId });
+    send({ type: "FortifyUnit", game_id: gameId, unit_id: unitId });
+  }, [send]);
+
+  const resign = useCallback((gameId: string) => {
+    send({ type: "Resign", game_id: gameId });
   }, [send]);
 
-  const rejoinGame = useCallback((gameId: string, playerId: string) => {
-    console.log("Sending RejoinGame:", { gameId, playerId });
-    send({ type: "RejoinGame", game_id: gameId, player_id: playerId });
+  const offerDraw = useCallback((gameId: string) => {
+    send({ type: "OfferDraw", game_id: gameId });
   }, [send]);
 
-  const moveUnit = useCallback((gameId: string, playerId: string, unitId: string, toQ: number, toR: number) => {
-    console.log("Sending MoveUnit:", { gameId, playerId, unitId, toQ, toR });
-    send({ type: "MoveUnit", game_id: gameId, player_id: playerId, unit_id: unitId, to_q: toQ, to_r: toR });
+  const acceptDraw = useCallback((gameId: string) => {
+    send({ type: "AcceptDraw", game_id: gameId });
   }, [send]);
 
-  const attackUnit = useCallback((gameId: string, playerId: string, attackerId: string, defenderId: string) => {
-    console.log("Sending AttackUnit:", { gameId, playerId, attackerId, defenderId });
-    send({ type: "AttackUnit", game_id: gameId, player_id: playerId, attacker_id: attackerId, defender_id: defenderId });
+  const abortGame = useCallback((gameId: string) => {
+    send({ type: "AbortGame", game_id: gameId });
   }, [send]);
 
-  const fortifyUnit = useCallback((gameId: string, playerId: string, unitId: string) => {
-    console.log("Sending FortifyUnit:", { gameId, playerId, unitId });
-    send({ type: "FortifyUnit", game_id: gameId, player_id: playerId, unit_id: unitId });
+  const setAway = useCallback((gameId: string, away: boolean) => {
+    send({ type: "SetAway", game_id: gameId, away });
   }, [send]);
 
-  const buyUnit = useCallback((gameId: string, playerId: string, cityId: string, unitType: string) => {
-    console.log("Sending BuyUnit:", { gameId, playerId, cityId, unitType });
-    send({ type: "BuyUnit", game_id: gameId, player_id: playerId, city_id: cityId, unit_type: unitType });
+  const buyUnit = useCallback((gameId: string, cityId: string, unitType: string) => {
+    console.log("Sending BuyUnit:", { gameId, cityId, unitType });
+    send({ type: "BuyUnit", game_id: gameId, city_id: cityId, unit_type: unitType });
   }, [send]);
 
   return {
@@ -382,6 +553,7 @@ export function useWebSocket() {
     lobbies,
     game,
     turnTimeRemaining,
+    presence,
     error,
     createLobby,
     joinLobby,
@@ -391,9 +563,15 @@ export function useWebSocket() {
     endTurn,
     rejoinGame,
     moveUnit,
+    moveUnitPath,
     attackUnit,
     fortifyUnit,
     buyUnit,
+    resign,
+    offerDraw,
+    acceptDraw,
+    abortGame,
+    setAway,
     setError,
     setCurrentLobby,
     setGame,
@@ -13,6 +13,7 @@ export interface Lobby {
   map_size: MapSize;
   max_players: number;
   status: string;
+  seed: number;
 }
 
 export interface Tile {
@@ -28,6 +29,7 @@ export interface GameMap {
 
 export interface GameSession {
   id: string;
+  seed: number;
   map: GameMap;
   players: Player[];
   current_turn: number;
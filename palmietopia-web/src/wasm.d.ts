@@ -2,4 +2,12 @@ declare module "../../pkg/palmietopia_core" {
   export default function init(path?: string): Promise<void>;
   export function get_welcome_message(): string;
   export function generate_tiny_map(): string;
+  export function generate_map(map_size: number, seed: number): string;
+  export function get_reachable_tiles(game_json: string, unit_id: string): string;
+  export function get_attackable_units(game_json: string, unit_id: string): string;
+  export function apply_command(game_json: string, command_json: string): string;
+  export function get_reachable_tiles_for_view(view_json: string, unit_id: string): string;
+  export function get_attackable_units_for_view(view_json: string, unit_id: string): string;
+  export function apply_command_to_view(view_json: string, command_json: string): string;
+  export function find_path_for_view(view_json: string, unit_id: string, to_q: number, to_r: number): string;
 }